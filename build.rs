@@ -0,0 +1,104 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Records the git commit, build time and the versions of the crypto
+//! dependencies, served by `GET /version`. With the `ffi` feature, also
+//! generates the C header of the bindings.
+
+use std::{
+    path::Path,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The dependencies whose versions are recorded, as named in Cargo.lock.
+const DEPENDENCIES: [(&str, &str); 3] = [
+    ("fastcrypto", "FASTCRYPTO"),
+    ("fastcrypto-zkp", "FASTCRYPTO_ZKP"),
+    ("sui-types", "SUI_TYPES"),
+];
+
+fn main() {
+    // Builds without a git checkout, e.g. in Docker, can pass the commit.
+    let commit = std::env::var("GIT_COMMIT").ok().or_else(|| {
+        Command::new("git")
+            .args(["rev-parse", "HEAD"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+            .map(|commit| commit.trim().to_string())
+    });
+    println!(
+        "cargo:rustc-env=ZKLOGIN_VERIFIER_GIT_COMMIT={}",
+        commit.as_deref().unwrap_or("unknown")
+    );
+
+    // Reproducible builds set the timestamp.
+    let timestamp = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs())
+        });
+    println!(
+        "cargo:rustc-env=ZKLOGIN_VERIFIER_BUILD_TIMESTAMP={}",
+        timestamp
+    );
+
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    let lock_path = Path::new(&manifest_dir).join("Cargo.lock");
+    let lock = std::fs::read_to_string(&lock_path).unwrap_or_default();
+    for (name, var) in DEPENDENCIES {
+        println!(
+            "cargo:rustc-env=ZKLOGIN_VERIFIER_{}_VERSION={}",
+            var,
+            locked_version(&lock, name).unwrap_or_else(|| "unknown".to_string())
+        );
+    }
+
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    println!("cargo:rerun-if-changed={}", lock_path.display());
+    println!("cargo:rerun-if-changed=.git/HEAD");
+
+    #[cfg(feature = "ffi")]
+    generate_header(Path::new(&manifest_dir));
+}
+
+/// Generates `include/zklogin_verifier.h` from the bindings in src/ffi.rs.
+#[cfg(feature = "ffi")]
+fn generate_header(manifest_dir: &Path) {
+    let config = cbindgen::Config::from_file(manifest_dir.join("cbindgen.toml"))
+        .expect("cbindgen.toml is invalid");
+    cbindgen::Builder::new()
+        .with_crate(manifest_dir)
+        .with_config(config)
+        .generate()
+        .expect("Cannot generate the C header")
+        .write_to_file(manifest_dir.join("include/zklogin_verifier.h"));
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+}
+
+/// Returns the version of the package in Cargo.lock, followed by the commit
+/// it was built from for git dependencies, e.g. "0.1.2+ea66012b".
+fn locked_version(lock: &str, name: &str) -> Option<String> {
+    let package = lock
+        .split("[[package]]")
+        .find(|package| package.contains(&format!("name = \"{}\"\n", name)))?;
+    let field = |key: &str| {
+        package
+            .lines()
+            .find_map(|line| line.strip_prefix(&format!("{} = \"", key)))
+            .map(|value| value.trim_end_matches('"').to_string())
+    };
+    let version = field("version")?;
+    match field("source").and_then(|source| source.split_once('#').map(|(_, rev)| rev.to_string()))
+    {
+        Some(rev) => Some(format!("{}+{}", version, &rev[..rev.len().min(8)])),
+        None => Some(version),
+    }
+}
@@ -0,0 +1,67 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Feeds arbitrary requests through the decoding done by `/verify`: the text
+//! encoding of the signature and bytes, `GenericSignature::from_bytes` and
+//! the BCS or JSON decoding of TransactionData. No JWK is known, so inputs
+//! that decode are rejected before the Groth16 proof is verified.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use fastcrypto_zkp::bn254::zk_login_api::ZkLoginEnv;
+use im::hashmap::HashMap as ImHashMap;
+use libfuzzer_sys::fuzz_target;
+use shared_crypto::intent::IntentScope;
+use zklogin_verifier::{
+    parse_zklogin_signature, verify_request, verify_request_ephemeral, BinaryEncoding,
+    PayloadFormat, VerifyPolicy, VerifyRequest,
+};
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    signature: String,
+    bytes: String,
+    personal_message: bool,
+    json: bool,
+    encoding: u8,
+}
+
+fuzz_target!(|input: Input| {
+    let _ = parse_zklogin_signature(&input.signature);
+
+    let request = VerifyRequest {
+        signature: input.signature,
+        bytes: input.bytes,
+        intent_scope: if input.personal_message {
+            IntentScope::PersonalMessage
+        } else {
+            IntentScope::TransactionData
+        },
+        author: None,
+        network: None,
+        curr_epoch: Some(0),
+        sponsor: None,
+        format: Some(if input.json {
+            PayloadFormat::Json
+        } else {
+            PayloadFormat::Bcs
+        }),
+        encoding: Some(match input.encoding % 3 {
+            0 => BinaryEncoding::Base64,
+            1 => BinaryEncoding::Base64Url,
+            _ => BinaryEncoding::Hex,
+        }),
+        policy: None,
+        jwk: None,
+        historical_epoch: None,
+    };
+    let _ = verify_request_ephemeral(&request);
+    let _ = verify_request(
+        &request,
+        ImHashMap::new(),
+        ZkLoginEnv::Test,
+        0,
+        VerifyPolicy::default(),
+    );
+});
@@ -0,0 +1,14 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Feeds arbitrary bytes through the BCS decoding of TransactionData and the
+//! required signers of `/verify_transaction`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use zklogin_verifier::{verify_transaction_signatures, VerifyOptions};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = verify_transaction_signatures(data, &[], VerifyOptions::default());
+});
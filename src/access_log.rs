@@ -0,0 +1,142 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! An access log of every request, written as JSON lines to a file or stdout
+//! apart from the application logs, so that it is kept whatever the log level
+//! or sampling.
+
+use crate::{config::AccessLogConfig, request_id::current_request_id, AppState};
+use axum::{
+    extract::{ConnectInfo, State},
+    http::Request,
+    middleware::Next,
+    response::Response,
+};
+use prometheus::IntCounter;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::OpenOptions,
+    io::{BufWriter, Write},
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::mpsc::{channel, error::TrySendError, Sender};
+use tracing::warn;
+
+/// A request in the access log.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AccessLogEntry {
+    /// When the request arrived, in Unix milliseconds.
+    pub timestamp_ms: u64,
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    /// Time until the response headers were sent, in milliseconds.
+    pub latency_ms: f64,
+    /// The IP of the client, taken from `rate_limit.client_ip_header` if
+    /// set and otherwise the peer address.
+    pub client_ip: Option<IpAddr>,
+    pub request_id: Option<String>,
+}
+
+/// Writes access log entries on a thread of its own, so that requests never
+/// wait for the disk. Up to a capacity of entries wait to be written, and
+/// entries beyond it are dropped and counted.
+#[derive(Clone, Debug)]
+pub struct AccessLog {
+    entries: Sender<AccessLogEntry>,
+    dropped: IntCounter,
+}
+
+impl AccessLog {
+    /// Opens the access log, appending to its file if it exists. Dropped
+    /// entries are counted in `dropped`.
+    pub fn open(config: &AccessLogConfig, dropped: IntCounter) -> Result<Self, String> {
+        let writer: Box<dyn Write + Send> = match &config.path {
+            Some(path) => Box::new(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| format!("Cannot open access log {}: {}", path.display(), e))?,
+            ),
+            None => Box::new(std::io::stdout()),
+        };
+        Ok(Self::with_writer(writer, config.capacity, dropped))
+    }
+
+    /// Creates an access log writing to `writer`, with up to `capacity`
+    /// entries waiting, which must be positive.
+    pub fn with_writer(
+        writer: Box<dyn Write + Send>,
+        capacity: usize,
+        dropped: IntCounter,
+    ) -> Self {
+        let (entries, mut receiver) = channel::<AccessLogEntry>(capacity);
+        std::thread::spawn(move || {
+            let mut writer = BufWriter::new(writer);
+            while let Some(entry) = receiver.blocking_recv() {
+                write_entry(&mut writer, &entry);
+                // Writes the entries that arrived meanwhile before flushing.
+                while let Ok(entry) = receiver.try_recv() {
+                    write_entry(&mut writer, &entry);
+                }
+                if let Err(e) = writer.flush() {
+                    warn!("Cannot write access log: {}", e);
+                }
+            }
+        });
+        Self { entries, dropped }
+    }
+
+    /// Adds the entry to the log, or drops it if the writer is behind by the
+    /// capacity of the log.
+    pub fn log(&self, entry: AccessLogEntry) {
+        match self.entries.try_send(entry) {
+            Err(TrySendError::Full(_)) => self.dropped.inc(),
+            // Only once the writer thread is gone.
+            Err(TrySendError::Closed(_)) | Ok(()) => {}
+        }
+    }
+}
+
+fn write_entry(writer: &mut impl Write, entry: &AccessLogEntry) {
+    let line = serde_json::to_string(entry).expect("entries are serializable");
+    if let Err(e) = writeln!(writer, "{}", line) {
+        warn!("Cannot write access log: {}", e);
+    }
+}
+
+/// Middleware that adds every request to the access log.
+pub async fn access_log<B>(
+    State(state): State<Arc<AppState>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let Some(log) = &state.access_log else {
+        return next.run(request).await;
+    };
+    let timestamp_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    let start = Instant::now();
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+    let client_ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(peer)| state.rate_limiter.client_ip(request.headers(), *peer));
+    let response = next.run(request).await;
+    log.log(AccessLogEntry {
+        timestamp_ms,
+        method,
+        path,
+        status: response.status().as_u16(),
+        latency_ms: start.elapsed().as_secs_f64() * 1000.0,
+        client_ip,
+        request_id: current_request_id(),
+    });
+    response
+}
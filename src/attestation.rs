@@ -0,0 +1,279 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Attestation of the verifier running in an AWS Nitro Enclave, so that
+//! relying parties can check which code produced a verdict. On startup the
+//! server generates an Ed25519 key that never leaves the enclave, and signs
+//! the response of each verify request with it, bound to the request by
+//! its digest. `GET /attestation` returns a
+//! document signed by the Nitro hypervisor, binding the measurements of the
+//! enclave image to the public key of the server.
+
+use crate::{ndjson::NDJSON, server::error_body, AppState, ErrorCode};
+use axum::{
+    body::{boxed, Body, Bytes, Full, HttpBody},
+    extract::{Query, State},
+    http::{header::CONTENT_TYPE, HeaderName, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use fastcrypto::{
+    ed25519::Ed25519KeyPair,
+    encoding::{Base64, Encoding, Hex},
+    hash::{HashFunction, Sha256},
+    traits::{KeyPair, Signer},
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// The header carrying the Base64 encoded Ed25519 signature of the server
+/// over the SHA-256 digest of the body of a verify request followed by the
+/// body of its response, see [signed_message].
+pub const X_VERDICT_SIGNATURE: HeaderName = HeaderName::from_static("x-verdict-signature");
+
+/// The maximum length of a nonce, as accepted by the Nitro Secure Module.
+const MAX_NONCE_BYTES: usize = 512;
+
+/// The signing key of the server.
+pub struct Attestation {
+    key: Ed25519KeyPair,
+}
+
+impl std::fmt::Debug for Attestation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Attestation")
+            .field("public_key", &self.public_key())
+            .finish()
+    }
+}
+
+impl Attestation {
+    /// Generates a new signing key.
+    pub fn generate() -> Self {
+        Self {
+            key: Ed25519KeyPair::generate(&mut rand::thread_rng()),
+        }
+    }
+
+    /// Returns the Base64 encoded public key of the server.
+    pub fn public_key(&self) -> String {
+        Base64::encode(self.key.public().as_ref())
+    }
+
+    /// Returns the Base64 encoded signature over the bytes.
+    pub fn sign(&self, bytes: &[u8]) -> String {
+        Base64::encode(self.key.sign(bytes).as_ref())
+    }
+
+    /// Requests an attestation document from the Nitro Secure Module over
+    /// the public key, with the version of the verifier as user data.
+    #[cfg(feature = "nitro")]
+    fn document(&self, nonce: Option<Vec<u8>>) -> Result<Vec<u8>, String> {
+        use aws_nitro_enclaves_nsm_api::{
+            api::{Request, Response},
+            driver::{nsm_exit, nsm_init, nsm_process_request},
+        };
+        use serde_bytes::ByteBuf;
+
+        let fd = nsm_init();
+        if fd < 0 {
+            return Err("Cannot open the Nitro Secure Module".to_string());
+        }
+        let response = nsm_process_request(
+            fd,
+            Request::Attestation {
+                user_data: Some(ByteBuf::from(
+                    format!("zklogin-verifier/{}", env!("CARGO_PKG_VERSION")).into_bytes(),
+                )),
+                nonce: nonce.map(ByteBuf::from),
+                public_key: Some(ByteBuf::from(self.key.public().as_ref().to_vec())),
+            },
+        );
+        nsm_exit(fd);
+        match response {
+            Response::Attestation { document } => Ok(document),
+            Response::Error(e) => Err(format!("Nitro Secure Module error: {:?}", e)),
+            _ => Err("Unexpected response of the Nitro Secure Module".to_string()),
+        }
+    }
+
+    #[cfg(not(feature = "nitro"))]
+    fn document(&self, _nonce: Option<Vec<u8>>) -> Result<Vec<u8>, String> {
+        Err("Attestation needs a build with the nitro feature".to_string())
+    }
+}
+
+/// Query of `GET /attestation`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AttestationQuery {
+    /// A hex encoded nonce of up to 512 bytes, included in the document to
+    /// prove that it is fresh.
+    pub nonce: Option<String>,
+}
+
+/// Response of `GET /attestation`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AttestationResponse {
+    /// The Base64 encoded Ed25519 public key verify responses are signed
+    /// with.
+    pub public_key: String,
+    /// The Base64 encoded attestation document, a COSE_Sign1 structure
+    /// signed by the Nitro hypervisor holding the PCRs of the enclave, the
+    /// public key, the nonce and the version of the verifier.
+    pub document: String,
+}
+
+/// Returns an attestation document over the public key of the server.
+pub async fn get_attestation(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AttestationQuery>,
+) -> Response {
+    let Some(attestation) = state.attestation.clone() else {
+        return (
+            StatusCode::NOT_FOUND,
+            error_body("Attestation is not enabled", ErrorCode::AttestationFailed),
+        )
+            .into_response();
+    };
+    let nonce = match query.nonce.as_deref().map(Hex::decode).transpose() {
+        Ok(Some(nonce)) if nonce.len() > MAX_NONCE_BYTES => {
+            return (
+                StatusCode::BAD_REQUEST,
+                error_body(
+                    format!("nonce is longer than {} bytes", MAX_NONCE_BYTES),
+                    ErrorCode::InvalidRequest,
+                ),
+            )
+                .into_response()
+        }
+        Ok(nonce) => nonce,
+        Err(_) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                error_body("nonce is not valid Hex", ErrorCode::InvalidRequest),
+            )
+                .into_response()
+        }
+    };
+    // The module is called with a blocking ioctl.
+    let result = tokio::task::spawn_blocking({
+        let attestation = attestation.clone();
+        move || attestation.document(nonce)
+    })
+    .await
+    .expect("attestation panicked");
+    match result {
+        Ok(document) => Json(AttestationResponse {
+            public_key: attestation.public_key(),
+            document: Base64::encode(document),
+        })
+        .into_response(),
+        Err(e) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            error_body(e, ErrorCode::AttestationFailed),
+        )
+            .into_response(),
+    }
+}
+
+/// Returns the message the verdict of a request is signed over: the SHA-256
+/// digest of the request body, so that a verdict cannot be passed off as
+/// the one of another request, followed by the response body.
+pub fn signed_message(request: &[u8], response: &[u8]) -> Vec<u8> {
+    let mut message = Sha256::digest(request).digest.to_vec();
+    message.extend_from_slice(response);
+    message
+}
+
+/// Middleware that signs JSON responses with the key of the server, in the
+/// `x-verdict-signature` header, if attestation is enabled. Streamed
+/// requests are passed on as they are, and their results not signed.
+pub async fn sign_response(
+    State(state): State<Arc<AppState>>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let Some(attestation) = &state.attestation else {
+        return next.run(request).await;
+    };
+    let is_streamed = request
+        .headers()
+        .get(CONTENT_TYPE)
+        .is_some_and(|content_type| content_type == NDJSON);
+    if is_streamed {
+        return next.run(request).await;
+    }
+    // Bounds what is held in memory, while the extractors of the handlers
+    // still enforce the limit of their route.
+    let max_body_bytes = {
+        let config = state.config.load();
+        config.max_body_bytes.max(config.max_batch_body_bytes)
+    };
+    let (parts, body) = request.into_parts();
+    let request_body = match read_body(body, max_body_bytes).await {
+        Ok(bytes) => bytes,
+        Err(response) => return response,
+    };
+    let response = next
+        .run(Request::from_parts(parts, Body::from(request_body.clone())))
+        .await;
+    let is_json = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .is_some_and(|content_type| content_type == "application/json");
+    if !is_json {
+        return response;
+    }
+    let (mut parts, mut body) = response.into_parts();
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        match chunk {
+            Ok(chunk) => bytes.extend_from_slice(&chunk),
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    error_body(
+                        format!("Cannot read the response: {}", e),
+                        ErrorCode::AttestationFailed,
+                    ),
+                )
+                    .into_response()
+            }
+        }
+    }
+    let signature = attestation.sign(&signed_message(&request_body, &bytes));
+    let signature = HeaderValue::from_str(&signature).expect("Base64 is ASCII");
+    parts.headers.insert(X_VERDICT_SIGNATURE, signature);
+    Response::from_parts(parts, boxed(Full::from(bytes)))
+}
+
+/// Reads the request body, answering with a 413 if it is longer than `max`
+/// bytes.
+async fn read_body(mut body: Body, max: usize) -> Result<Bytes, Response> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                error_body(
+                    format!("Cannot read the request: {}", e),
+                    ErrorCode::InvalidRequest,
+                ),
+            )
+                .into_response()
+        })?;
+        if bytes.len() + chunk.len() > max {
+            return Err((
+                StatusCode::PAYLOAD_TOO_LARGE,
+                error_body(
+                    format!("Request body exceeds {} bytes", max),
+                    ErrorCode::PayloadTooLarge,
+                ),
+            )
+                .into_response());
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes.into())
+}
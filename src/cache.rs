@@ -0,0 +1,68 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! A bounded LRU cache of successful verifications, so that retried and
+//! duplicate requests do not verify the same Groth16 proof again.
+
+use crate::{VerifyPolicy, VerifyRequest, VerifyResponse};
+use fastcrypto::hash::{HashFunction, Sha256};
+use fastcrypto_zkp::bn254::zk_login_api::ZkLoginEnv;
+use lru::LruCache;
+use parking_lot::Mutex;
+use std::{num::NonZeroUsize, sync::Arc};
+use sui_types::committee::EpochId;
+
+/// The digest of everything a verification depends on besides the JWKs.
+pub type CacheKey = [u8; 32];
+
+/// The verification cache shared by all requests. Disabled by default.
+#[derive(Clone, Debug, Default)]
+pub struct VerifyCache(Option<Arc<Mutex<LruCache<CacheKey, VerifyResponse>>>>);
+
+impl VerifyCache {
+    /// Creates a cache holding up to `capacity` results, or a disabled cache
+    /// if `capacity` is 0.
+    pub fn new(capacity: usize) -> Self {
+        Self(
+            NonZeroUsize::new(capacity)
+                .map(|capacity| Arc::new(Mutex::new(LruCache::new(capacity)))),
+        )
+    }
+
+    /// Returns the key of the request, verified at `curr_epoch` under the
+    /// `policy` of the server, accepting signatures of any scheme if
+    /// `any_scheme` is set. The key covers the whole request rather than
+    /// only the signature and bytes, since e.g. the author, sponsor flag and
+    /// policy of the request change the result too.
+    pub fn key(
+        payload: &VerifyRequest,
+        env: ZkLoginEnv,
+        curr_epoch: EpochId,
+        policy: VerifyPolicy,
+        any_scheme: bool,
+    ) -> CacheKey {
+        let encoded = serde_json::to_vec(&(payload, env, curr_epoch, policy, any_scheme))
+            .expect("request is serializable");
+        Sha256::digest(encoded).digest
+    }
+
+    /// Returns the cached result for the key, if any.
+    pub fn get(&self, key: &CacheKey) -> Option<VerifyResponse> {
+        self.0.as_ref()?.lock().get(key).cloned()
+    }
+
+    /// Caches a successful verification.
+    pub fn insert(&self, key: CacheKey, response: VerifyResponse) {
+        if let Some(cache) = &self.0 {
+            cache.lock().put(key, response);
+        }
+    }
+
+    /// Drops all cached results, e.g. when JWKs change, since a pruned JWK
+    /// must no longer verify.
+    pub fn clear(&self) {
+        if let Some(cache) = &self.0 {
+            cache.lock().clear();
+        }
+    }
+}
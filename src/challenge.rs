@@ -0,0 +1,272 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Canonical challenges proving ownership of a zkLogin address, issued by
+//! `POST /challenge` and verified by `POST /verify_challenge`, so that
+//! exchanges share one message format instead of each inventing their own.
+//!
+//! Challenges carry a tag computed with a secret of the verifier, so that
+//! their fields, the expiry included, are the ones it issued. The verifier
+//! keeps no other state: integrators check that the nonce of a verified
+//! challenge is one they requested and has not been used before.
+
+use crate::{
+    server::{request_jwk_ids, unix_secs, verify_payload},
+    AppState, ChallengeRequest, ChallengeResponse, VerifyChallengeRequest, VerifyChallengeResponse,
+    VerifyError, VerifyRequest,
+};
+use axum::{extract::State, Json};
+use fastcrypto::{
+    encoding::{Base64, Encoding, Hex},
+    hmac::{hmac_sha3_256, HmacKey},
+    traits::ToFromBytes,
+};
+use rand::Rng;
+use shared_crypto::intent::IntentScope;
+use std::{
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use subtle::ConstantTimeEq;
+use sui_types::base_types::SuiAddress;
+
+/// The first line of every challenge.
+const HEADER: &str = "Sui zkLogin ownership challenge";
+
+/// The maximum length in bytes of the domain of a challenge.
+const MAX_DOMAIN_LEN: usize = 253;
+
+/// The fields of an ownership challenge, which is signed as a personal
+/// message in the form of [Challenge::to_message].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Challenge {
+    /// The domain of the integrator asking for the proof, shown to the user.
+    pub domain: Option<String>,
+    /// The address whose ownership is proven.
+    pub address: SuiAddress,
+    /// 16 random bytes, lowercase hex encoded.
+    pub nonce: String,
+    /// When the challenge expires, in Unix seconds.
+    pub expires_at: u64,
+    /// The HMAC of the other fields under the key of the verifier, lowercase
+    /// hex encoded.
+    pub tag: String,
+}
+
+impl Challenge {
+    /// Creates a challenge for the address with a random nonce, expiring
+    /// `ttl` after `now`, tagged with `key`.
+    pub fn new(
+        key: &ChallengeKey,
+        address: SuiAddress,
+        domain: Option<String>,
+        ttl: Duration,
+        now: SystemTime,
+    ) -> Result<Self, VerifyError> {
+        if let Some(domain) = &domain {
+            check_domain(domain)?;
+        }
+        let mut challenge = Self {
+            domain,
+            address,
+            nonce: Hex::encode(rand::thread_rng().gen::<[u8; 16]>()),
+            expires_at: unix_secs(now + ttl),
+            tag: String::new(),
+        };
+        challenge.tag = key.tag(&challenge);
+        Ok(challenge)
+    }
+
+    /// Returns the message to sign, one field per line.
+    pub fn to_message(&self) -> String {
+        format!("{}\nTag: {}", self.tagged_message(), self.tag)
+    }
+
+    /// Returns the lines of the message that the tag is computed over.
+    fn tagged_message(&self) -> String {
+        let mut lines = vec![HEADER.to_string()];
+        if let Some(domain) = &self.domain {
+            lines.push(format!("Domain: {}", domain));
+        }
+        lines.push(format!("Address: {}", self.address));
+        lines.push(format!("Nonce: {}", self.nonce));
+        lines.push(format!("Expires At: {}", self.expires_at));
+        lines.join("\n")
+    }
+
+    /// Parses a message in the form of [Challenge::to_message]. Any other
+    /// form of the same fields, e.g. with another address encoding or
+    /// trailing whitespace, is rejected.
+    pub fn parse(message: &str) -> Result<Self, VerifyError> {
+        let lines: Vec<&str> = message.split('\n').collect();
+        let (domain, address, nonce, expires_at, tag) = match lines.as_slice() {
+            [HEADER, domain, address, nonce, expires_at, tag] => (
+                Some(field(domain, "Domain")?),
+                address,
+                nonce,
+                expires_at,
+                tag,
+            ),
+            [HEADER, address, nonce, expires_at, tag] => (None, address, nonce, expires_at, tag),
+            _ => return Err(invalid("Not an ownership challenge")),
+        };
+        if let Some(domain) = domain {
+            check_domain(domain)?;
+        }
+        let nonce = field(nonce, "Nonce")?;
+        if !is_lower_hex(nonce, 16) {
+            return Err(invalid("Nonce is not 16 hex encoded bytes"));
+        }
+        let tag = field(tag, "Tag")?;
+        if !is_lower_hex(tag, 32) {
+            return Err(invalid("Tag is not 32 hex encoded bytes"));
+        }
+        let challenge = Self {
+            domain: domain.map(str::to_string),
+            address: SuiAddress::from_str(field(address, "Address")?)
+                .map_err(|_| invalid("Invalid address"))?,
+            nonce: nonce.to_string(),
+            expires_at: field(expires_at, "Expires At")?
+                .parse()
+                .map_err(|_| invalid("Invalid expiry"))?,
+            tag: tag.to_string(),
+        };
+        if challenge.to_message() != message {
+            return Err(invalid("Challenge is not in canonical form"));
+        }
+        Ok(challenge)
+    }
+}
+
+/// The secret key challenges are tagged with, so that the verifier only
+/// accepts challenges it issued, unchanged.
+#[derive(Clone)]
+pub struct ChallengeKey(Arc<HmacKey>);
+
+impl ChallengeKey {
+    /// Creates the key from `challenge_secret`.
+    pub fn new(secret: &[u8]) -> Self {
+        Self(Arc::new(
+            HmacKey::from_bytes(secret).expect("keys of any length are accepted"),
+        ))
+    }
+
+    /// Returns the tag of the challenge.
+    pub fn tag(&self, challenge: &Challenge) -> String {
+        Hex::encode(hmac_sha3_256(&self.0, challenge.tagged_message().as_bytes()).digest)
+    }
+
+    /// Checks that the challenge was tagged with this key.
+    pub fn check(&self, challenge: &Challenge) -> Result<(), VerifyError> {
+        let tag = self.tag(challenge);
+        if !bool::from(tag.as_bytes().ct_eq(challenge.tag.as_bytes())) {
+            return Err(invalid("Challenge was not issued by this verifier"));
+        }
+        Ok(())
+    }
+}
+
+/// A random key of the process, for verifiers without `challenge_secret`.
+impl Default for ChallengeKey {
+    fn default() -> Self {
+        Self::new(&rand::thread_rng().gen::<[u8; 32]>())
+    }
+}
+
+impl std::fmt::Debug for ChallengeKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ChallengeKey").finish()
+    }
+}
+
+/// Whether `value` is the lowercase hex encoding of `len` bytes.
+fn is_lower_hex(value: &str, len: usize) -> bool {
+    value.len() == 2 * len
+        && value
+            .bytes()
+            .all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Returns the value of a line of the form "<name>: <value>".
+fn field<'a>(line: &'a str, name: &str) -> Result<&'a str, VerifyError> {
+    line.strip_prefix(name)
+        .and_then(|rest| rest.strip_prefix(": "))
+        .ok_or_else(|| invalid(&format!("Missing {}", name)))
+}
+
+fn check_domain(domain: &str) -> Result<(), VerifyError> {
+    if domain.is_empty()
+        || domain.len() > MAX_DOMAIN_LEN
+        || domain.chars().any(|c| c.is_control() || c.is_whitespace())
+    {
+        return Err(invalid("Domain must be 1 to 253 bytes without whitespace"));
+    }
+    Ok(())
+}
+
+fn invalid(error: &str) -> VerifyError {
+    VerifyError::InvalidChallenge(error.to_string())
+}
+
+/// Issues a challenge for the address, expiring after `challenge_ttl_secs`.
+pub async fn challenge(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<ChallengeRequest>,
+) -> Result<Json<ChallengeResponse>, VerifyError> {
+    let ttl = Duration::from_secs(state.config.load().challenge_ttl_secs);
+    let challenge = Challenge::new(
+        &state.challenge_key,
+        payload.address,
+        payload.domain,
+        ttl,
+        SystemTime::now(),
+    )?;
+    Ok(Json(ChallengeResponse {
+        challenge: challenge.to_message(),
+        nonce: challenge.nonce,
+        expires_at: challenge.expires_at,
+    }))
+}
+
+/// Verifies a zkLogin personal message signature over an unexpired
+/// challenge issued by the verifier, by the address of the challenge, at the
+/// current epoch of the network, and returns the proven address.
+pub async fn verify_challenge(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<VerifyChallengeRequest>,
+) -> Result<Json<VerifyChallengeResponse>, VerifyError> {
+    let challenge = Challenge::parse(&payload.challenge)?;
+    state.challenge_key.check(&challenge)?;
+    if unix_secs(SystemTime::now()) >= challenge.expires_at {
+        return Err(VerifyError::ChallengeExpired {
+            expires_at: challenge.expires_at,
+        });
+    }
+    let request = VerifyRequest {
+        signature: payload.signature,
+        bytes: Base64::encode(payload.challenge.as_bytes()),
+        intent_scope: IntentScope::PersonalMessage,
+        author: Some(challenge.address),
+        network: payload.network,
+        // Never chosen by the prover, so that a key past its max epoch
+        // proves nothing.
+        curr_epoch: None,
+        sponsor: None,
+        format: None,
+        encoding: None,
+        policy: None,
+        jwk: None,
+        historical_epoch: None,
+        intent: None,
+    };
+    let jwk_ids = request_jwk_ids(&request);
+    let result = verify_payload(&state, &request, &jwk_ids).await?;
+    Ok(Json(VerifyChallengeResponse {
+        address: challenge.address,
+        domain: challenge.domain,
+        nonce: challenge.nonce,
+        expires_at: challenge.expires_at,
+        result,
+    }))
+}
@@ -0,0 +1,104 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! A global limit of the verification requests handled at a time, so that a
+//! spike of requests queues or is shed instead of slowing all of them down.
+
+use crate::{config::ConcurrencyLimitConfig, server::error_body, AppState, ErrorCode};
+use axum::{
+    extract::State,
+    http::{header::RETRY_AFTER, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Seconds a shed request is told to wait before retrying.
+const SHED_RETRY_AFTER_SECS: u64 = 1;
+
+/// Limits the requests handled at a time to `max_in_flight`, queueing up to
+/// `max_queued` more. Unlimited if not configured.
+#[derive(Clone, Debug, Default)]
+pub struct ConcurrencyLimiter {
+    permits: Option<Arc<Semaphore>>,
+    queued: Arc<AtomicUsize>,
+    max_queued: usize,
+}
+
+/// The queue of a [ConcurrencyLimiter] is full.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Overloaded;
+
+impl ConcurrencyLimiter {
+    /// Creates a limiter with the config, unlimited if not set.
+    pub fn new(config: Option<&ConcurrencyLimitConfig>) -> Self {
+        Self {
+            permits: config.map(|config| Arc::new(Semaphore::new(config.max_in_flight))),
+            queued: Default::default(),
+            max_queued: config.map_or(0, |config| config.max_queued),
+        }
+    }
+
+    /// Waits for a free slot and returns it, or fails right away if the
+    /// queue is full. No slot is needed if unlimited.
+    pub async fn acquire(&self) -> Result<Option<OwnedSemaphorePermit>, Overloaded> {
+        let Some(permits) = &self.permits else {
+            return Ok(None);
+        };
+        if let Ok(permit) = permits.clone().try_acquire_owned() {
+            return Ok(Some(permit));
+        }
+        if self.queued.fetch_add(1, Ordering::SeqCst) >= self.max_queued {
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+            return Err(Overloaded);
+        }
+        // Leaves the queue even if the request is dropped while waiting,
+        // e.g. on the request timeout.
+        let _queued = Queued(&self.queued);
+        let permit = permits.clone().acquire_owned().await.expect("never closed");
+        Ok(Some(permit))
+    }
+
+    /// The number of requests waiting for a slot.
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::SeqCst)
+    }
+}
+
+/// A request waiting in the queue of a [ConcurrencyLimiter].
+struct Queued<'a>(&'a AtomicUsize);
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Middleware that handles requests once the concurrency limit allows, and
+/// sheds them with 503 and a Retry-After header if its queue is full.
+pub async fn concurrency_limit<B>(
+    State(state): State<Arc<AppState>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let _permit = match state.concurrency_limiter.acquire().await {
+        Ok(permit) => permit,
+        Err(Overloaded) => {
+            state.metrics.requests_shed.inc();
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                [(RETRY_AFTER, SHED_RETRY_AFTER_SECS.to_string())],
+                error_body(
+                    "Too many requests in progress, retry later",
+                    ErrorCode::Overloaded,
+                ),
+            )
+                .into_response();
+        }
+    };
+    next.run(request).await
+}
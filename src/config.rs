@@ -0,0 +1,776 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Server configuration, loaded from an optional TOML or YAML file and
+//! overridden by CLI flags and env vars in `main.rs`.
+
+use crate::{default_providers, request_id::X_REQUEST_ID, SuiEnv, VerifyPolicy};
+use axum::http::{HeaderName, HeaderValue, Method};
+use axum_server::{AddrIncomingConfig, HttpConfig};
+use fastcrypto_zkp::bn254::zk_login::OIDCProvider;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+use subtle::ConstantTimeEq;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+/// Configuration of the verifier HTTP server.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    /// The socket address the HTTP server listens on.
+    pub bind: SocketAddr,
+    /// Fullnode URLs overriding the default for each network, tried in
+    /// order until one responds.
+    pub fullnode_urls: HashMap<SuiEnv, Vec<String>>,
+    /// The network of requests that don't set one, e.g. Testnet for a
+    /// testnet-only deployment.
+    pub default_network: SuiEnv,
+    /// The OIDC providers to fetch JWKs from, by name, e.g. "Google".
+    pub providers: Vec<String>,
+    /// Seconds between refreshes of the JWKs of a provider.
+    pub jwk_refresh_interval_secs: u64,
+    /// Refresh intervals in seconds of individual providers by name,
+    /// overriding `jwk_refresh_interval_secs`.
+    pub provider_refresh_interval_secs: HashMap<String, u64>,
+    /// JWK endpoints of individual providers by name, overriding the
+    /// endpoint of the provider, e.g. to fetch from a mirror.
+    pub jwk_endpoints: HashMap<String, String>,
+    /// Seconds a JWK is kept after its provider stops returning it.
+    pub jwk_prune_grace_period_secs: u64,
+    /// The tracing filter directive, e.g. "info" or "zklogin_verifier=debug".
+    pub log_level: String,
+    /// Whether to emit logs as JSON instead of plain text.
+    pub log_json: bool,
+    /// Whether to log the full signature and bytes of verify requests, for
+    /// debugging. Otherwise only their hashes and lengths and the iss and kid
+    /// are logged, since signatures and transactions identify users.
+    pub log_payloads: bool,
+    /// Log the info logs of 1 in this many requests, e.g. 100 for a busy
+    /// deployment. Warnings, errors and failed verifications are always
+    /// logged.
+    pub log_sample_every: u64,
+    /// Serve HTTPS with the given certificate and key instead of plain HTTP.
+    pub tls: Option<TlsConfig>,
+    /// Bearer token required by the admin endpoints. They are disabled if
+    /// not set.
+    pub admin_token: Option<String>,
+    /// Seconds to let in-flight requests complete after SIGTERM or SIGINT
+    /// before closing their connections.
+    pub shutdown_timeout_secs: u64,
+    /// Maximum size in bytes of a request body. Larger requests get a 413.
+    pub max_body_bytes: usize,
+    /// Seconds a request may take before it is answered with a 503.
+    pub request_timeout_secs: u64,
+    /// Seconds to wait for a fullnode before failing over to the next one.
+    pub fullnode_timeout_secs: u64,
+    /// The networks whose current epoch is tracked in the background, so
+    /// that verify requests on them don't wait for a fullnode. The epoch of
+    /// other networks is fetched by each request. Defaults to the configured
+    /// networks, see [Config::tracked_networks], and an empty list tracks
+    /// none.
+    pub track_epoch_networks: Option<Vec<SuiEnv>>,
+    /// The longest time in seconds between two fetches of the epoch of a
+    /// tracked network. It is also fetched when the next epoch is due.
+    pub epoch_poll_interval_secs: u64,
+    /// The networks whose on-chain JWKs are compared against the JWKs
+    /// fetched from the providers, reported at `GET /jwks/drift`.
+    pub jwk_drift_networks: Vec<SuiEnv>,
+    /// Seconds between two JWK drift checks of a network.
+    pub jwk_drift_interval_secs: u64,
+    /// Age in seconds of the JWKs of a provider after which verify responses
+    /// using them carry a `stale_data` warning. Never warned about if not
+    /// set.
+    pub stale_jwks_after_secs: Option<u64>,
+    /// Age in seconds of a tracked current epoch after which verify
+    /// responses using it carry a `stale_data` warning. Never warned about
+    /// if not set.
+    pub stale_epoch_after_secs: Option<u64>,
+    /// Seconds to wait on startup for the JWKs of all providers before
+    /// accepting connections. 0 accepts connections right away.
+    pub startup_jwk_timeout_secs: u64,
+    /// The maximum number of requests in a batch verify.
+    pub max_batch_size: usize,
+    /// The maximum size of a batch verify request body in bytes, replacing
+    /// `max_body_bytes` for batches.
+    pub max_batch_body_bytes: usize,
+    /// The number of proofs verified in parallel. Defaults to the number of
+    /// CPUs less `verify_pool_reserved_cpus`, and at least 1.
+    pub verify_pool_size: Option<usize>,
+    /// The number of CPUs left to serving requests when sizing the
+    /// verification pool by the number of CPUs.
+    pub verify_pool_reserved_cpus: usize,
+    /// The maximum number of requests of a WebSocket connection or an NDJSON
+    /// stream verified at a time. Further requests are not read until one
+    /// completes.
+    pub ws_max_in_flight: usize,
+
+    /// Whether to accept requests carrying the JWK to verify against, e.g. to
+    /// verify fixtures signed with test keys. Never enable this where the
+    /// result is trusted, since anyone can sign with their own JWK.
+    pub allow_inline_jwks: bool,
+    /// Whether to serve `/verify_debug`, which returns the decoded public
+    /// inputs of zkLogin signatures and the JWKs selected for them next to
+    /// the verdict, e.g. to find out why deployments disagree on a signature.
+    pub debug_endpoint: bool,
+    /// Seconds an ownership challenge issued by `/challenge` stays valid.
+    pub challenge_ttl_secs: u64,
+    /// The secret ownership challenges are tagged with, at least 32 bytes.
+    /// Instances behind a load balancer must share it to accept the
+    /// challenges of each other. A random secret of the process if not set.
+    pub challenge_secret: Option<String>,
+    /// The iss values of the providers whose signatures are accepted. Signatures
+    /// from other providers are rejected even if they would verify. Any iss
+    /// is accepted if not set.
+    pub allowed_issuers: Option<Vec<String>>,
+    /// The verification policy, which requests can only tighten.
+    pub verify_policy: VerifyPolicy,
+    /// The number of epochs a fetched current epoch may be off by around an
+    /// epoch change. A signature that only fails because it expired, or
+    /// because its max epoch is too far ahead, is verified again at the
+    /// nearest epoch within this many epochs of the fetched one. 0 disables
+    /// retrying.
+    pub epoch_tolerance: u64,
+    /// The number of successful verifications cached, so that identical
+    /// requests are answered without verifying again. 0 disables the cache.
+    pub verify_cache_size: usize,
+
+    /// Rate limits of the verification endpoints.
+    pub rate_limit: RateLimitConfig,
+    /// Limit of the requests to the verification endpoints handled at a
+    /// time. Unlimited if not set.
+    pub concurrency_limit: Option<ConcurrencyLimitConfig>,
+
+    /// CORS settings, so that browsers can call the verifier directly.
+    /// CORS headers are not sent if not set.
+    pub cors: Option<CorsConfig>,
+    /// HTTP/2 and connection options.
+    pub http: HttpServerConfig,
+    /// The applications calling the verifier, each with its own API key and
+    /// policy. If any are set, requests to the verification endpoints need
+    /// the API key of one of them.
+    pub tenants: Vec<TenantConfig>,
+
+    /// Consume verification jobs from NATS next to serving HTTP. Needs the
+    /// `nats` feature.
+    pub queue: Option<QueueConfig>,
+    /// Sign verify responses with a key generated on startup, and serve
+    /// `GET /attestation` binding it to the measurements of the AWS Nitro
+    /// Enclave the server runs in. Needs the `nitro` feature.
+    pub attestation: bool,
+    /// Webhooks notified of JWK fetch failures and key rotations.
+    pub webhooks: Vec<WebhookConfig>,
+    /// Histogram buckets and SLOs of the Prometheus metrics.
+    pub metrics: MetricsConfig,
+    /// Log every request to an access log apart from the application logs.
+    /// No access log is written if not set.
+    pub access_log: Option<AccessLogConfig>,
+}
+
+/// The CORS policy of all routes.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct CorsConfig {
+    /// Origins allowed to call the verifier, or "*" for any origin.
+    pub allowed_origins: Vec<String>,
+    /// Methods allowed in cross-origin requests.
+    pub allowed_methods: Vec<String>,
+    /// Request headers allowed in cross-origin requests.
+    pub allowed_headers: Vec<String>,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: vec!["*".to_string()],
+            allowed_methods: vec!["GET".to_string(), "POST".to_string()],
+            allowed_headers: vec!["content-type".to_string()],
+        }
+    }
+}
+
+impl CorsConfig {
+    /// Builds the CORS layer, or returns an error naming an invalid value.
+    pub fn layer(&self) -> Result<CorsLayer, String> {
+        let allow_origin = if self.allowed_origins.iter().any(|o| o == "*") {
+            AllowOrigin::any()
+        } else {
+            AllowOrigin::list(
+                self.allowed_origins
+                    .iter()
+                    .map(|o| HeaderValue::from_str(o).map_err(|_| format!("Invalid origin: {}", o)))
+                    .collect::<Result<Vec<_>, _>>()?,
+            )
+        };
+        let methods = self
+            .allowed_methods
+            .iter()
+            .map(|m| Method::from_str(m).map_err(|_| format!("Invalid method: {}", m)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let headers = self
+            .allowed_headers
+            .iter()
+            .map(|h| HeaderName::from_str(h).map_err(|_| format!("Invalid header: {}", h)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods(methods)
+            .allow_headers(headers)
+            .expose_headers([X_REQUEST_ID]))
+    }
+}
+
+/// Protocol and connection options of the HTTP server. HTTP/1.1 and HTTP/2
+/// are both served, over TLS negotiated with ALPN and in cleartext with prior
+/// knowledge.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct HttpServerConfig {
+    /// Only serve HTTP/2, rejecting HTTP/1.1 connections.
+    pub http2_only: bool,
+    /// The maximum number of concurrent streams of an HTTP/2 connection.
+    /// Unlimited if not set.
+    pub http2_max_concurrent_streams: Option<u32>,
+    /// Seconds between HTTP/2 pings keeping idle connections alive. No
+    /// pings are sent if not set.
+    pub http2_keep_alive_interval_secs: Option<u64>,
+    /// Seconds to wait for the acknowledgement of an HTTP/2 ping before
+    /// closing the connection.
+    pub http2_keep_alive_timeout_secs: u64,
+    /// Whether to keep HTTP/1.1 connections open between requests.
+    pub http1_keep_alive: bool,
+    /// Seconds a connection is idle before TCP keepalive probes are sent.
+    /// No probes are sent if not set.
+    pub tcp_keepalive_secs: Option<u64>,
+    /// Whether to disable Nagle's algorithm on accepted connections.
+    pub tcp_nodelay: bool,
+    /// Whether to compress responses with gzip or Brotli for clients
+    /// accepting it, and accept request bodies compressed with them.
+    pub compression: bool,
+}
+
+impl Default for HttpServerConfig {
+    fn default() -> Self {
+        Self {
+            http2_only: false,
+            http2_max_concurrent_streams: None,
+            http2_keep_alive_interval_secs: None,
+            http2_keep_alive_timeout_secs: 20,
+            http1_keep_alive: true,
+            tcp_keepalive_secs: None,
+            tcp_nodelay: false,
+            compression: false,
+        }
+    }
+}
+
+impl HttpServerConfig {
+    /// Builds the protocol options of the server.
+    pub fn http_config(&self) -> HttpConfig {
+        HttpConfig::new()
+            .http2_only(self.http2_only)
+            .http2_max_concurrent_streams(self.http2_max_concurrent_streams)
+            .http2_keep_alive_interval(self.http2_keep_alive_interval_secs.map(Duration::from_secs))
+            .http2_keep_alive_timeout(Duration::from_secs(self.http2_keep_alive_timeout_secs))
+            .http1_keep_alive(self.http1_keep_alive)
+            .build()
+    }
+
+    /// Builds the options of accepted connections.
+    pub fn addr_incoming_config(&self) -> AddrIncomingConfig {
+        AddrIncomingConfig::new()
+            .tcp_keepalive(self.tcp_keepalive_secs.map(Duration::from_secs))
+            .tcp_nodelay(self.tcp_nodelay)
+            .build()
+    }
+}
+
+/// An application calling the verifier, identified by its API key.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TenantConfig {
+    /// The name of the tenant, for logs and rate limiting.
+    pub name: String,
+    /// The API key the tenant sends in the `X-Api-Key` header.
+    pub api_key: String,
+    /// The iss values of the providers whose signatures are accepted for the
+    /// tenant, on top of the global `allowed_issuers`.
+    #[serde(default)]
+    pub allowed_issuers: Option<Vec<String>>,
+    /// The limit of the requests of the tenant, on top of the global limits.
+    #[serde(default)]
+    pub rate_limit: Option<BucketConfig>,
+    /// The network of requests of the tenant that don't set one, instead of
+    /// the global `default_network`.
+    #[serde(default)]
+    pub default_network: Option<SuiEnv>,
+}
+
+/// How many requests are handled at a time, and how many more wait for a
+/// slot before requests are shed.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ConcurrencyLimitConfig {
+    /// The maximum number of requests handled at a time.
+    pub max_in_flight: usize,
+    /// The maximum number of requests waiting for a slot. Further requests
+    /// get a 503.
+    #[serde(default)]
+    pub max_queued: usize,
+}
+
+/// Token-bucket rate limits, applied to the verification routes: the
+/// `/verify*` endpoints, `/parse_signature`, `/derive_address`, `/preflight`,
+/// `/challenge`, `/verify_challenge` and `/epoch`, but not the health, JWK,
+/// metrics or admin routes. Each item of `/verify_batch`, `/verify_stream`
+/// and `/verify_ws` is charged a token of its own.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    /// Limit of all requests together.
+    pub global: Option<BucketConfig>,
+    /// Limit of the requests of each client IP.
+    pub per_ip: Option<BucketConfig>,
+    /// Limit of the verify requests signed for each zkLogin address, derived
+    /// from the signature before verifying it. Counts against every zkLogin
+    /// participant of a multisig.
+    pub per_address: Option<BucketConfig>,
+    /// Header to take the client IP from instead of the peer address, e.g.
+    /// "X-Forwarded-For" behind a load balancer. The first IP listed is used.
+    pub client_ip_header: Option<String>,
+}
+
+/// A token bucket that refills at `per_second` tokens up to `burst`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct BucketConfig {
+    pub per_second: f64,
+    pub burst: u32,
+}
+
+/// The NATS subjects to consume verification jobs from and publish their
+/// results to.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct QueueConfig {
+    /// The URL of the NATS server, e.g. "nats://localhost:4222".
+    pub url: String,
+    /// The subject jobs are published to.
+    pub subject: String,
+    /// The subject results are published to, for jobs without a reply
+    /// subject.
+    #[serde(default)]
+    pub response_subject: Option<String>,
+    /// The queue group to subscribe in, so that each job is verified by only
+    /// one of the verifiers of the group.
+    #[serde(default)]
+    pub queue_group: Option<String>,
+    /// The maximum number of jobs verified at a time.
+    #[serde(default = "default_queue_max_in_flight")]
+    pub max_in_flight: usize,
+}
+
+fn default_queue_max_in_flight() -> usize {
+    64
+}
+
+/// A webhook notified of JWK events, see [crate::webhook::WebhookEvent].
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct WebhookConfig {
+    /// The URL events are posted to.
+    pub url: String,
+    /// The format of the posted body.
+    #[serde(default)]
+    pub format: WebhookFormat,
+    /// The number of consecutive failed fetches of a provider after which
+    /// the webhook is notified.
+    #[serde(default = "default_webhook_failure_threshold")]
+    pub failure_threshold: u32,
+}
+
+fn default_webhook_failure_threshold() -> u32 {
+    3
+}
+
+/// The body posted to a webhook.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum WebhookFormat {
+    /// The event as JSON, with its kind in the `event` field.
+    #[default]
+    Generic,
+    /// A Slack incoming webhook message with a summary of the event.
+    Slack,
+}
+
+/// Where the access log is written.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct AccessLogConfig {
+    /// The file the access log is appended to. Written to stdout if not set.
+    pub path: Option<PathBuf>,
+    /// The number of entries waiting to be written. Entries beyond it, e.g.
+    /// while the disk stalls, are dropped and counted in the
+    /// `zklogin_verifier_access_log_dropped` metric.
+    pub capacity: usize,
+}
+
+impl Default for AccessLogConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            capacity: 10_000,
+        }
+    }
+}
+
+/// Options of the Prometheus metrics.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// Upper bounds in seconds of the buckets of the verify latency
+    /// histogram, in increasing order. The Prometheus default buckets if
+    /// empty.
+    pub verify_latency_buckets: Vec<f64>,
+    /// Upper bounds in seconds of the buckets of the epoch fetch latency
+    /// histogram, in increasing order. The Prometheus default buckets if
+    /// empty.
+    pub epoch_fetch_latency_buckets: Vec<f64>,
+    /// Objectives of the verify requests, whose burn rates are exported.
+    pub slos: Vec<SloConfig>,
+}
+
+/// A service level objective of the verify requests: the fraction of them
+/// that are good, i.e. don't fail on the verifier or its fullnodes, and if
+/// `latency_threshold_secs` is set, complete within it.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct SloConfig {
+    /// The name of the SLO, its label on the metrics.
+    pub name: String,
+    /// The fraction of requests that should be good, e.g. 0.999.
+    pub objective: f64,
+    /// The latency in seconds above which a request is not good.
+    #[serde(default)]
+    pub latency_threshold_secs: Option<f64>,
+    /// The windows in seconds over which burn rates are exported.
+    #[serde(default = "default_slo_windows_secs")]
+    pub windows_secs: Vec<u64>,
+}
+
+fn default_slo_windows_secs() -> Vec<u64> {
+    // The short and long windows of a multiwindow burn rate alert.
+    vec![300, 3600]
+}
+
+/// Paths to the PEM encoded certificate chain and private key for TLS.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind: SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 3000),
+            fullnode_urls: HashMap::new(),
+            default_network: SuiEnv::Mainnet,
+            providers: default_providers().iter().map(|p| p.to_string()).collect(),
+            jwk_refresh_interval_secs: 3600,
+            provider_refresh_interval_secs: HashMap::new(),
+            jwk_endpoints: HashMap::new(),
+            jwk_prune_grace_period_secs: 86400,
+            log_level: "info".to_string(),
+            log_json: false,
+            log_payloads: false,
+            log_sample_every: 1,
+            tls: None,
+            admin_token: None,
+            shutdown_timeout_secs: 30,
+            // Fits the largest transaction, Base64 encoded.
+            max_body_bytes: 256 * 1024,
+            request_timeout_secs: 30,
+            fullnode_timeout_secs: 10,
+            track_epoch_networks: None,
+            epoch_poll_interval_secs: 300,
+            jwk_drift_networks: Vec::new(),
+            jwk_drift_interval_secs: 600,
+            stale_jwks_after_secs: None,
+            stale_epoch_after_secs: None,
+            startup_jwk_timeout_secs: 30,
+            max_batch_size: 1000,
+            max_batch_body_bytes: 16 * 1024 * 1024,
+            verify_pool_size: None,
+            verify_pool_reserved_cpus: 1,
+            ws_max_in_flight: 64,
+            allow_inline_jwks: false,
+            debug_endpoint: false,
+            challenge_ttl_secs: 300,
+            challenge_secret: None,
+            allowed_issuers: None,
+            verify_policy: VerifyPolicy::default(),
+            epoch_tolerance: 0,
+            verify_cache_size: 10_000,
+            rate_limit: RateLimitConfig::default(),
+            concurrency_limit: None,
+            cors: None,
+            http: HttpServerConfig::default(),
+            tenants: Vec::new(),
+            queue: None,
+            attestation: false,
+            webhooks: Vec::new(),
+            metrics: MetricsConfig::default(),
+            access_log: None,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config from a file, parsed as YAML if the extension is
+    /// `.yaml` or `.yml` and as TOML otherwise.
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read config file {}: {}", path.display(), e))?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml") | Some("yml") => Self::from_yaml(&contents),
+            _ => Self::from_toml(&contents),
+        }
+    }
+
+    /// Parses the config from a TOML string.
+    pub fn from_toml(contents: &str) -> Result<Self, String> {
+        toml::from_str(contents).map_err(|e| format!("Invalid TOML config: {}", e))
+    }
+
+    /// Parses the config from a YAML string.
+    pub fn from_yaml(contents: &str) -> Result<Self, String> {
+        serde_yaml::from_str(contents).map_err(|e| format!("Invalid YAML config: {}", e))
+    }
+
+    /// Returns the configured OIDC providers without duplicates, or an error
+    /// naming the first unknown one. At least one provider must be enabled.
+    pub fn oidc_providers(&self) -> Result<Vec<OIDCProvider>, String> {
+        let mut providers = Vec::new();
+        for p in &self.providers {
+            let provider =
+                OIDCProvider::from_str(p).map_err(|_| format!("Unknown provider: {}", p))?;
+            if !providers.contains(&provider) {
+                providers.push(provider);
+            }
+        }
+        if providers.is_empty() {
+            return Err("At least one provider must be enabled".to_string());
+        }
+        if let Some(p) = self
+            .provider_refresh_interval_secs
+            .keys()
+            .find(|p| OIDCProvider::from_str(p).is_err())
+        {
+            return Err(format!("Unknown provider in refresh intervals: {}", p));
+        }
+        if let Some(p) = self
+            .jwk_endpoints
+            .keys()
+            .find(|p| OIDCProvider::from_str(p).is_err())
+        {
+            return Err(format!("Unknown provider in JWK endpoints: {}", p));
+        }
+        Ok(providers)
+    }
+
+    /// Checks that the config is usable, failing early on bad values.
+    pub fn validate(&self) -> Result<(), String> {
+        self.oidc_providers()?;
+        if let Some(cors) = &self.cors {
+            cors.layer()?;
+        }
+        if self.challenge_ttl_secs == 0 {
+            return Err("challenge_ttl_secs must be positive".to_string());
+        }
+        if self.request_timeout_secs == 0 {
+            return Err("request_timeout_secs must be positive".to_string());
+        }
+        if self.fullnode_timeout_secs == 0 {
+            return Err("fullnode_timeout_secs must be positive".to_string());
+        }
+        if self.jwk_refresh_interval_secs == 0 {
+            return Err("jwk_refresh_interval_secs must be positive".to_string());
+        }
+        if let Some((p, _)) = self
+            .provider_refresh_interval_secs
+            .iter()
+            .find(|(_, secs)| **secs == 0)
+        {
+            return Err(format!("Refresh interval of {} must be positive", p));
+        }
+        if self
+            .challenge_secret
+            .as_ref()
+            .is_some_and(|secret| secret.len() < 32)
+        {
+            return Err("challenge_secret must be at least 32 bytes".to_string());
+        }
+        if self
+            .concurrency_limit
+            .as_ref()
+            .is_some_and(|limit| limit.max_in_flight == 0)
+        {
+            return Err("concurrency_limit.max_in_flight must be positive".to_string());
+        }
+        if self.log_sample_every == 0 {
+            return Err("log_sample_every must be positive".to_string());
+        }
+        if self
+            .access_log
+            .as_ref()
+            .is_some_and(|access_log| access_log.capacity == 0)
+        {
+            return Err("access_log.capacity must be positive".to_string());
+        }
+        if self.jwk_drift_interval_secs == 0 {
+            return Err("jwk_drift_interval_secs must be positive".to_string());
+        }
+        if self.queue.is_some() && !cfg!(feature = "nats") {
+            return Err("The queue consumer needs a build with the nats feature".to_string());
+        }
+        if self.attestation && !cfg!(feature = "nitro") {
+            return Err("Attestation needs a build with the nitro feature".to_string());
+        }
+        for webhook in &self.webhooks {
+            if reqwest::Url::parse(&webhook.url).is_err() {
+                return Err("Invalid webhook URL".to_string());
+            }
+            if webhook.failure_threshold == 0 {
+                return Err("Webhook failure_threshold must be positive".to_string());
+            }
+        }
+        for bucket in [
+            &self.rate_limit.global,
+            &self.rate_limit.per_ip,
+            &self.rate_limit.per_address,
+        ]
+        .into_iter()
+        .chain(self.tenants.iter().map(|t| &t.rate_limit))
+        .flatten()
+        {
+            if bucket.per_second.is_nan() || bucket.per_second <= 0.0 || bucket.burst == 0 {
+                return Err(format!(
+                    "Rate limits need a positive per_second and burst, got {:?}",
+                    bucket
+                ));
+            }
+        }
+        for (name, buckets) in [
+            (
+                "verify_latency_buckets",
+                &self.metrics.verify_latency_buckets,
+            ),
+            (
+                "epoch_fetch_latency_buckets",
+                &self.metrics.epoch_fetch_latency_buckets,
+            ),
+        ] {
+            if buckets.iter().any(|b| !b.is_finite())
+                || buckets.windows(2).any(|pair| pair[0] >= pair[1])
+            {
+                return Err(format!("metrics.{} must be increasing numbers", name));
+            }
+        }
+        let mut slo_names = HashSet::new();
+        for slo in &self.metrics.slos {
+            if slo.name.is_empty() || !slo_names.insert(&slo.name) {
+                return Err("SLOs need a name, different from other SLOs".to_string());
+            }
+            if slo.objective.is_nan() || slo.objective <= 0.0 || slo.objective >= 1.0 {
+                return Err(format!(
+                    "SLO {} needs an objective between 0 and 1",
+                    slo.name
+                ));
+            }
+            if slo
+                .latency_threshold_secs
+                .is_some_and(|threshold| threshold.is_nan() || threshold <= 0.0)
+            {
+                return Err(format!(
+                    "SLO {} needs a positive latency_threshold_secs",
+                    slo.name
+                ));
+            }
+            if slo.windows_secs.is_empty() || slo.windows_secs.contains(&0) {
+                return Err(format!("SLO {} needs positive windows_secs", slo.name));
+            }
+        }
+        let mut names = HashSet::new();
+        let mut api_keys = HashSet::new();
+        for tenant in &self.tenants {
+            if tenant.api_key.is_empty() {
+                return Err(format!("Tenant {} has an empty api_key", tenant.name));
+            }
+            if !names.insert(&tenant.name) || !api_keys.insert(&tenant.api_key) {
+                return Err(format!(
+                    "Tenant {} has the name or api_key of another tenant",
+                    tenant.name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the tenant with the API key. Every key is compared in constant
+    /// time, so that timing does not reveal how much of a key matched.
+    pub fn tenant(&self, api_key: &str) -> Option<&TenantConfig> {
+        self.tenants.iter().fold(None, |found, tenant| {
+            let matches = tenant.api_key.as_bytes().ct_eq(api_key.as_bytes());
+            found.or(bool::from(matches).then_some(tenant))
+        })
+    }
+
+    /// Returns the endpoint to fetch the JWKs of the provider from.
+    pub fn jwk_endpoint(&self, provider: &OIDCProvider) -> String {
+        self.jwk_endpoints
+            .get(&provider.to_string())
+            .cloned()
+            .unwrap_or_else(|| provider.get_config().jwk_endpoint)
+    }
+
+    /// Returns the refresh interval of the JWKs of the provider.
+    pub fn jwk_refresh_interval(&self, provider: &OIDCProvider) -> Duration {
+        let secs = self
+            .provider_refresh_interval_secs
+            .get(&provider.to_string())
+            .copied()
+            .unwrap_or(self.jwk_refresh_interval_secs);
+        Duration::from_secs(secs)
+    }
+
+    /// Returns the networks whose epoch is tracked in the background:
+    /// `track_epoch_networks` if set, and otherwise the default network,
+    /// the networks with fullnode URLs and the default networks of tenants.
+    pub fn tracked_networks(&self) -> Vec<SuiEnv> {
+        if let Some(networks) = &self.track_epoch_networks {
+            return networks.clone();
+        }
+        let mut networks = vec![self.default_network];
+        let mut configured: Vec<SuiEnv> = self.fullnode_urls.keys().copied().collect();
+        // In a stable order, whatever the order of the map.
+        configured.sort_by_key(|network| format!("{:?}", network));
+        for network in configured
+            .into_iter()
+            .chain(self.tenants.iter().filter_map(|t| t.default_network))
+        {
+            if !networks.contains(&network) {
+                networks.push(network);
+            }
+        }
+        networks
+    }
+
+    /// Returns the fullnode URLs for the network in failover order, using
+    /// the configured ones if present.
+    pub fn fullnode_urls(&self, network: SuiEnv) -> Vec<String> {
+        match self.fullnode_urls.get(&network) {
+            Some(urls) if !urls.is_empty() => urls.clone(),
+            _ => vec![network.get_params().0.to_string()],
+        }
+    }
+}
@@ -0,0 +1,67 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Deadlines set by callers, so that requests they have given up on are not
+//! worked on any longer.
+
+use axum::http::{HeaderMap, HeaderName};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The header carrying the deadline of a request, in milliseconds since the
+/// Unix epoch.
+pub const X_REQUEST_DEADLINE: HeaderName = HeaderName::from_static("x-request-deadline");
+
+/// The header carrying the timeout of a request in the format of gRPC, e.g.
+/// "1500m" for 1.5 seconds.
+pub const GRPC_TIMEOUT: HeaderName = HeaderName::from_static("grpc-timeout");
+
+/// Returns the time left until the deadline of the request at `now`, zero if
+/// it has passed, or none if the request has no deadline. With both headers
+/// set, the earlier deadline applies.
+pub fn time_left(headers: &HeaderMap, now: SystemTime) -> Result<Option<Duration>, String> {
+    let deadline = match headers.get(&X_REQUEST_DEADLINE) {
+        Some(value) => {
+            let millis: u64 = value
+                .to_str()
+                .ok()
+                .and_then(|value| value.trim().parse().ok())
+                .ok_or_else(|| format!("Invalid {} header", X_REQUEST_DEADLINE))?;
+            let deadline = UNIX_EPOCH + Duration::from_millis(millis);
+            Some(deadline.duration_since(now).unwrap_or_default())
+        }
+        None => None,
+    };
+    let timeout = match headers.get(&GRPC_TIMEOUT) {
+        Some(value) => Some(
+            value
+                .to_str()
+                .ok()
+                .and_then(parse_grpc_timeout)
+                .ok_or_else(|| format!("Invalid {} header", GRPC_TIMEOUT))?,
+        ),
+        None => None,
+    };
+    Ok(match (deadline, timeout) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    })
+}
+
+/// Parses a gRPC timeout: at most 8 digits followed by the unit, one of H, M,
+/// S, m, u or n.
+fn parse_grpc_timeout(value: &str) -> Option<Duration> {
+    let (digits, unit) = value.split_at(value.len().checked_sub(1)?);
+    if digits.is_empty() || digits.len() > 8 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let amount: u64 = digits.parse().ok()?;
+    Some(match unit {
+        "H" => Duration::from_secs(amount * 3600),
+        "M" => Duration::from_secs(amount * 60),
+        "S" => Duration::from_secs(amount),
+        "m" => Duration::from_millis(amount),
+        "u" => Duration::from_micros(amount),
+        "n" => Duration::from_nanos(amount),
+        _ => return None,
+    })
+}
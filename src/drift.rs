@@ -0,0 +1,256 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Checks in the background that the JWKs fetched from the providers match
+//! the JWKs active in the on-chain authenticator state of networks. Where
+//! they differ, this verifier and the validators of the network disagree on
+//! signatures, e.g. accepting a signature validators reject.
+
+use crate::{server::unix_secs, AppState, SuiEnv};
+use axum::{extract::State, Json};
+use fastcrypto_zkp::bn254::zk_login::{JwkId, JWK};
+use im::hashmap::HashMap as ImHashMap;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use sui_sdk::{
+    rpc_types::{SuiObjectDataOptions, SuiRawData},
+    SuiClient,
+};
+use sui_types::{
+    authenticator_state::{AuthenticatorState, AuthenticatorStateInner},
+    base_types::ObjectID,
+    dynamic_field::{derive_dynamic_field_id, Field},
+    TypeTag, SUI_AUTHENTICATOR_STATE_OBJECT_ID,
+};
+use tracing::{info, warn};
+
+/// The JWKs on which the providers and a network differ, for the iss values
+/// of the configured providers.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JwkDrift {
+    /// JWKs the verifier holds that are not active on-chain, so signatures
+    /// with them are accepted here but rejected by validators.
+    pub only_fetched: Vec<JwkId>,
+    /// JWKs active on-chain that the verifier doesn't hold, so signatures
+    /// with them are rejected here but accepted by validators.
+    pub only_on_chain: Vec<JwkId>,
+    /// JWKs with the same iss and kid but different keys.
+    pub mismatched: Vec<JwkId>,
+}
+
+impl JwkDrift {
+    /// Returns whether the verifier and the network agree on all JWKs.
+    pub fn is_empty(&self) -> bool {
+        self.only_fetched.is_empty() && self.only_on_chain.is_empty() && self.mismatched.is_empty()
+    }
+}
+
+/// The latest drift check of a network.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct JwkDriftReport {
+    /// The network checked.
+    pub network: SuiEnv,
+    /// When the on-chain JWKs were last read successfully, in Unix seconds.
+    pub checked_at: Option<u64>,
+    /// The drift found by the latest successful check, if any.
+    pub drift: Option<JwkDrift>,
+    /// The error of the latest check, if it failed.
+    pub last_error: Option<String>,
+}
+
+/// Response of `GET /jwks/drift`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JwkDriftResponse {
+    /// The report of each network of `jwk_drift_networks`.
+    pub networks: Vec<JwkDriftReport>,
+}
+
+/// Compares the JWKs of the verifier against the JWKs active on-chain,
+/// ignoring JWKs of iss values outside `issuers` on either side.
+pub fn compare_jwks(
+    fetched: &ImHashMap<JwkId, JWK>,
+    on_chain: &[(JwkId, JWK)],
+    issuers: &[String],
+) -> JwkDrift {
+    let fetched: BTreeMap<_, _> = fetched
+        .iter()
+        .filter(|(id, _)| issuers.contains(&id.iss))
+        .collect();
+    let on_chain: BTreeMap<_, _> = on_chain
+        .iter()
+        .filter(|(id, _)| issuers.contains(&id.iss))
+        .map(|(id, jwk)| (id, jwk))
+        .collect();
+    let mut drift = JwkDrift::default();
+    for (id, jwk) in &fetched {
+        match on_chain.get(id) {
+            None => drift.only_fetched.push((*id).clone()),
+            Some(on_chain_jwk) if on_chain_jwk != jwk => drift.mismatched.push((*id).clone()),
+            Some(_) => {}
+        }
+    }
+    drift.only_on_chain = on_chain
+        .keys()
+        .filter(|id| !fetched.contains_key(*id))
+        .map(|id| (*id).clone())
+        .collect();
+    drift
+}
+
+/// Starts checking each network of `jwk_drift_networks`. The returned tasks
+/// run until aborted.
+pub fn spawn_drift_checkers(state: &Arc<AppState>) -> Vec<tokio::task::JoinHandle<()>> {
+    state
+        .config
+        .load()
+        .jwk_drift_networks
+        .iter()
+        .map(|network| tokio::spawn(run_drift_checker(state.clone(), *network)))
+        .collect()
+}
+
+/// Compares the JWKs of the verifier against the network every
+/// `jwk_drift_interval_secs`, keeping the report in `state.jwk_drift` and
+/// the `jwk_drift` metric.
+pub async fn run_drift_checker(state: Arc<AppState>, network: SuiEnv) {
+    info!("Checking the JWKs of {:?} for drift", network);
+    loop {
+        check_drift(&state, network).await;
+        let interval = Duration::from_secs(state.config.load().jwk_drift_interval_secs);
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Runs one drift check of the network.
+pub async fn check_drift(state: &AppState, network: SuiEnv) {
+    let result = fetch_on_chain_jwks(state, network).await;
+    let mut reports = state.jwk_drift.lock();
+    let report = reports.entry(network).or_insert_with(|| JwkDriftReport {
+        network,
+        ..Default::default()
+    });
+    match result {
+        Ok(on_chain) => {
+            let issuers: Vec<String> = state
+                .providers
+                .load()
+                .iter()
+                .map(|p| p.get_config().iss)
+                .collect();
+            let drift = compare_jwks(&state.jwks.load(), &on_chain, &issuers);
+            if !drift.is_empty() && report.drift.as_ref() != Some(&drift) {
+                warn!(
+                    "JWKs differ from the on-chain JWKs of {:?}: {:?}",
+                    network, drift
+                );
+            }
+            let label = format!("{:?}", network);
+            for (kind, ids) in [
+                ("only_fetched", &drift.only_fetched),
+                ("only_on_chain", &drift.only_on_chain),
+                ("mismatched", &drift.mismatched),
+            ] {
+                state
+                    .metrics
+                    .jwk_drift
+                    .with_label_values(&[&label, kind])
+                    .set(ids.len() as i64);
+            }
+            report.checked_at = Some(unix_secs(SystemTime::now()));
+            report.drift = Some(drift);
+            report.last_error = None;
+        }
+        Err(e) => {
+            warn!("Cannot read the on-chain JWKs of {:?}: {}", network, e);
+            report.last_error = Some(e);
+        }
+    }
+}
+
+/// Returns the latest drift check of each network of `jwk_drift_networks`.
+pub async fn get_jwk_drift(State(state): State<Arc<AppState>>) -> Json<JwkDriftResponse> {
+    let reports = state.jwk_drift.lock();
+    let networks = state
+        .config
+        .load()
+        .jwk_drift_networks
+        .iter()
+        .map(|network| {
+            reports.get(network).cloned().unwrap_or(JwkDriftReport {
+                network: *network,
+                ..Default::default()
+            })
+        })
+        .collect();
+    Json(JwkDriftResponse { networks })
+}
+
+/// Reads the JWKs active in the authenticator state of the network, failing
+/// over to the next configured fullnode when one errors.
+async fn fetch_on_chain_jwks(
+    state: &AppState,
+    network: SuiEnv,
+) -> Result<Vec<(JwkId, JWK)>, String> {
+    let mut last_error = "No fullnode configured".to_string();
+    for url in state.config.load().fullnode_urls(network) {
+        let timeout = Duration::from_secs(state.config.load().fullnode_timeout_secs);
+        let sui_client = match state.sui_clients.get(&url, timeout).await {
+            Ok(sui_client) => sui_client,
+            Err(e) => {
+                warn!("Cannot connect to fullnode {}", url);
+                last_error = e.to_string();
+                continue;
+            }
+        };
+        match tokio::time::timeout(timeout, read_active_jwks(&sui_client)).await {
+            Ok(Ok(jwks)) => return Ok(jwks),
+            Ok(Err(e)) => {
+                warn!("Cannot get JWKs from fullnode {}: {}", url, e);
+                last_error = e;
+            }
+            Err(_) => {
+                warn!("Timed out getting JWKs from fullnode {}", url);
+                last_error = format!("Timed out getting JWKs from fullnode {}", url);
+            }
+        }
+    }
+    Err(last_error)
+}
+
+/// Reads the active JWKs from the inner authenticator state, a dynamic field
+/// of the authenticator state object keyed by its version.
+async fn read_active_jwks(sui_client: &SuiClient) -> Result<Vec<(JwkId, JWK)>, String> {
+    let outer: AuthenticatorState =
+        read_object(sui_client, SUI_AUTHENTICATOR_STATE_OBJECT_ID).await?;
+    let key = bcs::to_bytes(&outer.version).expect("u64 is serializable");
+    let inner_id = derive_dynamic_field_id(SUI_AUTHENTICATOR_STATE_OBJECT_ID, &TypeTag::U64, &key)
+        .map_err(|e| e.to_string())?;
+    let inner: Field<u64, AuthenticatorStateInner> = read_object(sui_client, inner_id).await?;
+    Ok(inner
+        .value
+        .active_jwks
+        .into_iter()
+        .map(|active| (active.jwk_id, active.jwk))
+        .collect())
+}
+
+/// Reads a Move object and BCS decodes its contents.
+async fn read_object<T: DeserializeOwned>(
+    sui_client: &SuiClient,
+    id: ObjectID,
+) -> Result<T, String> {
+    let response = sui_client
+        .read_api()
+        .get_object_with_options(id, SuiObjectDataOptions::new().with_bcs())
+        .await
+        .map_err(|e| e.to_string())?;
+    match response.data.and_then(|data| data.bcs) {
+        Some(SuiRawData::MoveObject(object)) => bcs::from_bytes(&object.bcs_bytes)
+            .map_err(|e| format!("Cannot decode object {}: {}", id, e)),
+        _ => Err(format!("Object {} not found", id)),
+    }
+}
@@ -0,0 +1,242 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Tracking of the current epoch of networks in the background, so that
+//! verify requests on them don't wait for a fullnode.
+
+use crate::{
+    server::{request_network, unix_secs},
+    AppState, SuiEnv, VerifyError,
+};
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use sui_types::committee::EpochId;
+use tracing::{info, warn};
+
+/// The shortest delay between two fetches of the epoch of a network, used
+/// while waiting for an overdue epoch change and after failed fetches.
+const MIN_POLL_DELAY: Duration = Duration::from_secs(5);
+
+/// How long past the later of the expected epoch change and its fetch a
+/// tracked epoch is used, so that a tracker that cannot reach its fullnodes
+/// does not keep expired signatures verifying.
+const TRACKED_EPOCH_SLACK: Duration = Duration::from_secs(60);
+
+/// The number of failed background fetches in a row after which the tracked
+/// epoch of a network is no longer used.
+const MAX_TRACKED_EPOCH_FAILURES: u32 = 3;
+
+/// The current epoch of a network, as returned by a fullnode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EpochStatus {
+    /// The current epoch.
+    pub epoch: EpochId,
+    /// When the epoch was fetched.
+    pub fetched_at: SystemTime,
+    /// When the next epoch is expected to start, from the start and duration
+    /// of the current epoch.
+    pub next_epoch_at: SystemTime,
+}
+
+/// The outcome of the latest background fetches of the epoch of a network.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EpochFetchStatus {
+    /// When the epoch was last fetched successfully.
+    pub last_success: Option<SystemTime>,
+    /// The error of the latest fetch, if it failed.
+    pub last_error: Option<String>,
+    /// The number of fetches that failed since the last successful one.
+    pub consecutive_failures: u32,
+}
+
+/// Query of `GET /epoch`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EpochQuery {
+    /// The network, `default_network` if not set.
+    pub network: Option<SuiEnv>,
+}
+
+/// Response of `GET /epoch`.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EpochResponse {
+    pub network: SuiEnv,
+    /// The current epoch, as verify requests on the network use it.
+    pub epoch: EpochId,
+    /// When the epoch was fetched from a fullnode, in Unix seconds.
+    pub fetched_at: u64,
+    /// When the next epoch is expected to start, in Unix seconds.
+    pub next_epoch_at: u64,
+    /// Whether the epoch of the network is tracked in the background.
+    /// Otherwise it was fetched for this request, as it is for each verify
+    /// request.
+    pub tracked: bool,
+    /// The number of background fetches that failed since the last
+    /// successful one, for tracked networks.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub consecutive_failures: u32,
+    /// When the epoch was last fetched successfully in the background, in
+    /// Unix seconds, for tracked networks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_success: Option<u64>,
+    /// The error of the latest background fetch, if it failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
+
+/// Returns the current epoch of the network that verify requests without
+/// `curr_epoch` use, so that clients setting `curr_epoch` can agree with it.
+pub async fn get_current_epoch(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<EpochQuery>,
+) -> Result<Json<EpochResponse>, VerifyError> {
+    let network = request_network(&state, query.network);
+    let tracked = tracked_epoch(&state, network);
+    let status = match tracked {
+        Some(status) => status,
+        None => state.fetch_epoch_status(network).await?,
+    };
+    let fetch_status = state
+        .epoch_fetch_status
+        .lock()
+        .get(&network)
+        .cloned()
+        .unwrap_or_default();
+    Ok(Json(EpochResponse {
+        network,
+        epoch: status.epoch,
+        fetched_at: unix_secs(status.fetched_at),
+        next_epoch_at: unix_secs(status.next_epoch_at),
+        tracked: tracked.is_some(),
+        consecutive_failures: fetch_status.consecutive_failures,
+        last_success: fetch_status.last_success.map(unix_secs),
+        last_error: fetch_status.last_error,
+    }))
+}
+
+/// Returns the tracked epoch of the network, unless its epoch change is
+/// overdue by [TRACKED_EPOCH_SLACK] without a fetch confirming it, or more
+/// than [MAX_TRACKED_EPOCH_FAILURES] fetches failed in a row. The entry is
+/// then dropped, so that requests fetch the epoch themselves and fail if
+/// the fullnodes stay unreachable, until the tracker fetches it again.
+pub(crate) fn tracked_epoch(state: &AppState, network: SuiEnv) -> Option<EpochStatus> {
+    let mut epochs = state.epochs.lock();
+    let status = *epochs.get(&network)?;
+    let failures = state
+        .epoch_fetch_status
+        .lock()
+        .get(&network)
+        .map_or(0, |fetch_status| fetch_status.consecutive_failures);
+    let valid_until = status.next_epoch_at.max(status.fetched_at) + TRACKED_EPOCH_SLACK;
+    if SystemTime::now() > valid_until || failures > MAX_TRACKED_EPOCH_FAILURES {
+        warn!(
+            "Not using the tracked epoch {} of {:?}, fetched at {}s with {} failed fetches since",
+            status.epoch,
+            network,
+            unix_secs(status.fetched_at),
+            failures
+        );
+        epochs.remove(&network);
+        return None;
+    }
+    Some(status)
+}
+
+/// Starts tracking the epoch of each network of
+/// [crate::config::Config::tracked_networks], independently of requests.
+/// The returned tasks run until aborted.
+pub fn spawn_epoch_trackers(state: &Arc<AppState>) -> Vec<tokio::task::JoinHandle<()>> {
+    state
+        .config
+        .load()
+        .tracked_networks()
+        .into_iter()
+        .map(|network| tokio::spawn(run_epoch_tracker(state.clone(), network)))
+        .collect()
+}
+
+/// Keeps the epoch of the network in `state.epochs` current, and the outcome
+/// of its fetches in `state.epoch_fetch_status`. The epoch is fetched when
+/// the next epoch is expected to start, then every [MIN_POLL_DELAY] until it
+/// has, and at least every `epoch_poll_interval_secs` in between.
+pub async fn run_epoch_tracker(state: Arc<AppState>, network: SuiEnv) {
+    info!("Tracking the epoch of {:?}", network);
+    loop {
+        let poll_interval = Duration::from_secs(state.config.load().epoch_poll_interval_secs);
+        let result = state.fetch_epoch_status(network).await;
+        record_fetch(&state, network, &result);
+        let delay = match result {
+            Ok(status) => {
+                let previous = state.epochs.lock().insert(network, status);
+                if previous.map(|previous| previous.epoch) != Some(status.epoch) {
+                    info!("Epoch of {:?} is now {}", network, status.epoch);
+                }
+                status
+                    .next_epoch_at
+                    .duration_since(SystemTime::now())
+                    .unwrap_or_default()
+                    .clamp(MIN_POLL_DELAY, poll_interval.max(MIN_POLL_DELAY))
+            }
+            Err(e) => {
+                warn!(
+                    "Cannot fetch the epoch of {:?}: {:?}, retrying in {:?}",
+                    network, e, MIN_POLL_DELAY
+                );
+                MIN_POLL_DELAY
+            }
+        };
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Records the outcome of a background fetch of the epoch of the network,
+/// also in the `epoch_fetch_failures` metric.
+fn record_fetch(state: &AppState, network: SuiEnv, result: &Result<EpochStatus, VerifyError>) {
+    let mut fetch_status = state.epoch_fetch_status.lock();
+    let status = fetch_status.entry(network).or_default();
+    match result {
+        Ok(epoch) => {
+            status.last_success = Some(epoch.fetched_at);
+            status.last_error = None;
+            status.consecutive_failures = 0;
+        }
+        Err(e) => {
+            status.last_error = Some(error_chain(e));
+            status.consecutive_failures += 1;
+        }
+    }
+    state
+        .metrics
+        .epoch_fetch_failures
+        .with_label_values(&[&format!("{:?}", network)])
+        .set(status.consecutive_failures as i64);
+}
+
+/// Returns the message of the error followed by those of its sources, as
+/// the fullnode errors are kept as sources of [VerifyError::GetEpochError].
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(e) = source {
+        message.push_str(": ");
+        message.push_str(&e.to_string());
+        source = e.source();
+    }
+    message
+}
+
+/// Returns when an epoch that started at `start_ms` and lasts `duration_ms`,
+/// both in milliseconds as reported by fullnodes, ends.
+pub(crate) fn epoch_end(start_ms: u64, duration_ms: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(start_ms.saturating_add(duration_ms))
+}
@@ -0,0 +1,215 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! C bindings to the network-free verifier, so mobile wallets can link it
+//! into iOS and Android apps. Building with the `ffi` feature generates the
+//! header `include/zklogin_verifier.h` with cbindgen.
+//!
+//! Every call writes its result to `out` as a JSON string: the response on
+//! [ZkLoginStatus::Ok], or `{"error": ..., "code": ...}` as in error
+//! responses of the server on [ZkLoginStatus::Failed]. The caller owns the
+//! string and frees it with [zklogin_string_free].
+
+use crate::{
+    verifier::{
+        decode_signature, parse_zklogin_signature, verify_personal_message, verify_transaction_data,
+    },
+    BinaryEncoding, ErrorCode, JwkEntry, JwksResponse, SuiEnv, VerifyError,
+};
+use fastcrypto_zkp::bn254::{
+    zk_login::{JwkId, JWK},
+    zk_login_api::ZkLoginEnv,
+};
+use im::hashmap::HashMap as ImHashMap;
+use serde::Serialize;
+use std::{
+    ffi::{c_char, CStr, CString},
+    panic::{catch_unwind, AssertUnwindSafe},
+    slice,
+    str::FromStr,
+};
+use sui_types::base_types::SuiAddress;
+
+/// Whether a call succeeded, and so what `out` holds.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZkLoginStatus {
+    /// `out` holds the response.
+    Ok = 0,
+    /// `out` holds the error and its code.
+    Failed = 1,
+}
+
+/// The error a call failed with, as written to `out`.
+#[derive(Debug, Serialize)]
+struct Failure {
+    error: String,
+    code: ErrorCode,
+}
+
+impl From<VerifyError> for Failure {
+    fn from(e: VerifyError) -> Self {
+        Failure {
+            code: e.code(),
+            error: e.to_string(),
+        }
+    }
+}
+
+fn invalid_argument(error: String) -> Failure {
+    Failure {
+        error,
+        code: ErrorCode::InvalidRequest,
+    }
+}
+
+/// Verifies a Base64 encoded signature over transaction data, given as BCS
+/// bytes, against `jwks` at `curr_epoch`. `jwks` is JSON as returned by
+/// `GET /jwks`, and `network` one of "mainnet", "testnet", "devnet" or
+/// "localnet", or null for mainnet.
+///
+/// # Safety
+///
+/// `tx_bytes` must point to `tx_bytes_len` bytes, and the strings must be
+/// NUL-terminated or null. `out` must be valid for a write, or null to
+/// discard the result.
+#[no_mangle]
+pub unsafe extern "C" fn zklogin_verify_transaction(
+    tx_bytes: *const u8,
+    tx_bytes_len: usize,
+    signature: *const c_char,
+    jwks: *const c_char,
+    curr_epoch: u64,
+    network: *const c_char,
+    out: *mut *mut c_char,
+) -> ZkLoginStatus {
+    call(out, || {
+        let tx_bytes = bytes_arg(tx_bytes, tx_bytes_len, "tx_bytes")?;
+        let signature = decode_signature(str_arg(signature, "signature")?, BinaryEncoding::Base64)?;
+        let jwks = jwks_arg(jwks)?;
+        let env = env_arg(network)?;
+        Ok(verify_transaction_data(
+            tx_bytes, &signature, curr_epoch, jwks, env,
+        )?)
+    })
+}
+
+/// Verifies a Base64 encoded signature by `author`, a hex encoded Sui
+/// address, over a personal message. The other arguments are as for
+/// [zklogin_verify_transaction].
+///
+/// # Safety
+///
+/// `message` must point to `message_len` bytes, and the strings must be
+/// NUL-terminated or null. `out` must be valid for a write, or null to
+/// discard the result.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn zklogin_verify_personal_message(
+    message: *const u8,
+    message_len: usize,
+    author: *const c_char,
+    signature: *const c_char,
+    jwks: *const c_char,
+    curr_epoch: u64,
+    network: *const c_char,
+    out: *mut *mut c_char,
+) -> ZkLoginStatus {
+    call(out, || {
+        let message = bytes_arg(message, message_len, "message")?;
+        let author = SuiAddress::from_str(str_arg(author, "author")?)
+            .map_err(|e| invalid_argument(format!("Invalid author: {}", e)))?;
+        let signature = decode_signature(str_arg(signature, "signature")?, BinaryEncoding::Base64)?;
+        let jwks = jwks_arg(jwks)?;
+        let env = env_arg(network)?;
+        Ok(verify_personal_message(
+            message, author, &signature, curr_epoch, jwks, env,
+        )?)
+    })
+}
+
+/// Parses a Base64 encoded zkLogin signature and writes its components to
+/// `out`, without verifying it.
+///
+/// # Safety
+///
+/// `signature` must be NUL-terminated or null. `out` must be valid for a
+/// write, or null to discard the result.
+#[no_mangle]
+pub unsafe extern "C" fn zklogin_parse_signature(
+    signature: *const c_char,
+    out: *mut *mut c_char,
+) -> ZkLoginStatus {
+    call(out, || {
+        Ok(parse_zklogin_signature(str_arg(signature, "signature")?)?)
+    })
+}
+
+/// Frees a string written to `out` by the other functions. Does nothing if
+/// `s` is null.
+///
+/// # Safety
+///
+/// `s` must have been returned by this library, and not be freed before.
+#[no_mangle]
+pub unsafe extern "C" fn zklogin_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Runs `f` and writes its result to `out`. Panics are reported as failures
+/// rather than unwinding into the caller, which is undefined behavior.
+unsafe fn call<T: Serialize>(
+    out: *mut *mut c_char,
+    f: impl FnOnce() -> Result<T, Failure>,
+) -> ZkLoginStatus {
+    let result = catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| {
+        Err(Failure {
+            error: "The verifier panicked".to_string(),
+            code: ErrorCode::VerifyFailed,
+        })
+    });
+    let (status, json) = match result {
+        Ok(res) => (ZkLoginStatus::Ok, serde_json::to_string(&res)),
+        Err(e) => (ZkLoginStatus::Failed, serde_json::to_string(&e)),
+    };
+    if !out.is_null() {
+        // JSON escapes NUL, so the string has none inside.
+        let json = json.expect("result is serializable");
+        *out = CString::new(json).expect("JSON has no NUL").into_raw();
+    }
+    status
+}
+
+unsafe fn bytes_arg<'a>(ptr: *const u8, len: usize, name: &str) -> Result<&'a [u8], Failure> {
+    match (ptr.is_null(), len) {
+        (true, 0) => Ok(&[]),
+        (true, _) => Err(invalid_argument(format!("{} is null", name))),
+        (false, _) => Ok(slice::from_raw_parts(ptr, len)),
+    }
+}
+
+unsafe fn str_arg<'a>(ptr: *const c_char, name: &str) -> Result<&'a str, Failure> {
+    if ptr.is_null() {
+        return Err(invalid_argument(format!("{} is null", name)));
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|_| invalid_argument(format!("{} is not UTF-8", name)))
+}
+
+unsafe fn jwks_arg(ptr: *const c_char) -> Result<ImHashMap<JwkId, JWK>, Failure> {
+    let jwks: JwksResponse = serde_json::from_str(str_arg(ptr, "jwks")?)
+        .map_err(|e| invalid_argument(format!("Invalid jwks: {}", e)))?;
+    Ok(jwks.jwks.into_iter().map(JwkEntry::into_parts).collect())
+}
+
+unsafe fn env_arg(ptr: *const c_char) -> Result<ZkLoginEnv, Failure> {
+    let network = if ptr.is_null() {
+        SuiEnv::default()
+    } else {
+        SuiEnv::from_str(str_arg(ptr, "network")?).map_err(invalid_argument)?
+    };
+    Ok(network.get_params().1)
+}
@@ -0,0 +1,183 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! A GraphQL API served at `/graphql`, mirroring `/verify` and `/jwks`.
+
+use crate::{
+    deserialize_intent_scope,
+    server::{request_jwk_ids, verify_payload},
+    AppState, JwkEntry, SuiEnv, VerifyError, VerifyRequest, VerifyResponse,
+};
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, ErrorExtensions, InputObject, Object, Schema,
+    SimpleObject,
+};
+use async_graphql_axum::GraphQL;
+use std::{str::FromStr, sync::Arc};
+use sui_types::{base_types::SuiAddress, committee::EpochId};
+
+/// The schema of the GraphQL API.
+pub type VerifierSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+/// Returns the schema of the GraphQL API, serving `state`.
+pub fn schema(state: Arc<AppState>) -> VerifierSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(state)
+        .finish()
+}
+
+/// Returns the service of the GraphQL API, serving `state`.
+pub fn service(state: Arc<AppState>) -> GraphQL<VerifierSchema> {
+    GraphQL::new(schema(state))
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Verifies a zkLogin signature, like `POST /verify`.
+    async fn verify_signature(
+        &self,
+        ctx: &Context<'_>,
+        request: VerifyInput,
+    ) -> async_graphql::Result<VerifyResult> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let payload = request.into_request()?;
+        let jwk_ids = request_jwk_ids(&payload);
+        verify_payload(state, &payload, &jwk_ids)
+            .await
+            .map(VerifyResult::from)
+            .map_err(into_graphql_error)
+    }
+
+    /// All JWKs currently cached by the verifier, like `GET /jwks`.
+    async fn jwks(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Jwk>> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let mut jwks: Vec<Jwk> = state
+            .jwks
+            .load()
+            .iter()
+            .map(|(id, jwk)| Jwk::from(JwkEntry::new(id, jwk)))
+            .collect();
+        jwks.sort_by(|a, b| (&a.iss, &a.kid).cmp(&(&b.iss, &b.kid)));
+        Ok(jwks)
+    }
+}
+
+/// A signature to verify. The fields are as for `POST /verify`.
+#[derive(InputObject)]
+pub struct VerifyInput {
+    /// The Base64 encoded zkLogin signature.
+    signature: String,
+    /// The Base64 encoded bytes of payload.
+    bytes: String,
+    /// "TransactionData" or "PersonalMessage".
+    intent_scope: String,
+    /// The author of the intent, required for PersonalMessage.
+    author: Option<String>,
+    /// The network to verify the signature against. Defaults to Mainnet.
+    network: Option<String>,
+    /// The current epoch to verify against, fetched from `network` if not
+    /// set.
+    curr_epoch: Option<EpochId>,
+    /// Verify against the gas owner instead of the sender.
+    sponsor: Option<bool>,
+}
+
+impl VerifyInput {
+    fn into_request(self) -> async_graphql::Result<VerifyRequest> {
+        Ok(VerifyRequest {
+            signature: self.signature,
+            bytes: self.bytes,
+            intent_scope: deserialize_intent_scope(serde_json::Value::String(self.intent_scope))?,
+            author: self
+                .author
+                .map(|author| SuiAddress::from_str(&author))
+                .transpose()?,
+            network: self
+                .network
+                .map(|network| SuiEnv::from_str(&network))
+                .transpose()?,
+            curr_epoch: self.curr_epoch,
+            sponsor: self.sponsor,
+            format: None,
+            encoding: None,
+            policy: None,
+            jwk: None,
+            historical_epoch: None,
+            intent: None,
+        })
+    }
+}
+
+/// The result of verifying a signature, as returned by `POST /verify`.
+#[derive(SimpleObject)]
+pub struct VerifyResult {
+    is_verified: bool,
+    address: Option<String>,
+    scheme: Option<String>,
+    iss: Option<String>,
+    provider: Option<String>,
+    kid: Option<String>,
+    max_epoch: Option<EpochId>,
+    curr_epoch: Option<EpochId>,
+    epochs_remaining: Option<u64>,
+    tx_digest: Option<String>,
+}
+
+impl From<VerifyResponse> for VerifyResult {
+    fn from(response: VerifyResponse) -> Self {
+        Self {
+            is_verified: response.is_verified,
+            address: response.address.map(|address| address.to_string()),
+            scheme: response.scheme,
+            iss: response.iss,
+            provider: response.provider,
+            kid: response.kid,
+            max_epoch: response.max_epoch,
+            curr_epoch: response.curr_epoch,
+            epochs_remaining: response.epochs_remaining,
+            tx_digest: response.tx_digest.map(|digest| digest.to_string()),
+        }
+    }
+}
+
+/// A JWK, as returned by `GET /jwks`.
+#[derive(SimpleObject)]
+pub struct Jwk {
+    iss: String,
+    kid: String,
+    kty: String,
+    alg: String,
+    n: String,
+    e: String,
+}
+
+impl From<JwkEntry> for Jwk {
+    fn from(entry: JwkEntry) -> Self {
+        Self {
+            iss: entry.iss,
+            kid: entry.kid,
+            kty: entry.kty,
+            alg: entry.alg,
+            n: entry.n,
+            e: entry.e,
+        }
+    }
+}
+
+/// Converts the error to a GraphQL error with the message of the REST API
+/// and its code as the `code` extension.
+fn into_graphql_error(error: VerifyError) -> async_graphql::Error {
+    let code = error.code();
+    let (_, message) = error.status_and_message();
+    async_graphql::Error::new(message).extend_with(|_, e| {
+        e.set(
+            "code",
+            serde_json::to_value(code)
+                .ok()
+                .and_then(|code| code.as_str().map(str::to_string))
+                .unwrap_or_default(),
+        )
+    })
+}
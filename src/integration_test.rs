@@ -0,0 +1,739 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! End-to-end tests of the HTTP server against a mock OIDC provider and a
+//! mock fullnode, covering JWK fetching and the epoch fetch of `/verify`.
+
+use crate::{
+    access_log::AccessLogEntry,
+    attestation::{signed_message, X_VERDICT_SIGNATURE},
+    config::{AccessLogConfig, Config, TenantConfig, WebhookConfig, WebhookFormat},
+    deadline::X_REQUEST_DEADLINE,
+    epoch::{spawn_epoch_trackers, EpochResponse, EpochStatus},
+    jwks::refresh_provider_jwks,
+    router,
+    self_check::{test_jwk, TEST_BYTES, TEST_SIGNATURE},
+    server::unix_secs,
+    tenant::X_API_KEY,
+    AppState, ErrorCode, SuiEnv, VerifyResponse,
+};
+use axum::{
+    extract::State,
+    routing::{get, post},
+    Json, Router,
+};
+use fastcrypto::{
+    ed25519::{Ed25519PublicKey, Ed25519Signature},
+    encoding::{Base64, Encoding},
+    traits::{ToFromBytes, VerifyingKey},
+};
+use fastcrypto_zkp::bn254::zk_login::OIDCProvider;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    net::{SocketAddr, TcpListener},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use sui_types::digests::TransactionDigest;
+
+/// Serves the router on a free local port and returns its URL.
+fn serve(app: Router) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>()),
+    );
+    url
+}
+
+/// Serves the test JWK as the JWKS document of Twitch.
+fn mock_oidc_provider() -> String {
+    let (jwk_id, jwk) = test_jwk();
+    let jwks = json!({
+        "keys": [{
+            "kty": jwk.kty,
+            "e": jwk.e,
+            "n": jwk.n,
+            "alg": jwk.alg,
+            "kid": jwk_id.kid,
+            "use": "sig",
+        }]
+    });
+    serve(Router::new().route("/jwks", get(move || async move { Json(jwks) })))
+}
+
+/// The epoch of a mock fullnode failing to return its system state.
+const FULLNODE_DOWN: u64 = u64::MAX;
+
+/// Serves the JSON-RPC methods of a fullnode used by the verifier, with the
+/// current epoch read from `epoch`, or failing if it is [FULLNODE_DOWN].
+fn mock_fullnode(epoch: Arc<AtomicU64>) -> String {
+    async fn rpc(State(epoch): State<Arc<AtomicU64>>, Json(request): Json<Value>) -> Json<Value> {
+        let mut response = match request["method"].as_str() {
+            // Called by SuiClientBuilder to check the API of the fullnode.
+            Some("rpc.discover") => json!({
+                "result": {
+                    "info": { "version": "1.12.0" },
+                    "methods": [{ "name": "suix_getLatestSuiSystemState" }],
+                }
+            }),
+            Some("suix_getLatestSuiSystemState") => match epoch.load(Ordering::SeqCst) {
+                FULLNODE_DOWN => json!({
+                    "error": { "code": -32000, "message": "Fullnode is down" }
+                }),
+                epoch => json!({ "result": system_state(epoch) }),
+            },
+            method => json!({
+                "error": { "code": -32601, "message": format!("Method not found: {:?}", method) }
+            }),
+        };
+        response["jsonrpc"] = "2.0".into();
+        response["id"] = request["id"].clone();
+        Json(response)
+    }
+    serve(Router::new().route("/", post(rpc)).with_state(epoch))
+}
+
+/// A SuiSystemStateSummary at the epoch, as returned by fullnodes.
+fn system_state(epoch: u64) -> Value {
+    let id = format!("0x{}", "0".repeat(64));
+    json!({
+        "epoch": epoch.to_string(),
+        "protocolVersion": "1",
+        "systemStateVersion": "1",
+        "storageFundTotalObjectStorageRebates": "0",
+        "storageFundNonRefundableBalance": "0",
+        "referenceGasPrice": "1000",
+        "safeMode": false,
+        "safeModeStorageRewards": "0",
+        "safeModeComputationRewards": "0",
+        "safeModeStorageRebates": "0",
+        "safeModeNonRefundableStorageFee": "0",
+        "epochStartTimestampMs": "0",
+        "epochDurationMs": "86400000",
+        "stakeSubsidyStartEpoch": "0",
+        "maxValidatorCount": "150",
+        "minValidatorJoiningStake": "0",
+        "validatorLowStakeThreshold": "0",
+        "validatorVeryLowStakeThreshold": "0",
+        "validatorLowStakeGracePeriod": "7",
+        "stakeSubsidyBalance": "0",
+        "stakeSubsidyDistributionCounter": "0",
+        "stakeSubsidyCurrentDistributionAmount": "0",
+        "stakeSubsidyPeriodLength": "10",
+        "stakeSubsidyDecreaseRate": 1000,
+        "totalStake": "0",
+        "activeValidators": [],
+        "pendingActiveValidatorsId": id,
+        "pendingActiveValidatorsSize": "0",
+        "pendingRemovals": [],
+        "stakingPoolMappingsId": id,
+        "stakingPoolMappingsSize": "0",
+        "inactivePoolsId": id,
+        "inactivePoolsSize": "0",
+        "validatorCandidatesId": id,
+        "validatorCandidatesSize": "0",
+        "atRiskValidators": [],
+        "validatorReportRecords": [],
+    })
+}
+
+/// Starts the verifier for Twitch on Devnet, with the JWKs fetched from the
+/// mock provider and the epoch from the fullnodes, and the other settings of
+/// `config`, and returns its URL.
+async fn start_verifier(fullnode_urls: Vec<String>, config: Config) -> String {
+    let config = Config {
+        providers: vec!["Twitch".to_string()],
+        jwk_endpoints: HashMap::from([(
+            "Twitch".to_string(),
+            format!("{}/jwks", mock_oidc_provider()),
+        )]),
+        fullnode_urls: HashMap::from([(SuiEnv::Devnet, fullnode_urls)]),
+        fullnode_timeout_secs: 2,
+        ..config
+    };
+    let state = Arc::new(AppState::new(config));
+    refresh_provider_jwks(&state, &OIDCProvider::Twitch)
+        .await
+        .unwrap();
+    serve(router(state))
+}
+
+/// Posts a verify request for the test signature without a current epoch,
+/// so that the verifier fetches it.
+async fn post_verify(url: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/verify", url))
+        .json(&json!({
+            "signature": TEST_SIGNATURE,
+            "bytes": TEST_BYTES,
+            "intent_scope": 0,
+            "network": "Devnet",
+        }))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_verify_with_fetched_epoch() {
+    let epoch = Arc::new(AtomicU64::new(5));
+    let url = start_verifier(vec![mock_fullnode(epoch.clone())], Config::default()).await;
+
+    let res = reqwest::get(format!("{}/ready", url)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = post_verify(&url).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let res: VerifyResponse = res.json().await.unwrap();
+    assert!(res.is_verified);
+    assert_eq!(res.curr_epoch, Some(5));
+    assert_eq!(res.epochs_remaining, Some(5));
+
+    // The test signature has max epoch 10.
+    epoch.store(11, Ordering::SeqCst);
+    let res = post_verify(&url).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "SIGNATURE_EXPIRED");
+}
+
+#[tokio::test]
+async fn test_verify_epoch_tolerance() {
+    // The test signature has max epoch 10.
+    let epoch = Arc::new(AtomicU64::new(11));
+    let config = Config {
+        epoch_tolerance: 1,
+        ..Default::default()
+    };
+    let url = start_verifier(vec![mock_fullnode(epoch.clone())], config).await;
+
+    // Verified at the max epoch, a tolerated epoch before the fetched one.
+    let res = post_verify(&url).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let res: VerifyResponse = res.json().await.unwrap();
+    assert!(res.is_verified);
+    assert_eq!(res.curr_epoch, Some(10));
+    assert_eq!(res.chain_epoch, Some(11));
+
+    epoch.store(12, Ordering::SeqCst);
+    let res = post_verify(&url).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "SIGNATURE_EXPIRED");
+}
+
+#[tokio::test]
+async fn test_epoch_tracker() {
+    let epoch = Arc::new(AtomicU64::new(5));
+    let config = Config {
+        fullnode_urls: HashMap::from([(SuiEnv::Devnet, vec![mock_fullnode(epoch.clone())])]),
+        track_epoch_networks: Some(vec![SuiEnv::Devnet]),
+        ..Default::default()
+    };
+    let state = Arc::new(AppState::new(config));
+    let trackers = spawn_epoch_trackers(&state);
+    for _ in 0..50 {
+        if state.epochs.lock().contains_key(&SuiEnv::Devnet) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(state.epochs.lock()[&SuiEnv::Devnet].epoch, 5);
+    let fetch_status = state.epoch_fetch_status.lock()[&SuiEnv::Devnet].clone();
+    assert!(fetch_status.last_success.is_some());
+    assert_eq!(fetch_status.consecutive_failures, 0);
+
+    // Requests use the tracked epoch instead of fetching it.
+    epoch.store(6, Ordering::SeqCst);
+    assert_eq!(state.get_epoch(SuiEnv::Devnet).await.unwrap(), 5);
+    trackers.iter().for_each(|tracker| tracker.abort());
+}
+
+#[tokio::test]
+async fn test_epoch_tracker_failing_fullnode() {
+    let epoch = Arc::new(AtomicU64::new(FULLNODE_DOWN));
+    let config = Config {
+        fullnode_urls: HashMap::from([(SuiEnv::Devnet, vec![mock_fullnode(epoch.clone())])]),
+        track_epoch_networks: Some(vec![SuiEnv::Devnet]),
+        ..Default::default()
+    };
+    let state = Arc::new(AppState::new(config));
+    // Tracked before the fullnode went down, and the epoch change is overdue.
+    let fetched_at = SystemTime::now() - Duration::from_secs(600);
+    state.epochs.lock().insert(
+        SuiEnv::Devnet,
+        EpochStatus {
+            epoch: 5,
+            fetched_at,
+            next_epoch_at: fetched_at + Duration::from_secs(300),
+        },
+    );
+    let trackers = spawn_epoch_trackers(&state);
+    for _ in 0..50 {
+        if state
+            .epoch_fetch_status
+            .lock()
+            .contains_key(&SuiEnv::Devnet)
+        {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let fetch_status = state.epoch_fetch_status.lock()[&SuiEnv::Devnet].clone();
+    assert_eq!(fetch_status.consecutive_failures, 1);
+    // With the error of the fullnode, not only that the fetch failed.
+    assert!(fetch_status
+        .last_error
+        .unwrap()
+        .contains("Fullnode is down"));
+
+    // Requests no longer use the stale epoch, but fail to fetch it.
+    let e = state.get_epoch(SuiEnv::Devnet).await.unwrap_err();
+    assert_eq!(e.code(), ErrorCode::EpochFetchFailed);
+    assert!(!state.epochs.lock().contains_key(&SuiEnv::Devnet));
+
+    epoch.store(6, Ordering::SeqCst);
+    assert_eq!(state.get_epoch(SuiEnv::Devnet).await.unwrap(), 6);
+
+    // A current epoch is not used either after too many failed fetches.
+    let fetched_at = SystemTime::now();
+    state.epochs.lock().insert(
+        SuiEnv::Devnet,
+        EpochStatus {
+            epoch: 5,
+            fetched_at,
+            next_epoch_at: fetched_at + Duration::from_secs(300),
+        },
+    );
+    assert_eq!(state.get_epoch(SuiEnv::Devnet).await.unwrap(), 5);
+    state
+        .epoch_fetch_status
+        .lock()
+        .get_mut(&SuiEnv::Devnet)
+        .unwrap()
+        .consecutive_failures = 4;
+    assert_eq!(state.get_epoch(SuiEnv::Devnet).await.unwrap(), 6);
+    trackers.iter().for_each(|tracker| tracker.abort());
+}
+
+#[tokio::test]
+async fn test_get_epoch() {
+    let config = Config {
+        fullnode_urls: HashMap::from([(
+            SuiEnv::Devnet,
+            vec![mock_fullnode(Arc::new(AtomicU64::new(5)))],
+        )]),
+        ..Default::default()
+    };
+    let state = Arc::new(AppState::new(config));
+    let url = serve(router(state.clone()));
+    let get_epoch = || async {
+        let res = reqwest::get(format!("{}/epoch?network=Devnet", url))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        res.json::<EpochResponse>().await.unwrap()
+    };
+
+    // Untracked networks are fetched as verify requests fetch them.
+    let response = get_epoch().await;
+    assert_eq!(response.epoch, 5);
+    assert!(!response.tracked);
+
+    let fetched_at = SystemTime::now();
+    state.epochs.lock().insert(
+        SuiEnv::Devnet,
+        EpochStatus {
+            epoch: 4,
+            fetched_at,
+            next_epoch_at: fetched_at + Duration::from_secs(60),
+        },
+    );
+    let response = get_epoch().await;
+    assert_eq!(response.epoch, 4);
+    assert_eq!(response.fetched_at, unix_secs(fetched_at));
+    assert!(response.tracked);
+}
+
+#[tokio::test]
+async fn test_verify_fullnode_failover() {
+    // Nothing listens on the first fullnode.
+    let unreachable = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    };
+    let fullnode = mock_fullnode(Arc::new(AtomicU64::new(5)));
+    let url = start_verifier(vec![unreachable.clone(), fullnode], Config::default()).await;
+    let res = post_verify(&url).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.json::<VerifyResponse>().await.unwrap().is_verified);
+
+    let url = start_verifier(vec![unreachable], Config::default()).await;
+    let res = post_verify(&url).await;
+    assert_eq!(res.status(), StatusCode::BAD_GATEWAY);
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "EPOCH_FETCH_FAILED");
+}
+
+#[tokio::test]
+async fn test_verify_digest_fetch_failed() {
+    // Nothing listens on the fullnode.
+    let unreachable = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    };
+    let url = start_verifier(vec![unreachable], Config::default()).await;
+    let res = reqwest::Client::new()
+        .post(format!("{}/verify_digest", url))
+        .json(&json!({
+            "digest": TransactionDigest::new([0; 32]),
+            "network": "Devnet",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_GATEWAY);
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "TRANSACTION_FETCH_FAILED");
+}
+
+#[tokio::test]
+async fn test_attestation_signature() {
+    let state = Arc::new(AppState::new(Config {
+        providers: vec![],
+        attestation: true,
+        ..Default::default()
+    }));
+    state.update_jwks(|jwks| {
+        let (jwk_id, jwk) = test_jwk();
+        jwks.insert(jwk_id, jwk);
+    });
+    let public_key = state.attestation.as_ref().unwrap().public_key();
+    let url = serve(router(state));
+
+    let request = serde_json::to_vec(&json!({
+        "signature": TEST_SIGNATURE,
+        "bytes": TEST_BYTES,
+        "intent_scope": 0,
+        "network": "Devnet",
+        "curr_epoch": 1,
+    }))
+    .unwrap();
+    let res = reqwest::Client::new()
+        .post(format!("{}/verify", url))
+        .header("content-type", "application/json")
+        .body(request.clone())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let signature = res.headers()[X_VERDICT_SIGNATURE]
+        .to_str()
+        .unwrap()
+        .to_string();
+    let body = res.bytes().await.unwrap();
+    let public_key = Ed25519PublicKey::from_bytes(&Base64::decode(&public_key).unwrap()).unwrap();
+    let signature = Ed25519Signature::from_bytes(&Base64::decode(&signature).unwrap()).unwrap();
+    assert!(public_key
+        .verify(&signed_message(&request, &body), &signature)
+        .is_ok());
+    // The verdict does not hold for another request.
+    let other_request = request.strip_suffix(b"}").unwrap();
+    assert!(public_key
+        .verify(&signed_message(other_request, &body), &signature)
+        .is_err());
+
+    // Outside of an enclave, there is no document to return.
+    let res = reqwest::get(format!("{}/attestation", url)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "ATTESTATION_FAILED");
+}
+
+#[tokio::test]
+async fn test_compression() {
+    let get_jwks = |url: String| async move {
+        reqwest::Client::new()
+            .get(format!("{}/jwks", url))
+            .header("accept-encoding", "gzip")
+            .send()
+            .await
+            .unwrap()
+    };
+    let url = start_verifier(vec![], Config::default()).await;
+    let res = get_jwks(url).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers().get("content-encoding").is_none());
+
+    let mut config = Config::default();
+    config.http.compression = true;
+    let url = start_verifier(vec![], config).await;
+    let res = get_jwks(url).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-encoding"], "gzip");
+}
+
+#[tokio::test]
+async fn test_verify_stream() {
+    let url = start_verifier(vec![], Config::default()).await;
+    let request = json!({
+        "signature": TEST_SIGNATURE,
+        "bytes": TEST_BYTES,
+        "intent_scope": 0,
+        "network": "Devnet",
+        "curr_epoch": 1,
+    });
+    let mut expired = request.clone();
+    expired["curr_epoch"] = json!(11);
+    let body = [
+        json!({"id": "a", "request": request}).to_string(),
+        String::new(),
+        "not json".to_string(),
+        json!({"id": 2, "request": expired}).to_string(),
+    ]
+    .join("\r\n");
+    let res = reqwest::Client::new()
+        .post(format!("{}/verify_stream", url))
+        .header("content-type", "application/x-ndjson")
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "application/x-ndjson");
+    let text = res.text().await.unwrap();
+    let results: HashMap<String, Value> = text
+        .lines()
+        .map(|line| {
+            let result: Value = serde_json::from_str(line).unwrap();
+            (result["id"].to_string(), result["result"].clone())
+        })
+        .collect();
+    assert_eq!(results.len(), 3);
+    assert_eq!(results["\"a\""]["is_verified"], true);
+    assert_eq!(results["null"]["code"], "INVALID_REQUEST");
+    // The test signature has max epoch 10.
+    assert_eq!(results["2"]["code"], "SIGNATURE_EXPIRED");
+}
+
+#[tokio::test]
+async fn test_verify_transaction_dry_run_failed() {
+    // Nothing listens on the fullnode.
+    let unreachable = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    };
+    let url = start_verifier(vec![unreachable], Config::default()).await;
+    let post = |signatures: Vec<&str>| {
+        reqwest::Client::new()
+            .post(format!("{}/verify_transaction", url))
+            .json(&json!({
+                "bytes": TEST_BYTES,
+                "signatures": signatures,
+                "network": "Devnet",
+                "curr_epoch": 1,
+                "dry_run": true,
+            }))
+            .send()
+    };
+
+    let res = post(vec![TEST_SIGNATURE]).await.unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_GATEWAY);
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "DRY_RUN_FAILED");
+
+    // A transaction failing verification is not dry run.
+    let res = post(vec![TEST_SIGNATURE, TEST_SIGNATURE]).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["is_verified"], false);
+    assert!(body.get("dry_run").is_none());
+}
+
+#[tokio::test]
+async fn test_request_deadline() {
+    let fullnode = mock_fullnode(Arc::new(AtomicU64::new(5)));
+    let url = start_verifier(vec![fullnode], Config::default()).await;
+    let post = |deadline: &str| {
+        reqwest::Client::new()
+            .post(format!("{}/verify", url))
+            .header(X_REQUEST_DEADLINE, deadline)
+            .json(&json!({
+                "signature": TEST_SIGNATURE,
+                "bytes": TEST_BYTES,
+                "intent_scope": 0,
+                "network": "Devnet",
+            }))
+            .send()
+    };
+
+    // The caller has already given up.
+    let res = post("1000").await.unwrap();
+    assert_eq!(res.status(), StatusCode::GATEWAY_TIMEOUT);
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "DEADLINE_EXCEEDED");
+
+    let deadline = SystemTime::now() + Duration::from_secs(10);
+    let millis = deadline.duration_since(UNIX_EPOCH).unwrap().as_millis();
+    let res = post(&millis.to_string()).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = post("tomorrow").await.unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_tenants() {
+    let tenant = |name: &str, allowed_issuers: Option<Vec<String>>| TenantConfig {
+        name: name.to_string(),
+        api_key: format!("{}-key", name),
+        allowed_issuers,
+        rate_limit: None,
+        default_network: Some(SuiEnv::Devnet),
+    };
+    let fullnode = mock_fullnode(Arc::new(AtomicU64::new(5)));
+    let config = Config {
+        tenants: vec![
+            tenant("twitch", None),
+            tenant(
+                "google",
+                Some(vec!["https://accounts.google.com".to_string()]),
+            ),
+        ],
+        ..Default::default()
+    };
+    let url = start_verifier(vec![fullnode], config).await;
+    // The network is left to the default of the tenant.
+    let post = |api_key: Option<&str>| {
+        let mut request = reqwest::Client::new()
+            .post(format!("{}/verify", url))
+            .json(&json!({
+                "signature": TEST_SIGNATURE,
+                "bytes": TEST_BYTES,
+                "intent_scope": 0,
+            }));
+        if let Some(api_key) = api_key {
+            request = request.header(X_API_KEY, api_key);
+        }
+        request.send()
+    };
+
+    for api_key in [None, Some("unknown")] {
+        let res = post(api_key).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    let res = post(Some("twitch-key")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.json::<VerifyResponse>().await.unwrap().is_verified);
+
+    let res = post(Some("google-key")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "ISSUER_NOT_ALLOWED");
+
+    // Unauthenticated routes don't need an API key.
+    let res = reqwest::get(format!("{}/jwks", url)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_webhooks() {
+    let (sender, mut events) = tokio::sync::mpsc::unbounded_channel::<Value>();
+    let webhook = serve(Router::new().route(
+        "/hook",
+        post(move |Json(event): Json<Value>| async move {
+            sender.send(event).unwrap();
+        }),
+    ));
+    let config = Config {
+        providers: vec!["Twitch".to_string()],
+        // Nothing listens on port 1, so fetches fail.
+        jwk_endpoints: HashMap::from([("Twitch".to_string(), "http://127.0.0.1:1".to_string())]),
+        webhooks: vec![WebhookConfig {
+            url: format!("{}/hook", webhook),
+            format: WebhookFormat::Generic,
+            failure_threshold: 2,
+        }],
+        ..Default::default()
+    };
+    let state = Arc::new(AppState::new(config.clone()));
+    let next_event = || tokio::time::timeout(Duration::from_secs(5), events.recv());
+
+    for _ in 0..3 {
+        assert!(refresh_provider_jwks(&state, &OIDCProvider::Twitch)
+            .await
+            .is_err());
+    }
+    // Only the failure reaching the threshold is sent.
+    let event = next_event().await.unwrap().unwrap();
+    assert_eq!(event["event"], "fetch_failed");
+    assert_eq!(event["provider"], "Twitch");
+    assert_eq!(event["consecutive_failures"], 2);
+
+    state.config.store(Config {
+        jwk_endpoints: HashMap::from([(
+            "Twitch".to_string(),
+            format!("{}/jwks", mock_oidc_provider()),
+        )]),
+        ..config
+    });
+    refresh_provider_jwks(&state, &OIDCProvider::Twitch)
+        .await
+        .unwrap();
+    let event = next_event().await.unwrap().unwrap();
+    assert_eq!(event["event"], "fetch_recovered");
+    assert_eq!(event["consecutive_failures"], 3);
+
+    // The first keys of a provider are not a rotation.
+    assert!(next_event().await.is_err());
+}
+
+#[tokio::test]
+async fn test_access_log() {
+    let path = std::env::temp_dir().join(format!(
+        "zklogin-verifier-access-{:x}.log",
+        rand::random::<u64>()
+    ));
+    let fullnode = mock_fullnode(Arc::new(AtomicU64::new(5)));
+    let config = Config {
+        access_log: Some(AccessLogConfig {
+            path: Some(path.clone()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let url = start_verifier(vec![fullnode], config).await;
+    let res = reqwest::Client::new()
+        .get(format!("{}/health", url))
+        .header("x-request-id", "access-log-test")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // Entries are written in the background.
+    let mut contents = String::new();
+    for _ in 0..50 {
+        contents = std::fs::read_to_string(&path).unwrap_or_default();
+        if !contents.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    std::fs::remove_file(&path).unwrap();
+    let entry: AccessLogEntry = serde_json::from_str(contents.trim_end()).unwrap();
+    assert_eq!(entry.method, "GET");
+    assert_eq!(entry.path, "/health");
+    assert_eq!(entry.status, 200);
+    assert_eq!(entry.client_ip, Some("127.0.0.1".parse().unwrap()));
+    assert_eq!(entry.request_id.as_deref(), Some("access-log-test"));
+}
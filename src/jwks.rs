@@ -0,0 +1,435 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Fetching of JWKs from the OIDC providers into the [AppState] cache, both on
+//! a schedule and on demand when a signature refers to an unknown kid.
+
+use crate::{
+    webhook::{notify, WebhookEvent},
+    AppState, JwkSource,
+};
+use fastcrypto::error::FastCryptoError;
+use fastcrypto_zkp::bn254::zk_login::{parse_jwks, JwkId, OIDCProvider, JWK};
+use rand::Rng;
+use reqwest::{
+    header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    StatusCode,
+};
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::task::{AbortHandle, JoinSet};
+use tracing::{info, warn};
+
+/// Minimum time between two on-demand fetches for the same provider, so that
+/// signatures with made up kids cannot make the verifier hammer a provider.
+const ON_DEMAND_FETCH_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// Delay before retrying after the first failed fetch of a provider.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Upper bound of the delay between retries of a failing provider.
+const MAX_BACKOFF: Duration = Duration::from_secs(600);
+
+/// Periodically fetches the JWKs of all configured providers. Each provider
+/// is fetched by its own task, so that a failing provider does not delay the
+/// others. The tasks follow changes of the providers on config reloads.
+/// Aborting this task stops all of them.
+pub async fn run_jwk_updater(state: Arc<AppState>) {
+    info!("Starting JWK updater task");
+    let mut tasks = JoinSet::new();
+    let mut updaters: HashMap<OIDCProvider, AbortHandle> = HashMap::new();
+    loop {
+        let providers = state.providers.load();
+        updaters.retain(|p, task| {
+            let enabled = providers.contains(p);
+            if !enabled {
+                info!("Stopping JWK updates of provider {:?}", p);
+                task.abort();
+            }
+            enabled
+        });
+        for p in providers.iter() {
+            if !updaters.contains_key(p) {
+                let task = tasks.spawn(run_provider_updater(state.clone(), p.clone()));
+                updaters.insert(p.clone(), task);
+            }
+        }
+        tokio::select! {
+            _ = state.providers_changed.notified() => {}
+            // Updaters only end when aborted.
+            Some(_) = tasks.join_next() => {}
+        }
+    }
+}
+
+/// Fetches the JWKs of the provider every refresh interval, or right away
+/// when triggered, retrying with exponential backoff and jitter while
+/// fetching fails.
+async fn run_provider_updater(state: Arc<AppState>, provider: OIDCProvider) {
+    let trigger = state.refresh_trigger(&provider);
+    let mut failures = 0;
+    loop {
+        let delay = match refresh_provider_jwks(&state, &provider).await {
+            Ok(()) => {
+                failures = 0;
+                state.config.load().jwk_refresh_interval(&provider)
+            }
+            Err(e) => {
+                failures += 1;
+                let delay = with_jitter(backoff_delay(failures));
+                warn!(
+                    "Error when fetching JWK with provider {:?} {:?}, retrying in {:?}",
+                    provider, e, delay
+                );
+                delay
+            }
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = trigger.notified() => info!("Refresh of provider {:?} triggered", provider),
+        }
+    }
+}
+
+/// Returns the delay before retrying after the given number of consecutive
+/// failures, doubling from [INITIAL_BACKOFF] up to [MAX_BACKOFF].
+pub(crate) fn backoff_delay(failures: u32) -> Duration {
+    INITIAL_BACKOFF
+        .saturating_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+        .min(MAX_BACKOFF)
+}
+
+/// Randomizes the delay to between half and all of it, so that instances
+/// don't retry a recovering provider in lockstep.
+fn with_jitter(delay: Duration) -> Duration {
+    delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+}
+
+/// The outcome of the latest JWK fetches of a provider.
+#[derive(Clone, Debug, Default)]
+pub struct FetchStatus {
+    /// When the JWKs of the provider were last fetched successfully.
+    pub last_success: Option<SystemTime>,
+    /// The error of the latest fetch, if it failed.
+    pub last_error: Option<String>,
+    /// The number of fetches that failed since the last successful one.
+    pub consecutive_failures: u32,
+    /// The JWKs returned by the latest modified response.
+    pub keys: Vec<(JwkId, JWK)>,
+    /// The ETag of the latest modified response, sent as If-None-Match.
+    pub etag: Option<String>,
+    /// The Last-Modified of the latest modified response, sent as
+    /// If-Modified-Since.
+    pub last_modified: Option<String>,
+}
+
+/// Where a cached JWK came from and when, as listed by `GET /jwks`.
+#[derive(Clone, Debug)]
+pub struct JwkMetadata {
+    /// Where the JWK was last confirmed from.
+    pub source: JwkSource,
+    /// When the JWK was cached, or last replaced with new key material.
+    pub first_seen: SystemTime,
+    /// When the source last returned the JWK.
+    pub last_confirmed: SystemTime,
+    /// `last_confirmed` as an [Instant], to prune JWKs their provider no
+    /// longer returns.
+    pub last_seen: Instant,
+}
+
+impl JwkMetadata {
+    /// Metadata of a JWK first returned by `source` at `now`.
+    pub fn new(source: JwkSource, now: Instant) -> Self {
+        Self {
+            source,
+            first_seen: SystemTime::now(),
+            last_confirmed: SystemTime::now(),
+            last_seen: now,
+        }
+    }
+
+    /// Records that `source` returned the JWK again at `now`.
+    pub fn confirm(&mut self, source: JwkSource, now: Instant) {
+        self.source = source;
+        self.last_confirmed = SystemTime::now();
+        self.last_seen = now;
+    }
+}
+
+/// The response to a conditional fetch of a provider's JWKs.
+enum FetchedJwks {
+    /// The JWKs have not changed since the previous fetch.
+    NotModified,
+    /// The JWKs, along with the validators to send on the next fetch.
+    Modified {
+        keys: Vec<(JwkId, JWK)>,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    },
+}
+
+/// Fetches the JWKs of the provider and merges them into the cache. The
+/// response is skipped if the provider reports the JWKs as not modified.
+pub async fn refresh_provider_jwks(
+    state: &AppState,
+    provider: &OIDCProvider,
+) -> Result<(), FastCryptoError> {
+    let (etag, last_modified) = state
+        .fetch_status
+        .lock()
+        .get(provider)
+        .map(|s| (s.etag.clone(), s.last_modified.clone()))
+        .unwrap_or_default();
+    let result = fetch_jwks_if_modified(
+        provider,
+        &state.config.load().jwk_endpoint(provider),
+        &state.http_client,
+        etag,
+        last_modified,
+    )
+    .await;
+
+    let name = provider.to_string();
+    let mut fetch_status = state.fetch_status.lock();
+    let status = fetch_status.entry(provider.clone()).or_default();
+    let iss = provider.get_config().iss;
+    match result {
+        Ok(fetched) => {
+            let first_success = status.last_success.is_none();
+            status.last_success = Some(SystemTime::now());
+            status.last_error = None;
+            if status.consecutive_failures > 0 {
+                notify(
+                    state,
+                    WebhookEvent::FetchRecovered {
+                        provider: name.clone(),
+                        iss: iss.clone(),
+                        consecutive_failures: status.consecutive_failures,
+                    },
+                );
+                status.consecutive_failures = 0;
+            }
+            let outcome = match fetched {
+                FetchedJwks::NotModified => "not_modified",
+                FetchedJwks::Modified {
+                    keys,
+                    etag,
+                    last_modified,
+                } => {
+                    status.keys = keys;
+                    status.etag = etag;
+                    status.last_modified = last_modified;
+                    "modified"
+                }
+            };
+            state
+                .metrics
+                .jwk_fetches
+                .with_label_values(&[&name, outcome])
+                .inc();
+            // The keys are applied even if not modified, to keep them from
+            // being pruned.
+            let kids_before = cached_kids(state, &iss);
+            if apply_provider_jwks(state, &iss, status.keys.clone(), Instant::now()) {
+                state
+                    .metrics
+                    .jwk_generation
+                    .with_label_values(&[&name])
+                    .inc();
+                // The first keys of a provider are not a rotation.
+                if !first_success {
+                    let kids = cached_kids(state, &iss);
+                    notify(
+                        state,
+                        WebhookEvent::KeysChanged {
+                            provider: name,
+                            iss,
+                            added: kids.difference(&kids_before).cloned().collect(),
+                            removed: kids_before.difference(&kids).cloned().collect(),
+                            kids: kids.into_iter().collect(),
+                        },
+                    );
+                }
+            }
+            Ok(())
+        }
+        Err(e) => {
+            status.last_error = Some(e.to_string());
+            status.consecutive_failures += 1;
+            state
+                .metrics
+                .jwk_fetches
+                .with_label_values(&[&name, "error"])
+                .inc();
+            notify(
+                state,
+                WebhookEvent::FetchFailed {
+                    provider: name,
+                    iss,
+                    consecutive_failures: status.consecutive_failures,
+                    error: e.to_string(),
+                },
+            );
+            Err(e)
+        }
+    }
+}
+
+/// Fetches the JWKs of the provider, sending the validators of the previous
+/// response so that the provider can answer 304 Not Modified.
+async fn fetch_jwks_if_modified(
+    provider: &OIDCProvider,
+    endpoint: &str,
+    client: &reqwest::Client,
+    etag: Option<String>,
+    last_modified: Option<String>,
+) -> Result<FetchedJwks, FastCryptoError> {
+    let mut request = client.get(endpoint);
+    if let Some(etag) = etag {
+        request = request.header(IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = last_modified {
+        request = request.header(IF_MODIFIED_SINCE, last_modified);
+    }
+    let response = request.send().await.map_err(|e| {
+        FastCryptoError::GeneralError(format!(
+            "Failed to get JWK {:?} {:?}",
+            e.to_string(),
+            provider
+        ))
+    })?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(FetchedJwks::NotModified);
+    }
+
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string())
+    };
+    let etag = header(ETAG);
+    let last_modified = header(LAST_MODIFIED);
+    let bytes = response.bytes().await.map_err(|e| {
+        FastCryptoError::GeneralError(format!(
+            "Failed to get bytes {:?} {:?}",
+            e.to_string(),
+            provider
+        ))
+    })?;
+    Ok(FetchedJwks::Modified {
+        keys: parse_jwks(&bytes, provider)?,
+        etag,
+        last_modified,
+    })
+}
+
+/// Returns the kids of the cached JWKs of the provider with `iss`.
+fn cached_kids(state: &AppState, iss: &str) -> BTreeSet<String> {
+    state
+        .jwks
+        .load()
+        .keys()
+        .filter(|id| id.iss == iss)
+        .map(|id| id.kid.clone())
+        .collect()
+}
+
+/// Merges the JWKs just fetched from the provider with `iss` into the cache.
+/// New kids are added and a kid returned with different key material is
+/// replaced. Keys of the provider that have not been returned for longer than
+/// the prune grace period are removed. Returns whether the cache changed.
+pub(crate) fn apply_provider_jwks(
+    state: &AppState,
+    iss: &str,
+    keys: Vec<(JwkId, JWK)>,
+    now: Instant,
+) -> bool {
+    let grace_period = Duration::from_secs(state.config.load().jwk_prune_grace_period_secs);
+    state.update_jwks(|oauth_provider_jwk| {
+        let mut changed = false;
+        let mut metadata = state.jwk_metadata.lock();
+        for (jwk_id, jwk) in keys {
+            match oauth_provider_jwk.get(&jwk_id) {
+                Some(existing) if existing == &jwk => {
+                    metadata
+                        .entry(jwk_id)
+                        .and_modify(|m| m.confirm(JwkSource::Provider, now))
+                        .or_insert_with(|| JwkMetadata::new(JwkSource::Provider, now));
+                }
+                Some(_) => {
+                    warn!(
+                        "{:?} JWK replaced with new key material: {:?}",
+                        &jwk_id, jwk
+                    );
+                    oauth_provider_jwk.insert(jwk_id.clone(), jwk);
+                    metadata.insert(jwk_id, JwkMetadata::new(JwkSource::Provider, now));
+                    changed = true;
+                }
+                None => {
+                    info!("{:?} JWK updated: {:?}", &jwk_id, jwk);
+                    oauth_provider_jwk.insert(jwk_id.clone(), jwk);
+                    metadata.insert(jwk_id, JwkMetadata::new(JwkSource::Provider, now));
+                    changed = true;
+                }
+            }
+        }
+
+        oauth_provider_jwk.retain(|jwk_id, _| {
+            if jwk_id.iss != iss {
+                return true;
+            }
+            // Keys cached by other means count as seen the first time around.
+            let seen = metadata
+                .entry(jwk_id.clone())
+                .or_insert_with(|| JwkMetadata::new(JwkSource::Admin, now))
+                .last_seen;
+            if now.saturating_duration_since(seen) <= grace_period {
+                return true;
+            }
+            info!("{:?} JWK pruned, last seen {:?} ago", jwk_id, now - seen);
+            metadata.remove(jwk_id);
+            changed = true;
+            false
+        });
+        changed
+    })
+}
+
+/// Fetches the JWKs of the configured providers of any of the ids missing
+/// from the cache, so that keys rotated in since the last scheduled refresh
+/// are picked up. Each provider is fetched at most once per cooldown.
+pub async fn fetch_missing_jwks(state: &AppState, ids: &[JwkId]) {
+    let providers: Vec<OIDCProvider> = {
+        let jwks = state.jwks.load();
+        state
+            .providers
+            .load()
+            .iter()
+            .filter(|p| {
+                let iss = p.get_config().iss;
+                ids.iter().any(|id| id.iss == iss && !jwks.contains_key(id))
+            })
+            .cloned()
+            .collect()
+    };
+
+    for p in providers {
+        {
+            let mut last_fetches = state.on_demand_fetches.lock();
+            let now = Instant::now();
+            match last_fetches.get(&p) {
+                Some(last) if now.duration_since(*last) < ON_DEMAND_FETCH_COOLDOWN => continue,
+                _ => last_fetches.insert(p.clone(), now),
+            };
+        }
+        info!("Fetching JWKs of provider {:?} for an unknown kid", p);
+        if let Err(e) = refresh_provider_jwks(state, &p).await {
+            warn!("Error when fetching JWK with provider {:?} {:?}", p, e);
+        }
+    }
+}
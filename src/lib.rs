@@ -1,63 +1,193 @@
 // Copyright (c) Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use axum::response::{IntoResponse, Response};
-use axum::{extract::State, Json};
-use fastcrypto::encoding::{Base64, Encoding};
+use fastcrypto::error::FastCryptoError;
 use fastcrypto_zkp::bn254::{
-    zk_login::{JwkId, JWK},
+    zk_login::{JwkId, OIDCProvider, JWK},
     zk_login_api::ZkLoginEnv,
 };
-use im::hashmap::HashMap as ImHashMap;
-use parking_lot::RwLock;
-use reqwest::StatusCode;
-use serde::{Deserialize, Serialize};
-use serde_json::json;
-use shared_crypto::intent::IntentVersion;
-use shared_crypto::intent::{AppId, Intent, IntentMessage, IntentScope, PersonalMessage};
-use std::{collections::HashMap, sync::Arc};
-use sui_sdk::SuiClientBuilder;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize};
+use shared_crypto::intent::{Intent, IntentScope};
+use std::{str::FromStr, time::Duration};
 use sui_types::committee::EpochId;
 use sui_types::{
-    base_types::SuiAddress,
-    crypto::ToFromBytes,
-    signature::{AuthenticatorTrait, GenericSignature, VerifyParams},
-    transaction::TransactionData,
+    base_types::SuiAddress, digests::TransactionDigest, error::SuiError,
+    signature::GenericSignature,
 };
-use tracing::info;
 
-#[cfg(test)]
+pub mod verifier;
+pub use verifier::{
+    debug_zklogin_signatures, derive_zklogin_addresses, parse_zklogin_signature,
+    preflight_zklogin_signature, validate_request, verify_ephemeral_signature,
+    verify_generic_request, verify_generic_signature, verify_personal_message, verify_request,
+    verify_request_ephemeral, verify_transaction_data, verify_transaction_signatures,
+    verify_zklogin_signature, zklogin_jwk_ids, VerifyOptions, VerifyPolicy, VerifyPolicyOverride,
+    MAX_BYTES_LEN, MAX_SIGNATURE_LEN,
+};
+
+pub mod self_check;
+
+#[cfg(feature = "server")]
+pub mod access_log;
+#[cfg(feature = "server")]
+pub mod attestation;
+#[cfg(feature = "server")]
+pub mod cache;
+#[cfg(feature = "server")]
+pub mod challenge;
+#[cfg(feature = "server")]
+pub mod concurrency;
+#[cfg(feature = "server")]
+pub mod config;
+#[cfg(feature = "server")]
+pub mod deadline;
+#[cfg(feature = "server")]
+pub mod drift;
+#[cfg(feature = "server")]
+pub mod epoch;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+#[cfg(feature = "server")]
+pub mod jwks;
+#[cfg(feature = "server")]
+pub mod log_sampling;
+#[cfg(feature = "server")]
+pub mod metrics;
+#[cfg(feature = "server")]
+pub mod ndjson;
+#[cfg(feature = "server")]
+pub mod pool;
+#[cfg(feature = "nats")]
+pub mod queue;
+#[cfg(feature = "server")]
+pub mod rate_limit;
+#[cfg(feature = "server")]
+pub mod reload;
+#[cfg(feature = "server")]
+pub mod request_id;
+#[cfg(feature = "server")]
+mod server;
+#[cfg(feature = "server")]
+pub mod slo;
+#[cfg(feature = "server")]
+pub mod tenant;
+#[cfg(feature = "server")]
+pub mod test_vector;
+#[cfg(feature = "server")]
+pub use server::*;
+#[cfg(feature = "server")]
+pub mod webhook;
+#[cfg(feature = "server")]
+pub mod ws;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(all(test, feature = "server"))]
 #[path = "test.rs"]
 pub mod test;
 
-/// Application state that contains the seed and JWKs.
-#[derive(Clone, Debug)]
-pub struct AppState {
-    /// This is the latest JWKs stored in a mapping from iss -> (kid -> JWK).
-    pub jwks: Arc<RwLock<HashMap<JwkId, JWK>>>,
-}
+#[cfg(all(test, feature = "server"))]
+mod integration_test;
 
 /// Request to get salt. It contains the JWT token.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VerifyRequest {
     /// The Base64 encoded zkLogin signature.
     pub signature: String,
     /// The Base64 encoded bytes of payload.
     pub bytes: String,
     /// The intent scope, can be either TransactionData or PersonalMessage.
-    /// This determines how the `bytes` is deserialized.
+    /// This determines how the `bytes` is deserialized. Accepted as the
+    /// number of the scope or its name.
+    #[serde(deserialize_with = "deserialize_intent_scope")]
     pub intent_scope: IntentScope,
     /// The author of the intent.
     pub author: Option<SuiAddress>,
     /// The network to verify the signature against. This determins the
-    /// ZkLoginEnv.
+    /// ZkLoginEnv. The server defaults to its `default_network`.
     pub network: Option<SuiEnv>,
     /// The current epoch to verify the signature against. If not provided,
     /// use `network` to fetch the current epoch.
     pub curr_epoch: Option<EpochId>,
+    /// For TransactionData, verify the signature against the gas owner
+    /// (sponsor) instead of the sender. Defaults to false.
+    pub sponsor: Option<bool>,
+    /// How `bytes` is encoded. Defaults to Base64 encoded BCS bytes.
+    pub format: Option<PayloadFormat>,
+    /// The text encoding of `signature`, and of `bytes` in the BCS format.
+    /// Defaults to Base64.
+    pub encoding: Option<BinaryEncoding>,
+    /// Overrides of the verification policy for this request. Fields that
+    /// are set can only disable what the policy of the verifier allows.
+    pub policy: Option<VerifyPolicyOverride>,
+    /// The JWK to verify against instead of the JWKs held by the verifier,
+    /// e.g. a synthetic test key. The server only accepts it if
+    /// `allow_inline_jwks` is set.
+    pub jwk: Option<JwkEntry>,
+    /// A past epoch to verify the signature at, e.g. to re-verify old
+    /// transactions. The server checks it against the current epoch of
+    /// `network`. Cannot be combined with `curr_epoch`.
+    pub historical_epoch: Option<EpochId>,
+    /// The full intent the signature is over, for expert use, e.g. by
+    /// protocols with their own app ID. Its scope must be `intent_scope`.
+    /// Defaults to `intent_scope` with version V0 and app ID Sui.
+    pub intent: Option<Intent>,
+}
+
+/// Deserializes an [IntentScope] from its number or, case-insensitively, the
+/// name of a scope supported by the verifier.
+fn deserialize_intent_scope<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<IntentScope, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Scope {
+        Number(u8),
+        Name(String),
+    }
+    match Scope::deserialize(deserializer)? {
+        Scope::Number(n) => IntentScope::try_from(n)
+            .map_err(|_| D::Error::custom(format!("Unknown intent scope: {}", n))),
+        Scope::Name(name) => match name.to_lowercase().as_str() {
+            "transactiondata" => Ok(IntentScope::TransactionData),
+            "personalmessage" => Ok(IntentScope::PersonalMessage),
+            _ => Err(D::Error::custom(format!(
+                "Unsupported intent scope: {}, expected TransactionData or PersonalMessage",
+                name
+            ))),
+        },
+    }
+}
+
+/// The format of the `bytes` payload in [VerifyRequest].
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PayloadFormat {
+    /// Base64 encoded BCS bytes.
+    #[default]
+    Bcs,
+    /// The JSON representation of TransactionData, which the server encodes
+    /// to BCS. Only valid for TransactionData.
+    Json,
+}
+
+/// The text encoding of binary fields in [VerifyRequest].
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum BinaryEncoding {
+    /// Standard Base64, as emitted by the TS SDK.
+    #[default]
+    Base64,
+    /// URL-safe Base64, with or without padding.
+    Base64Url,
+    /// Hex, with or without a `0x` prefix.
+    Hex,
 }
 
-#[derive(Default, Debug, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum SuiEnv {
     #[default]
     Mainnet,
@@ -66,8 +196,23 @@ pub enum SuiEnv {
     Localnet,
 }
 
+impl FromStr for SuiEnv {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mainnet" => Ok(SuiEnv::Mainnet),
+            "testnet" => Ok(SuiEnv::Testnet),
+            "devnet" => Ok(SuiEnv::Devnet),
+            "localnet" => Ok(SuiEnv::Localnet),
+            _ => Err(format!("Unknown network: {}", s)),
+        }
+    }
+}
+
 impl SuiEnv {
-    fn get_params(&self) -> (&str, ZkLoginEnv) {
+    /// Returns the fullnode url and the ZkLoginEnv for the network.
+    pub fn get_params(&self) -> (&str, ZkLoginEnv) {
         match self {
             SuiEnv::Mainnet => ("https://fullnode.mainnet.sui.io:443", ZkLoginEnv::Prod),
             SuiEnv::Testnet => ("https://fullnode.testnet.sui.io:443", ZkLoginEnv::Prod),
@@ -77,121 +222,852 @@ impl SuiEnv {
     }
 }
 
-/// Response to get salt.
-#[derive(Debug, Serialize)]
+/// Response to verify.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VerifyResponse {
-    /// The salt value represented as a BigInt
+    /// Whether the signature is verified.
     pub is_verified: bool,
+    /// The address the signature was verified against. For a zkLogin
+    /// signature this is the derived zkLogin address.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<SuiAddress>,
+    /// The scheme of the signature, e.g. "ZkLoginAuthenticator", "MultiSig"
+    /// or "ED25519".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scheme: Option<String>,
+    /// The iss of the zkLogin signature.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    /// The name of the provider of `iss`, e.g. "Google", if it is a known
+    /// provider.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// The kid of the JWK used to verify the zkLogin signature.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+    /// The epoch after which the zkLogin signature expires.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_epoch: Option<EpochId>,
+    /// The epoch the signature was verified at.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub curr_epoch: Option<EpochId>,
+    /// The number of epochs after `curr_epoch` the zkLogin signature keeps
+    /// verifying, 0 if it expires at the end of the current epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub epochs_remaining: Option<u64>,
+    /// The current epoch of the chain, when verifying at a historical epoch
+    /// or at another epoch within the epoch tolerance.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chain_epoch: Option<EpochId>,
+    /// The digest of the transaction, for TransactionData, as returned by
+    /// fullnodes and shown by explorers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tx_digest: Option<TransactionDigest>,
+    /// The inputs of the verification that are older than the configured
+    /// staleness thresholds, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stale_data: Option<StaleData>,
 }
 
-/// Error enum for get salt response.
-#[derive(Debug, PartialEq)]
-pub enum VerifyError {
-    /// The Groth16 proof failed to verify.
-    GenericError(String),
-    /// Fail to parse payload.
-    ParsingError,
-    /// Error when getting epoch from sui client.
-    GetEpochError,
-}
-
-impl IntoResponse for VerifyError {
-    fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            VerifyError::GenericError(e) => (StatusCode::BAD_REQUEST, e),
-            VerifyError::ParsingError => (StatusCode::BAD_REQUEST, "Parsing error".to_string()),
-            VerifyError::GetEpochError => (StatusCode::BAD_REQUEST, "Cannot get epoch".to_string()),
+/// A warning that a verification used inputs older than the configured
+/// thresholds, so that strict consumers can retry or reject the result.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StaleData {
+    /// Seconds since the JWKs of the provider of the signature were last
+    /// fetched successfully, if older than `stale_jwks_after_secs`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jwks_age_secs: Option<u64>,
+    /// Seconds since the tracked current epoch was fetched, if older than
+    /// `stale_epoch_after_secs`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub epoch_age_secs: Option<u64>,
+}
+
+impl VerifyResponse {
+    /// Response for a signature verified against the given author at the
+    /// given epoch, with the zkLogin details filled in if it is a zkLogin
+    /// signature.
+    pub(crate) fn verified(
+        sig: &GenericSignature,
+        author: SuiAddress,
+        curr_epoch: EpochId,
+    ) -> Self {
+        let (iss, kid, max_epoch) = match sig {
+            GenericSignature::ZkLoginAuthenticator(zk) => (
+                Some(zk.get_iss().to_string()),
+                Some(zk.inputs.get_kid().to_string()),
+                Some(zk.get_max_epoch()),
+            ),
+            _ => (None, None, None),
         };
-        let body = Json(json!({
-            "error": error_message,
-        }));
-        (status, body).into_response()
+        let provider = iss
+            .as_deref()
+            .and_then(|iss| OIDCProvider::from_iss(iss).ok())
+            .map(|provider| provider.to_string());
+        Self {
+            is_verified: true,
+            address: Some(author),
+            scheme: Some(verifier::signature_scheme(sig)),
+            iss,
+            provider,
+            kid,
+            max_epoch,
+            curr_epoch: Some(curr_epoch),
+            epochs_remaining: max_epoch.map(|max_epoch| max_epoch.saturating_sub(curr_epoch)),
+            chain_epoch: None,
+            tx_digest: None,
+            stale_data: None,
+        }
     }
 }
 
-pub async fn verify(
-    State(state): State<Arc<AppState>>,
-    Json(payload): Json<VerifyRequest>,
-) -> Result<Json<VerifyResponse>, VerifyError> {
-    info!("verify called");
-
-    let network = payload.network.unwrap_or_default();
-    let (url, env) = network.get_params();
-
-    // Use payload.curr_epoch if provided, otherwise fetch current epoch
-    // from payload.network (default to Mainnet if not provided).
-    let curr_epoch = match payload.curr_epoch {
-        Some(curr_epoch) => curr_epoch,
-        None => {
-            let sui_client = SuiClientBuilder::default()
-                .build(url)
-                .await
-                .map_err(|_| VerifyError::GetEpochError)?;
-
-            sui_client
-                .governance_api()
-                .get_latest_sui_system_state()
-                .await
-                .map_err(|_| VerifyError::GetEpochError)?
-                .epoch
-        }
-    };
-    info!("curr_epoch: {:?}", curr_epoch);
-
-    let parsed: ImHashMap<JwkId, JWK> = state.jwks.read().clone().into_iter().collect();
-    let aux_verify_data = VerifyParams::new(parsed, vec![], env, true, true);
-    info!("aux_verify_data: {:?}", aux_verify_data);
-
-    match GenericSignature::from_bytes(
-        &Base64::decode(&payload.signature).map_err(|_| VerifyError::ParsingError)?,
-    )
-    .map_err(|_| VerifyError::ParsingError)?
-    {
-        GenericSignature::ZkLoginAuthenticator(zk) => {
-            let bytes = Base64::decode(&payload.bytes).map_err(|_| VerifyError::ParsingError)?;
-            match payload.intent_scope {
-                IntentScope::TransactionData => {
-                    let tx_data: TransactionData =
-                        bcs::from_bytes(&bytes).map_err(|_| VerifyError::ParsingError)?;
-                    let intent_msg = IntentMessage::new(Intent::sui_transaction(), tx_data.clone());
-                    let author = tx_data.execution_parts().1;
-                    match zk.verify_authenticator(
-                        &intent_msg,
-                        author,
-                        Some(curr_epoch),
-                        &aux_verify_data,
-                    ) {
-                        Ok(_) => Ok(Json(VerifyResponse { is_verified: true })),
-                        Err(e) => Err(VerifyError::GenericError(e.to_string())),
-                    }
+/// Request to parse a zkLogin signature without verifying it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ParseSignatureRequest {
+    /// The Base64 encoded zkLogin signature.
+    pub signature: String,
+}
+
+/// The components of a zkLogin signature.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct ParseSignatureResponse {
+    /// The iss of the JWT the proof was generated for.
+    pub iss: String,
+    /// The kid from the JWT header, identifies the JWK to verify against.
+    pub kid: String,
+    /// The epoch after which the signature expires.
+    pub max_epoch: EpochId,
+    /// The Base64 encoded ephemeral public key.
+    pub ephemeral_pubkey: String,
+    /// The signature scheme of the ephemeral key.
+    pub ephemeral_scheme: String,
+    /// The address seed, represented as a BigInt.
+    pub address_seed: String,
+}
+
+/// The public inputs of a zkLogin signature and the JWK selected to verify
+/// it.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct ZkLoginDebugInfo {
+    /// The components of the signature, as returned by `/parse_signature`.
+    #[serde(flatten)]
+    pub signature: ParseSignatureResponse,
+    /// The JWT header, decoded from `header_base64`.
+    pub header: serde_json::Value,
+    /// The Base64URL encoded JWT header.
+    pub header_base64: String,
+    /// The Base64URL encoded part of the JWT payload holding the iss claim.
+    pub iss_base64_details: IssClaimDetails,
+    /// The JWK the proof is verified against, none if there is no JWK for
+    /// the iss and kid.
+    pub jwk: Option<JWK>,
+}
+
+/// The part of the JWT payload holding the iss claim, as committed to by the
+/// proof.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct IssClaimDetails {
+    /// The Base64URL encoded claim.
+    pub value: String,
+    /// The offset of the claim in the Base64URL encoding of the payload,
+    /// modulo 4.
+    pub index_mod_4: u8,
+}
+
+/// Request to derive the addresses of the signer of a zkLogin signature.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeriveAddressRequest {
+    /// The zkLogin signature.
+    pub signature: String,
+    /// The text encoding of `signature`. Defaults to Base64.
+    pub encoding: Option<BinaryEncoding>,
+}
+
+/// The addresses of the signer of a zkLogin signature.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct DeriveAddressResponse {
+    /// The address derived from the address seed without leading zero
+    /// bytes, which all signatures verify for.
+    pub address: SuiAddress,
+    /// The legacy address derived from the address seed padded to 32 bytes.
+    /// Equal to `address` unless the address seed has leading zero bytes.
+    pub legacy_address: SuiAddress,
+    /// Whether signatures also verify for `legacy_address` under the policy
+    /// of the verifier.
+    pub legacy_address_accepted: bool,
+}
+
+/// Request to check a zkLogin signature before submitting it, without
+/// verifying its proof.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PreflightRequest {
+    /// The zkLogin signature, or a multisig with zkLogin participants.
+    pub signature: String,
+    /// The text encoding of `signature`. Defaults to Base64.
+    pub encoding: Option<BinaryEncoding>,
+    /// The network whose current epoch is checked against. Defaults to the
+    /// default network of the verifier.
+    pub network: Option<SuiEnv>,
+    /// The epoch to check against instead of the current epoch.
+    pub curr_epoch: Option<EpochId>,
+}
+
+/// Response of a preflight check.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct PreflightResponse {
+    /// Whether all zkLogin signatures pass the checks, so that verifying
+    /// them can only fail on the proof or the signature over the bytes.
+    pub ready: bool,
+    /// The epoch the signatures were checked against.
+    pub curr_epoch: EpochId,
+    /// The checks of the zkLogin signatures, standalone or as multisig
+    /// participants.
+    pub signatures: Vec<PreflightSignature>,
+}
+
+/// The preflight checks of one zkLogin signature.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct PreflightSignature {
+    /// The iss of the signature.
+    pub iss: String,
+    /// The kid of the JWK the signature refers to.
+    pub kid: String,
+    /// The epoch after which the signature expires.
+    pub max_epoch: EpochId,
+    /// Whether the verifier holds the JWK for the iss and kid.
+    pub jwk_known: bool,
+    /// Whether the signature has not expired at the current epoch, and its
+    /// max epoch is not further ahead than the policy allows.
+    pub epoch_in_range: bool,
+    /// The error verifying would fail with, if a check failed.
+    pub error: Option<String>,
+    /// The code of `error`.
+    pub code: Option<ErrorCode>,
+}
+
+/// Request to issue a challenge proving ownership of a zkLogin address.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChallengeRequest {
+    /// The address whose ownership is to be proven.
+    pub address: SuiAddress,
+    /// The domain of the integrator asking for the proof, e.g.
+    /// "exchange.example", included in the challenge so that users see who
+    /// they prove ownership to.
+    pub domain: Option<String>,
+}
+
+/// An ownership challenge, to be signed as a personal message by the
+/// address.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChallengeResponse {
+    /// The message to sign, exactly as returned.
+    pub challenge: String,
+    /// The random nonce of the challenge, hex encoded.
+    pub nonce: String,
+    /// When the challenge expires, in Unix seconds.
+    pub expires_at: u64,
+}
+
+/// Request to verify a signature over an ownership challenge.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifyChallengeRequest {
+    /// The challenge, exactly as returned by `/challenge`.
+    pub challenge: String,
+    /// The Base64 encoded zkLogin signature over the challenge as a personal
+    /// message.
+    pub signature: String,
+    /// The network to verify the signature against, at its current epoch.
+    /// Defaults to the default network of the verifier.
+    pub network: Option<SuiEnv>,
+}
+
+/// Response to verifying an ownership challenge.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifyChallengeResponse {
+    /// The address proven to be owned by the signer.
+    pub address: SuiAddress,
+    /// The domain of the challenge, if any.
+    pub domain: Option<String>,
+    /// The nonce of the challenge, to check against the nonce issued.
+    pub nonce: String,
+    /// When the challenge expires, in Unix seconds.
+    pub expires_at: u64,
+    /// The result of verifying the signature.
+    pub result: VerifyResponse,
+}
+
+/// Request to verify many signatures at once.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifyBatchRequest {
+    /// The requests to verify, each as for a single verify.
+    pub requests: Vec<VerifyRequest>,
+}
+
+/// Response to a batch verify, with a result per request in the order of the
+/// batch.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifyBatchResponse {
+    pub results: Vec<BatchResult>,
+}
+
+/// Response of `/verify_debug`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifyDebugResponse {
+    /// The result of verifying the request as `/verify` does.
+    pub result: BatchResult,
+    /// The network the request was verified on.
+    pub network: SuiEnv,
+    /// The verifying key the proofs are checked with, "Prod" or "Test".
+    pub env: String,
+    /// The zkLogin signatures of the request, standalone or as multisig
+    /// participants. Empty if the signature cannot be decoded.
+    pub signatures: Vec<ZkLoginDebugInfo>,
+}
+
+/// The result of one request of a batch: the response to a single verify, or
+/// the error it failed with.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum BatchResult {
+    Verified(VerifyResponse),
+    Failed { error: String, code: ErrorCode },
+}
+
+#[cfg(feature = "server")]
+impl From<Result<VerifyResponse, VerifyError>> for BatchResult {
+    fn from(result: Result<VerifyResponse, VerifyError>) -> Self {
+        match result {
+            Ok(response) => BatchResult::Verified(response),
+            Err(e) => {
+                let code = e.code();
+                BatchResult::Failed {
+                    error: e.status_and_message().1,
+                    code,
                 }
-                IntentScope::PersonalMessage => {
-                    let tx_data = PersonalMessage { message: bytes };
-                    let intent_msg = IntentMessage::new(
-                        Intent {
-                            scope: IntentScope::PersonalMessage,
-                            version: IntentVersion::V0,
-                            app_id: AppId::Sui,
+            }
+        }
+    }
+}
+
+/// Request to verify all signatures on a transaction.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifyTransactionRequest {
+    /// The BCS bytes of the TransactionData.
+    pub bytes: String,
+    /// The signatures on the transaction, of any scheme.
+    pub signatures: Vec<String>,
+    /// The network to verify the signatures against. Defaults to the default
+    /// network of the verifier.
+    pub network: Option<SuiEnv>,
+    /// The current epoch to verify the signatures against. If not provided,
+    /// use `network` to fetch the current epoch.
+    pub curr_epoch: Option<EpochId>,
+    /// The text encoding of `bytes` and `signatures`. Defaults to Base64.
+    pub encoding: Option<BinaryEncoding>,
+    /// Whether to also dry run the transaction on a fullnode of `network`
+    /// when its signatures are verified. Defaults to false.
+    pub dry_run: Option<bool>,
+}
+
+/// Request to verify the signatures of a transaction on chain, fetched by
+/// its digest.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifyDigestRequest {
+    /// The digest of the transaction.
+    pub digest: TransactionDigest,
+    /// The network the transaction was executed on. Defaults to the default
+    /// network of the verifier.
+    pub network: Option<SuiEnv>,
+    /// The epoch to verify the signatures at, instead of the epoch the
+    /// transaction was executed in.
+    pub curr_epoch: Option<EpochId>,
+}
+
+/// Response to verifying the signatures of a transaction fetched by its
+/// digest.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct VerifyDigestResponse {
+    /// The epoch the transaction was executed in.
+    pub executed_epoch: EpochId,
+    /// The epoch the signatures were verified at.
+    pub curr_epoch: EpochId,
+    /// The verdicts on the signatures of the transaction.
+    #[serde(flatten)]
+    pub transaction: VerifyTransactionResponse,
+}
+
+/// Response to verifying all signatures on a transaction.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct VerifyTransactionResponse {
+    /// Whether validators would accept the signatures: each is verified, and
+    /// there is exactly one for the sender and one for the gas owner if it
+    /// differs from the sender.
+    pub is_verified: bool,
+    /// The digest of the transaction.
+    pub tx_digest: TransactionDigest,
+    /// The verdict on each signature, in the order of the request.
+    pub signatures: Vec<SignatureVerdict>,
+    /// The outcome of the dry run, if one was requested and the signatures
+    /// are verified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<DryRunResult>,
+}
+
+/// The outcome of dry running a transaction on a fullnode.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct DryRunResult {
+    /// Whether executing the transaction would succeed.
+    pub success: bool,
+    /// The error executing the transaction would fail with, if any.
+    pub error: Option<String>,
+}
+
+/// The verdict on one signature of a transaction.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct SignatureVerdict {
+    /// The signature scheme, e.g. ED25519, ZkLoginAuthenticator or MultiSig.
+    pub scheme: String,
+    /// Whether the signature is verified.
+    pub is_verified: bool,
+    /// The required signer the signature is verified for.
+    pub signer: Option<SuiAddress>,
+    /// Why the signature failed to verify.
+    pub error: Option<String>,
+}
+
+/// Response to verifying only the ephemeral signature of a zkLogin signature.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct EphemeralSignatureResponse {
+    /// Whether the ephemeral signature is verified.
+    pub is_verified: bool,
+    /// The Base64 encoded ephemeral public key.
+    pub ephemeral_pubkey: String,
+    /// The signature scheme of the ephemeral key.
+    pub ephemeral_scheme: String,
+}
+
+/// A JWK held by the verifier, along with the (iss, kid) it is keyed by.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct JwkEntry {
+    /// The iss string that identifies the OIDC provider.
+    pub iss: String,
+    /// The kid string that identifies the JWK.
+    pub kid: String,
+    /// Key type parameter.
+    pub kty: String,
+    /// Algorithm parameter.
+    pub alg: String,
+    /// RSA modulus.
+    pub n: String,
+    /// RSA public exponent.
+    pub e: String,
+    /// Where the verifier got the JWK from, as listed by `GET /jwks`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<JwkSource>,
+    /// When the verifier cached the JWK, in Unix seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_seen: Option<u64>,
+    /// When the source of the JWK last returned it, in Unix seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_confirmed: Option<u64>,
+}
+
+/// Where the verifier got a JWK from.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum JwkSource {
+    /// Fetched from the JWK endpoint of its provider.
+    Provider,
+    /// Inserted with `POST /admin/jwks`.
+    Admin,
+}
+
+impl JwkEntry {
+    /// Creates an entry for the JWK keyed by the given id.
+    pub fn new(id: &JwkId, jwk: &JWK) -> Self {
+        Self {
+            iss: id.iss.clone(),
+            kid: id.kid.clone(),
+            kty: jwk.kty.clone(),
+            alg: jwk.alg.clone(),
+            n: jwk.n.clone(),
+            e: jwk.e.clone(),
+            source: None,
+            first_seen: None,
+            last_confirmed: None,
+        }
+    }
+
+    /// Splits the entry into the JWK and the id it is keyed by.
+    pub fn into_parts(self) -> (JwkId, JWK) {
+        (
+            JwkId::new(self.iss, self.kid),
+            JWK {
+                kty: self.kty,
+                e: self.e,
+                n: self.n,
+                alg: self.alg,
+            },
+        )
+    }
+}
+
+/// Response to get JWKs.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JwksResponse {
+    /// All JWKs currently cached, sorted by (iss, kid).
+    pub jwks: Vec<JwkEntry>,
+}
+
+/// Request to insert JWKs into the cache, either a JWKS document as served by
+/// a provider along with its iss, or a single JWK with its iss and kid.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum AdminJwksRequest {
+    /// A standard JWKS document, e.g. `{"iss": "...", "keys": [...]}`.
+    Jwks { iss: String, keys: Vec<JwksKey> },
+    /// A single JWK along with the (iss, kid) it is keyed by.
+    Single(JwkEntry),
+}
+
+impl AdminJwksRequest {
+    /// Returns the JWKs in the request keyed by their (iss, kid).
+    pub fn into_jwks(self) -> Vec<(JwkId, JWK)> {
+        match self {
+            AdminJwksRequest::Jwks { iss, keys } => keys
+                .into_iter()
+                .map(|key| {
+                    (
+                        JwkId::new(iss.clone(), key.kid),
+                        JWK {
+                            kty: key.kty,
+                            e: key.e,
+                            n: key.n,
+                            alg: key.alg,
                         },
-                        tx_data,
-                    );
-                    let author = match payload.author {
-                        Some(author) => author,
-                        None => return Err(VerifyError::ParsingError),
-                    };
-                    match zk.verify_authenticator(
-                        &intent_msg,
-                        author,
-                        Some(curr_epoch),
-                        &aux_verify_data,
-                    ) {
-                        Ok(_) => Ok(Json(VerifyResponse { is_verified: true })),
-                        Err(e) => Err(VerifyError::GenericError(e.to_string())),
-                    }
-                }
-                _ => Err(VerifyError::ParsingError),
+                    )
+                })
+                .collect(),
+            AdminJwksRequest::Single(entry) => vec![entry.into_parts()],
+        }
+    }
+}
+
+/// A key of a JWKS document.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JwksKey {
+    /// The kid string that identifies the JWK.
+    pub kid: String,
+    /// Key type parameter.
+    pub kty: String,
+    /// RSA public exponent.
+    pub e: String,
+    /// RSA modulus.
+    pub n: String,
+    /// Algorithm parameter, RS256 if absent.
+    #[serde(default = "default_alg")]
+    pub alg: String,
+}
+
+fn default_alg() -> String {
+    "RS256".to_string()
+}
+
+/// Response to inserting JWKs.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminJwksResponse {
+    /// The number of JWKs inserted or replaced.
+    pub inserted: usize,
+}
+
+/// Response to a config reload.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminReloadResponse {
+    /// The names of the settings that changed.
+    pub changed: Vec<String>,
+}
+
+/// Request to refresh the JWKs of a provider right away.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminRefreshRequest {
+    /// The name of the provider, e.g. "Google".
+    pub provider: String,
+}
+
+/// Error of verifying a signature. The fastcrypto, sui-types, bcs and
+/// fullnode errors it stems from are kept as its
+/// [source](std::error::Error::source).
+#[derive(Debug, thiserror::Error)]
+pub enum VerifyError {
+    /// The signature failed to verify, e.g. its ephemeral signature, or its
+    /// zkLogin inputs are invalid.
+    #[error("{0}")]
+    VerifyFailed(#[source] SuiError),
+    /// The Groth16 proof of a standalone zkLogin signature does not verify,
+    /// after its epoch, JWK, author and ephemeral signature passed.
+    #[error("{0}")]
+    ProofVerifyFailed(#[source] SuiError),
+    /// The named field is not valid in the encoding of the request.
+    #[error("Invalid encoding of {field}")]
+    InvalidEncoding {
+        field: &'static str,
+        #[source]
+        source: FastCryptoError,
+    },
+    /// The signature bytes are not a valid GenericSignature.
+    #[error("Invalid signature bytes")]
+    InvalidSignature(#[source] FastCryptoError),
+    /// The signature is neither a zkLogin signature nor a multisig with a
+    /// zkLogin participant. Holds the scheme found instead.
+    #[error("Not a zkLogin signature, found scheme {0}. Signatures of any scheme can be verified with /verify_generic")]
+    NotZkLoginSignature(String),
+    /// The bytes are not the BCS encoding of TransactionData.
+    #[error("Cannot BCS decode bytes as TransactionData")]
+    BcsDecodeError(#[source] bcs::Error),
+    /// The TransactionData parsed from JSON cannot be BCS encoded.
+    #[error("Cannot BCS encode TransactionData")]
+    BcsEncodeError(#[source] bcs::Error),
+    /// The bytes are not the JSON representation of TransactionData.
+    #[error("Cannot parse bytes as TransactionData JSON")]
+    InvalidTransactionJson(#[source] serde_json::Error),
+    /// The signature is by another address than the author of the intent,
+    /// as found before verifying it.
+    #[error("Signature is by {signer}, not by the author {author}")]
+    AuthorMismatch {
+        author: SuiAddress,
+        signer: SuiAddress,
+    },
+    /// The intent scope is not supported, or not with the payload format.
+    #[error("Unsupported intent scope {0} for the payload format")]
+    UnsupportedIntentScope(u8),
+    /// The scope of the custom intent is not the intent scope verified.
+    #[error("Intent has scope {intent}, not the intent scope {scope}")]
+    IntentScopeMismatch { intent: u8, scope: u8 },
+    /// The author is required for PersonalMessage but was not given.
+    #[error("Author is required for PersonalMessage")]
+    MissingAuthor,
+    /// The request carries its own JWK, but the server does not allow it.
+    #[error("Inline JWKs are not allowed by this verifier")]
+    InlineJwkNotAllowed,
+    /// The signature is from a provider the server does not accept. Holds
+    /// its iss.
+    #[error("Signatures from iss {0} are not accepted")]
+    IssuerNotAllowed(String),
+    /// The historical epoch is after the current epoch, or combined with
+    /// `curr_epoch`.
+    #[error("{0}")]
+    InvalidHistoricalEpoch(String),
+    /// The current epoch is past the max epoch of the signature.
+    #[error("Signature expired at the end of epoch {max_epoch}, current epoch is {curr_epoch}")]
+    SignatureExpired {
+        max_epoch: EpochId,
+        curr_epoch: EpochId,
+    },
+    /// The max epoch of the signature is further ahead of the current epoch
+    /// than the policy allows.
+    #[error("Max epoch {max_epoch} is more than {max_delta} epochs after the current epoch {curr_epoch}")]
+    MaxEpochTooFar {
+        max_epoch: EpochId,
+        curr_epoch: EpochId,
+        max_delta: u64,
+    },
+    /// The JWK of the signature is not known. Holds the kids known for the
+    /// iss, to tell a key not fetched yet from a wrong iss.
+    #[error("JWK not found for iss {iss} and kid {kid}, known kids for this iss: {known_kids:?}")]
+    JwkNotFound {
+        iss: String,
+        kid: String,
+        known_kids: Vec<String>,
+    },
+    /// The batch holds more requests than the server accepts.
+    #[error("Batch of {len} requests exceeds the maximum of {max}")]
+    BatchTooLarge { len: usize, max: usize },
+    /// The named field is longer than the verifier accepts, checked before
+    /// decoding it.
+    #[error("Field {field} of {len} characters exceeds the maximum of {max}")]
+    PayloadTooLarge {
+        field: &'static str,
+        len: usize,
+        max: usize,
+    },
+    /// Fields of the request are invalid, as found before verifying it.
+    /// Holds all problems found, at least one.
+    #[error("{}", join_field_errors(.0))]
+    InvalidFields(Vec<FieldError>),
+    /// The zkLogin address signing the request is over its rate limit.
+    #[error("Too many requests for address {address}")]
+    RateLimited {
+        address: SuiAddress,
+        retry_after: Duration,
+    },
+    /// The ownership challenge is malformed or not in canonical form.
+    #[error("Invalid challenge: {0}")]
+    InvalidChallenge(String),
+    /// The ownership challenge expired at the given Unix time.
+    #[error("Challenge expired at {expires_at}")]
+    ChallengeExpired { expires_at: u64 },
+    /// No fullnode returned the epoch. Holds the error of the last fullnode
+    /// tried, if any was.
+    #[error("Cannot get epoch")]
+    GetEpochError(#[source] Option<Box<dyn std::error::Error + Send + Sync>>),
+    /// No fullnode returned the transaction. Holds the error of the last
+    /// fullnode tried, if any was.
+    #[error("Cannot get transaction {digest}")]
+    GetTransactionError {
+        digest: TransactionDigest,
+        #[source]
+        source: Option<Box<dyn std::error::Error + Send + Sync>>,
+    },
+    /// No fullnode dry ran the transaction. Holds the error of the last
+    /// fullnode tried, if any was.
+    #[error("Cannot dry run transaction")]
+    DryRunError(#[source] Option<Box<dyn std::error::Error + Send + Sync>>),
+}
+
+/// Errors are equal if they are the same variant with the same message, as
+/// the errors they hold as sources are not all comparable.
+impl PartialEq for VerifyError {
+    fn eq(&self, other: &Self) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+            && self.to_string() == other.to_string()
+    }
+}
+
+fn join_field_errors(fields: &[FieldError]) -> String {
+    fields
+        .iter()
+        .map(|field| field.error.as_str())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// A problem with a field of a request.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldError {
+    /// The name of the field, e.g. "author".
+    pub field: String,
+    /// The code of the error verifying the request would fail with.
+    pub code: ErrorCode,
+    /// The description of the problem.
+    pub error: String,
+}
+
+/// Stable, machine-readable codes of the errors returned by the verifier,
+/// sent as the `code` field of error responses next to the message. Clients
+/// should match on these rather than on the messages, which may change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    /// The signature is not Base64 encoded GenericSignature bytes.
+    InvalidSignatureEncoding,
+    /// The signature is valid but not a zkLogin signature.
+    NotZkLoginSignature,
+    /// The bytes could not be decoded as the given format.
+    InvalidBytes,
+    /// The intent scope is not supported, or not with the payload format.
+    UnsupportedIntentScope,
+    /// The scope of the custom intent is not the intent scope verified.
+    IntentScopeMismatch,
+    /// The author is required for the intent scope but was not given.
+    MissingAuthor,
+    /// The request carries its own JWK, which this verifier does not allow.
+    InlineJwkNotAllowed,
+    /// The signature is from a provider this verifier does not accept.
+    IssuerNotAllowed,
+    /// The historical epoch is after the current epoch of the chain, or
+    /// combined with `curr_epoch`.
+    InvalidHistoricalEpoch,
+    /// The JWK the signature refers to by iss and kid is not known.
+    UnknownJwk,
+    /// The zkLogin proof does not verify against the JWK and the verifying
+    /// key of the network.
+    ProofVerifyFailed,
+    /// The current epoch is past the max epoch of the signature.
+    SignatureExpired,
+    /// The max epoch of the signature is further ahead of the current epoch
+    /// than the verification policy allows.
+    MaxEpochTooFar,
+    /// The signature is by another address than the author of the intent.
+    AuthorMismatch,
+    /// The signature failed to verify for another reason, e.g. a bad
+    /// ephemeral signature.
+    VerifyFailed,
+    /// The current epoch could not be fetched from a fullnode.
+    EpochFetchFailed,
+    /// The transaction could not be fetched from a fullnode, e.g. because
+    /// no transaction has the digest.
+    TransactionFetchFailed,
+    /// The transaction could not be dry run on a fullnode. A transaction
+    /// that would fail to execute is not an error, but reported in the
+    /// response.
+    DryRunFailed,
+    /// Attestation is not enabled, or the enclave failed to attest.
+    AttestationFailed,
+    /// The batch holds more requests than the verifier accepts.
+    BatchTooLarge,
+    /// The signature or bytes are longer than the verifier accepts.
+    PayloadTooLarge,
+    /// The request is malformed in a way no other code covers, e.g. a
+    /// message on the WebSocket or a line of a stream that is not a valid
+    /// request, or an invalid deadline header.
+    InvalidRequest,
+    /// The client, or the zkLogin address signing the request, is over the
+    /// rate limit.
+    RateLimited,
+    /// The verifier is handling as many requests as it accepts at a time,
+    /// and as many more are waiting.
+    Overloaded,
+    /// The request was not handled within the request timeout.
+    RequestTimeout,
+    /// The request was not handled by the deadline set by the caller.
+    DeadlineExceeded,
+    /// The admin token is missing or wrong.
+    Unauthorized,
+    /// The provider is not enabled on this verifier.
+    UnknownProvider,
+    /// The config could not be reloaded.
+    InvalidConfig,
+    /// The ownership challenge is malformed or not in canonical form.
+    InvalidChallenge,
+    /// The ownership challenge has expired.
+    ChallengeExpired,
+}
+
+impl VerifyError {
+    /// Returns the code of the error.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            VerifyError::JwkNotFound { .. } => ErrorCode::UnknownJwk,
+            VerifyError::SignatureExpired { .. } => ErrorCode::SignatureExpired,
+            VerifyError::MaxEpochTooFar { .. } => ErrorCode::MaxEpochTooFar,
+            VerifyError::ProofVerifyFailed(_) => ErrorCode::ProofVerifyFailed,
+            VerifyError::VerifyFailed(_) => ErrorCode::VerifyFailed,
+            VerifyError::InvalidEncoding {
+                field: "signature", ..
             }
+            | VerifyError::InvalidSignature(_) => ErrorCode::InvalidSignatureEncoding,
+            VerifyError::InvalidEncoding { .. }
+            | VerifyError::BcsDecodeError(_)
+            | VerifyError::BcsEncodeError(_)
+            | VerifyError::InvalidTransactionJson(_) => ErrorCode::InvalidBytes,
+            VerifyError::NotZkLoginSignature(_) => ErrorCode::NotZkLoginSignature,
+            VerifyError::AuthorMismatch { .. } => ErrorCode::AuthorMismatch,
+            VerifyError::UnsupportedIntentScope(_) => ErrorCode::UnsupportedIntentScope,
+            VerifyError::IntentScopeMismatch { .. } => ErrorCode::IntentScopeMismatch,
+            VerifyError::MissingAuthor => ErrorCode::MissingAuthor,
+            VerifyError::InlineJwkNotAllowed => ErrorCode::InlineJwkNotAllowed,
+            VerifyError::IssuerNotAllowed(_) => ErrorCode::IssuerNotAllowed,
+            VerifyError::InvalidHistoricalEpoch(_) => ErrorCode::InvalidHistoricalEpoch,
+            VerifyError::BatchTooLarge { .. } => ErrorCode::BatchTooLarge,
+            VerifyError::PayloadTooLarge { .. } => ErrorCode::PayloadTooLarge,
+            VerifyError::InvalidFields(fields) => fields
+                .first()
+                .map_or(ErrorCode::InvalidRequest, |field| field.code),
+            VerifyError::RateLimited { .. } => ErrorCode::RateLimited,
+            VerifyError::InvalidChallenge(_) => ErrorCode::InvalidChallenge,
+            VerifyError::ChallengeExpired { .. } => ErrorCode::ChallengeExpired,
+            VerifyError::GetEpochError(_) => ErrorCode::EpochFetchFailed,
+            VerifyError::GetTransactionError { .. } => ErrorCode::TransactionFetchFailed,
+            VerifyError::DryRunError(_) => ErrorCode::DryRunFailed,
         }
-        _ => Err(VerifyError::ParsingError),
     }
 }
@@ -0,0 +1,73 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Sampling of the logs of requests, so that busy deployments log a fraction
+//! of the successful requests but every failure.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::{span, Event, Level, Subscriber};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+/// The name of the span of a request, see [crate::request_id::request_id].
+const REQUEST_SPAN: &str = "request";
+
+/// The field of the event reporting that a request failed, e.g.
+/// `info!(failed = true, "verify failed: {}", e)`.
+const FAILED_FIELD: &str = "failed";
+
+/// Marks the span of a request whose info and debug logs are dropped.
+struct Unsampled;
+
+/// A layer keeping the info and debug logs of 1 in `every` requests.
+/// Warnings and errors are always kept, as are logs outside of requests. An
+/// event with a `failed` field is kept too, and so are the logs of its
+/// request after it, so that failed requests are logged whatever the
+/// sampling. Logs of the request before the failure may have been dropped.
+#[derive(Debug)]
+pub struct LogSampler {
+    every: u64,
+    requests: AtomicU64,
+}
+
+impl LogSampler {
+    /// Creates a sampler keeping the logs of 1 in `every` requests, or of all
+    /// if `every` is 0 or 1.
+    pub fn new(every: u64) -> Self {
+        Self {
+            every: every.max(1),
+            requests: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<S> Layer<S> for LogSampler
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        if self.every == 1 || attrs.metadata().name() != REQUEST_SPAN {
+            return;
+        }
+        if self.requests.fetch_add(1, Ordering::Relaxed) % self.every != 0 {
+            if let Some(span) = ctx.span(id) {
+                span.extensions_mut().insert(Unsampled);
+            }
+        }
+    }
+
+    fn event_enabled(&self, event: &Event<'_>, ctx: Context<'_, S>) -> bool {
+        if *event.metadata().level() <= Level::WARN {
+            return true;
+        }
+        let Some(mut scope) = ctx.event_scope(event) else {
+            return true;
+        };
+        if event.metadata().fields().field(FAILED_FIELD).is_some() {
+            for span in scope {
+                span.extensions_mut().remove::<Unsampled>();
+            }
+            return true;
+        }
+        !scope.any(|span| span.extensions().get::<Unsampled>().is_some())
+    }
+}
@@ -1,74 +1,538 @@
 // Copyright (c) Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use axum::{
-    routing::{get, post},
-    Router,
+use axum_server::{tls_rustls::RustlsConfig, Handle};
+use clap::{Args, Parser, Subcommand};
+use shared_crypto::intent::IntentScope;
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    path::PathBuf,
+    process::ExitCode,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
 };
-use fastcrypto_zkp::bn254::zk_login::{fetch_jwks, OIDCProvider};
-use std::{net::SocketAddr, sync::Arc, time::Duration};
+use sui_types::{base_types::SuiAddress, committee::EpochId};
 use tracing::{info, warn};
-use zklogin_verifier::{verify, AppState};
+use tracing_subscriber::{prelude::*, reload, EnvFilter};
+use zklogin_verifier::{
+    config::{Config, TlsConfig},
+    drift::spawn_drift_checkers,
+    epoch::spawn_epoch_trackers,
+    jwks::run_jwk_updater,
+    log_sampling::LogSampler,
+    reload::{reload_config, Reloader},
+    router,
+    self_check::self_check,
+    test_vector::{generate_test_vector, TestVectorParams, DEV_PROVER_URL},
+    verify_request, AppState, JwksResponse, SuiEnv, VerifyPolicy, VerifyRequest,
+};
+
+#[derive(Parser)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Arguments to the default `serve` command.
+    #[command(flatten)]
+    serve: ServeArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the verifier HTTP server. This is the default.
+    Serve(ServeArgs),
+    /// Verify a signature offline and print the result, without starting the
+    /// HTTP server.
+    Verify(VerifyArgs),
+    /// Generate a verify request from a JWT to use as a test fixture. The
+    /// proof is requested from the prover, so this is for test JWTs only.
+    GenTestVector(GenTestVectorArgs),
+}
+
+#[derive(Args, Clone)]
+struct ServeArgs {
+    /// Path to a TOML or YAML config file. Flags and env vars override the
+    /// values in the file.
+    #[arg(long, env = "ZKLOGIN_VERIFIER_CONFIG")]
+    config: Option<PathBuf>,
+    /// The socket address to listen on, e.g. `0.0.0.0:8080`.
+    #[arg(long, env = "ZKLOGIN_VERIFIER_ADDR")]
+    bind: Option<SocketAddr>,
+    /// Fullnode URL override for a network, e.g. `Mainnet=https://...`.
+    /// Can be repeated, URLs of the same network are tried in order.
+    #[arg(long = "fullnode-url", env = "ZKLOGIN_VERIFIER_FULLNODE_URLS", value_delimiter = ',', value_parser = parse_fullnode_url)]
+    fullnode_urls: Vec<(SuiEnv, String)>,
+    /// The network of requests that don't set one, e.g. `Testnet`.
+    #[arg(long, env = "ZKLOGIN_VERIFIER_DEFAULT_NETWORK")]
+    default_network: Option<SuiEnv>,
+    /// Comma separated OIDC providers to fetch JWKs from, e.g. `Google,Apple`.
+    #[arg(long, env = "ZKLOGIN_VERIFIER_PROVIDERS", value_delimiter = ',')]
+    providers: Option<Vec<String>>,
+    /// Seconds between refreshes of the JWKs of a provider, unless set per
+    /// provider in the config file.
+    #[arg(long, env = "ZKLOGIN_VERIFIER_JWK_REFRESH_INTERVAL_SECS")]
+    jwk_refresh_interval_secs: Option<u64>,
+    /// Seconds a JWK is kept after its provider stops returning it.
+    #[arg(long, env = "ZKLOGIN_VERIFIER_JWK_PRUNE_GRACE_PERIOD_SECS")]
+    jwk_prune_grace_period_secs: Option<u64>,
+    /// The tracing filter directive, e.g. `info` or `zklogin_verifier=debug`.
+    #[arg(long, env = "ZKLOGIN_VERIFIER_LOG_LEVEL")]
+    log_level: Option<String>,
+    /// Emit logs as JSON instead of plain text.
+    #[arg(long, env = "ZKLOGIN_VERIFIER_LOG_JSON")]
+    log_json: Option<bool>,
+    /// Log the full signature and bytes of verify requests.
+    #[arg(long, env = "ZKLOGIN_VERIFIER_LOG_PAYLOADS")]
+    log_payloads: Option<bool>,
+    /// Log the info logs of 1 in this many requests. Warnings, errors and
+    /// failed verifications are always logged.
+    #[arg(long, env = "ZKLOGIN_VERIFIER_LOG_SAMPLE_EVERY")]
+    log_sample_every: Option<u64>,
+    /// Path to a PEM encoded certificate chain. Serves HTTPS if set.
+    #[arg(long, env = "ZKLOGIN_VERIFIER_TLS_CERT", requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+    /// Path to the PEM encoded private key for `--tls-cert`.
+    #[arg(long, env = "ZKLOGIN_VERIFIER_TLS_KEY", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+    /// Bearer token that enables the admin endpoints, e.g. `POST /admin/jwks`.
+    #[arg(long, env = "ZKLOGIN_VERIFIER_ADMIN_TOKEN")]
+    admin_token: Option<String>,
+    /// Secret that ownership challenges are tagged with, shared by the
+    /// instances behind a load balancer.
+    #[arg(long, env = "ZKLOGIN_VERIFIER_CHALLENGE_SECRET")]
+    challenge_secret: Option<String>,
+    /// Comma separated origins allowed to call the verifier from a browser,
+    /// or `*` for any. Enables CORS.
+    #[arg(
+        long,
+        env = "ZKLOGIN_VERIFIER_CORS_ALLOWED_ORIGINS",
+        value_delimiter = ','
+    )]
+    cors_allowed_origins: Option<Vec<String>>,
+    /// Maximum size in bytes of a request body.
+    #[arg(long, env = "ZKLOGIN_VERIFIER_MAX_BODY_BYTES")]
+    max_body_bytes: Option<usize>,
+    /// Compress responses and accept compressed request bodies, with gzip or
+    /// Brotli.
+    #[arg(long, env = "ZKLOGIN_VERIFIER_COMPRESSION")]
+    compression: Option<bool>,
+    /// Seconds a request may take before it is answered with a 503.
+    #[arg(long, env = "ZKLOGIN_VERIFIER_REQUEST_TIMEOUT_SECS")]
+    request_timeout_secs: Option<u64>,
+    /// Seconds to let in-flight requests complete on shutdown.
+    #[arg(long, env = "ZKLOGIN_VERIFIER_SHUTDOWN_TIMEOUT_SECS")]
+    shutdown_timeout_secs: Option<u64>,
+    /// Seconds to wait on startup for the JWKs of all providers before
+    /// listening.
+    #[arg(long, env = "ZKLOGIN_VERIFIER_STARTUP_JWK_TIMEOUT_SECS")]
+    startup_jwk_timeout_secs: Option<u64>,
+    /// The number of proofs verified in parallel.
+    #[arg(long, env = "ZKLOGIN_VERIFIER_VERIFY_POOL_SIZE")]
+    verify_pool_size: Option<usize>,
+    /// The number of CPUs left to serving requests when sizing the
+    /// verification pool by the number of CPUs.
+    #[arg(long, env = "ZKLOGIN_VERIFIER_VERIFY_POOL_RESERVED_CPUS")]
+    verify_pool_reserved_cpus: Option<usize>,
+}
+
+impl ServeArgs {
+    /// Loads the config file if given, and applies the flags on top of it.
+    fn load_config(self) -> Result<Config, String> {
+        let mut config = match &self.config {
+            Some(path) => Config::from_file(path)?,
+            None => Config::default(),
+        };
+        if let Some(bind) = self.bind {
+            config.bind = bind;
+        }
+        let mut fullnode_urls: HashMap<SuiEnv, Vec<String>> = HashMap::new();
+        for (network, url) in self.fullnode_urls {
+            fullnode_urls.entry(network).or_default().push(url);
+        }
+        config.fullnode_urls.extend(fullnode_urls);
+        if let Some(network) = self.default_network {
+            config.default_network = network;
+        }
+        if let Some(providers) = self.providers {
+            config.providers = providers;
+        }
+        if let Some(interval) = self.jwk_refresh_interval_secs {
+            config.jwk_refresh_interval_secs = interval;
+        }
+        if let Some(grace_period) = self.jwk_prune_grace_period_secs {
+            config.jwk_prune_grace_period_secs = grace_period;
+        }
+        if let Some(log_level) = self.log_level {
+            config.log_level = log_level;
+        }
+        if let Some(log_json) = self.log_json {
+            config.log_json = log_json;
+        }
+        if let Some(log_payloads) = self.log_payloads {
+            config.log_payloads = log_payloads;
+        }
+        if let Some(every) = self.log_sample_every {
+            config.log_sample_every = every;
+        }
+        if let (Some(cert_path), Some(key_path)) = (self.tls_cert, self.tls_key) {
+            config.tls = Some(TlsConfig {
+                cert_path,
+                key_path,
+            });
+        }
+        if let Some(admin_token) = self.admin_token {
+            config.admin_token = Some(admin_token);
+        }
+        if let Some(challenge_secret) = self.challenge_secret {
+            config.challenge_secret = Some(challenge_secret);
+        }
+        if let Some(allowed_origins) = self.cors_allowed_origins {
+            config
+                .cors
+                .get_or_insert_with(Default::default)
+                .allowed_origins = allowed_origins;
+        }
+        if let Some(max_body_bytes) = self.max_body_bytes {
+            config.max_body_bytes = max_body_bytes;
+        }
+        if let Some(compression) = self.compression {
+            config.http.compression = compression;
+        }
+        if let Some(timeout) = self.request_timeout_secs {
+            config.request_timeout_secs = timeout;
+        }
+        if let Some(timeout) = self.shutdown_timeout_secs {
+            config.shutdown_timeout_secs = timeout;
+        }
+        if let Some(timeout) = self.startup_jwk_timeout_secs {
+            config.startup_jwk_timeout_secs = timeout;
+        }
+        if let Some(size) = self.verify_pool_size {
+            config.verify_pool_size = Some(size);
+        }
+        if let Some(reserved_cpus) = self.verify_pool_reserved_cpus {
+            config.verify_pool_reserved_cpus = reserved_cpus;
+        }
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/// Parses a `network=url` pair.
+fn parse_fullnode_url(s: &str) -> Result<(SuiEnv, String), String> {
+    let (network, url) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Expected network=url, got {}", s))?;
+    Ok((SuiEnv::from_str(network)?, url.to_string()))
+}
+
+#[derive(Args)]
+struct VerifyArgs {
+    /// The Base64 encoded zkLogin signature.
+    #[arg(long)]
+    signature: String,
+    /// The Base64 encoded bytes of payload.
+    #[arg(long)]
+    bytes: String,
+    /// The intent scope, 0 (TransactionData) or 3 (PersonalMessage).
+    #[arg(long)]
+    intent_scope: u8,
+    /// The current epoch to verify the signature against.
+    #[arg(long)]
+    epoch: EpochId,
+    /// Path to a JSON file of JWKs, in the format returned by `GET /jwks`.
+    #[arg(long)]
+    jwks: PathBuf,
+    /// The network whose ZkLoginEnv to verify against.
+    #[arg(long, default_value = "Mainnet")]
+    network: SuiEnv,
+    /// The author of the intent, required for PersonalMessage.
+    #[arg(long)]
+    author: Option<SuiAddress>,
+}
+
+#[derive(Args)]
+struct GenTestVectorArgs {
+    /// The JWT, issued with the nonce of the ephemeral key, max epoch and
+    /// randomness.
+    #[arg(long)]
+    jwt: String,
+    /// The ephemeral key pair, Base64 encoded as flag || private key.
+    #[arg(long)]
+    ephemeral_key: String,
+    /// The randomness of the nonce of the JWT, as a BigInt.
+    #[arg(long)]
+    jwt_randomness: String,
+    /// The user salt, as a BigInt.
+    #[arg(long)]
+    salt: String,
+    /// The max epoch of the nonce of the JWT.
+    #[arg(long)]
+    max_epoch: EpochId,
+    /// Sign this PersonalMessage instead of a transaction.
+    #[arg(long)]
+    message: Option<String>,
+    /// The URL of the prover, which must prove with the Test key.
+    #[arg(long, default_value = DEV_PROVER_URL)]
+    prover_url: String,
+}
 
 #[tokio::main]
-async fn main() {
-    tracing_subscriber::FmtSubscriber::builder()
-        .try_init()
-        .expect("setting default subscriber failed");
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match cli.command.unwrap_or(Command::Serve(cli.serve)) {
+        Command::Serve(args) => match args.clone().load_config() {
+            Ok(config) => {
+                serve(config, args).await;
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                ExitCode::FAILURE
+            }
+        },
+        Command::Verify(args) => verify_offline(args),
+        Command::GenTestVector(args) => gen_test_vector(args).await,
+    }
+}
 
-    let state = Arc::new(AppState {
-        jwks: Default::default(),
-    });
+/// Generates a verify request from a JWT and prints it as JSON.
+async fn gen_test_vector(args: GenTestVectorArgs) -> ExitCode {
+    let params = TestVectorParams {
+        jwt: args.jwt,
+        ephemeral_key: args.ephemeral_key,
+        jwt_randomness: args.jwt_randomness,
+        salt: args.salt,
+        max_epoch: args.max_epoch,
+        message: args.message,
+        prover_url: args.prover_url,
+    };
+    match generate_test_vector(params).await {
+        Ok(request) => {
+            println!("{}", serde_json::to_string_pretty(&request).unwrap());
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Runs the server with the config loaded from `args`, which are kept to
+/// reload it.
+async fn serve(config: Config, args: ServeArgs) {
+    let builder = tracing_subscriber::FmtSubscriber::builder()
+        .with_env_filter(EnvFilter::new(&config.log_level));
+    let sampler = LogSampler::new(config.log_sample_every);
+    let (subscriber, set_log_level): (_, Box<SetLogLevel>) = if config.log_json {
+        let builder = builder.json().with_filter_reloading();
+        let set_log_level = log_level_setter(builder.reload_handle());
+        (
+            builder.finish().with(sampler).try_init(),
+            Box::new(set_log_level),
+        )
+    } else {
+        let builder = builder.with_filter_reloading();
+        let set_log_level = log_level_setter(builder.reload_handle());
+        (
+            builder.finish().with(sampler).try_init(),
+            Box::new(set_log_level),
+        )
+    };
+    subscriber.expect("setting default subscriber failed");
 
-    let state_clone = state.clone();
-
-    tokio::task::spawn(async move {
-        info!("Starting JWK updater task");
-        loop {
-            let client = reqwest::Client::new();
-            for p in [
-                OIDCProvider::Facebook,
-                OIDCProvider::Google,
-                OIDCProvider::Twitch,
-                OIDCProvider::Kakao,
-                OIDCProvider::Apple,
-                OIDCProvider::Slack,
-            ] {
-                match fetch_jwks(&p, &client).await {
-                    Err(e) => {
-                        warn!("Error when fetching JWK with provider {:?} {:?}", p, e);
-                        tokio::time::sleep(Duration::from_secs(30)).await;
-                    }
-                    Ok(keys) => {
-                        for (jwk_id, jwk) in keys {
-                            let mut oauth_provider_jwk = state_clone.jwks.write();
-                            if oauth_provider_jwk.contains_key(&jwk_id) {
-                                continue;
-                            }
-                            info!("{:?} JWK updated: {:?}", &jwk_id, jwk);
-                            // todo(joyqvq): prune old jwks.
-                            oauth_provider_jwk.insert(jwk_id, jwk.clone());
-                        }
-                    }
+    // Load the verifying keys before serving, and refuse to serve if they
+    // don't verify the test vector.
+    let start = Instant::now();
+    self_check().expect("verifier self-check failed");
+    info!("Verifying keys loaded and checked in {:?}", start.elapsed());
+
+    let mut state = AppState::new(config);
+    state.reloader = Reloader::new(move || args.clone().load_config(), set_log_level);
+    let state = Arc::new(state);
+
+    #[cfg(unix)]
+    tokio::spawn({
+        let state = state.clone();
+        async move {
+            let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+                .expect("failed to install SIGHUP handler");
+            while hangup.recv().await.is_some() {
+                info!("SIGHUP received, reloading config");
+                if let Err(e) = reload_config(&state) {
+                    warn!("Config reload failed: {}", e);
                 }
             }
-            tokio::time::sleep(Duration::from_secs(3600)).await;
         }
     });
 
-    let app = Router::new()
-        .route("/", get(ping))
-        .route("/verify", post(verify))
-        .with_state(state);
-
-    let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
-    info!("listening on {}", addr);
-    axum::Server::bind(&addr)
-        .serve(app.into_make_service())
-        .await
-        .unwrap();
+    let updater = tokio::task::spawn(run_jwk_updater(state.clone()));
+    let epoch_trackers = spawn_epoch_trackers(&state);
+    let drift_checkers = spawn_drift_checkers(&state);
+
+    // Don't take traffic before the first JWK fetches, since every request
+    // would fail. If they take too long, serve anyway and stay unready.
+    let jwk_timeout = Duration::from_secs(state.config.load().startup_jwk_timeout_secs);
+    if !jwk_timeout.is_zero() {
+        info!(
+            "Waiting up to {:?} for the JWKs of all providers",
+            jwk_timeout
+        );
+        let missing = state.wait_for_jwks(jwk_timeout).await;
+        if !missing.is_empty() {
+            warn!(
+                "No JWKs of {:?} after {:?}, serving but unready until they are fetched",
+                missing, jwk_timeout
+            );
+        }
+    }
+
+    #[cfg(feature = "nats")]
+    if let Some(queue) = state.config.load().queue.clone() {
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = zklogin_verifier::queue::run_consumer(state, queue).await {
+                tracing::error!("Queue consumer stopped: {}", e);
+            }
+        });
+    }
+
+    let app = router(state.clone());
+
+    // On SIGTERM or SIGINT, stop accepting connections and give in-flight
+    // requests the drain timeout to complete.
+    let handle = Handle::new();
+    let drain_timeout = Duration::from_secs(state.config.load().shutdown_timeout_secs);
+    tokio::spawn({
+        let handle = handle.clone();
+        async move {
+            shutdown_signal().await;
+            info!("Shutting down, draining for up to {:?}", drain_timeout);
+            handle.graceful_shutdown(Some(drain_timeout));
+        }
+    });
+
+    let addr = state.config.load().bind;
+    let service = app.into_make_service_with_connect_info::<SocketAddr>();
+    match &state.config.load().tls {
+        Some(tls) => {
+            let tls_config = RustlsConfig::from_pem_file(&tls.cert_path, &tls.key_path)
+                .await
+                .expect("failed to load TLS certificate and key");
+            info!("listening on https://{}", addr);
+            axum_server::bind_rustls(addr, tls_config)
+                .handle(handle)
+                .http_config(state.config.load().http.http_config())
+                .addr_incoming_config(state.config.load().http.addr_incoming_config())
+                .serve(service)
+                .await
+                .unwrap();
+        }
+        None => {
+            info!("listening on http://{}", addr);
+            axum_server::bind(addr)
+                .handle(handle)
+                .http_config(state.config.load().http.http_config())
+                .addr_incoming_config(state.config.load().http.addr_incoming_config())
+                .serve(service)
+                .await
+                .unwrap();
+        }
+    }
+
+    updater.abort();
+    epoch_trackers.iter().for_each(|tracker| tracker.abort());
+    drift_checkers.iter().for_each(|checker| checker.abort());
+    info!("Server stopped");
 }
 
-async fn ping() -> &'static str {
-    "Pong!"
+type SetLogLevel = dyn Fn(&str) -> Result<(), String> + Send + Sync;
+
+/// Returns a function replacing the log filter through the reload handle.
+fn log_level_setter<S: 'static>(
+    handle: reload::Handle<EnvFilter, S>,
+) -> impl Fn(&str) -> Result<(), String> + Send + Sync {
+    move |level| {
+        let filter =
+            EnvFilter::try_new(level).map_err(|e| format!("Invalid log level {}: {}", level, e))?;
+        handle.reload(filter).map_err(|e| e.to_string())
+    }
+}
+
+/// Completes when the process receives SIGINT (Ctrl+C) or SIGTERM.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+/// Verifies a signature against a JWKs file and prints the result as JSON.
+fn verify_offline(args: VerifyArgs) -> ExitCode {
+    let result = std::fs::read_to_string(&args.jwks)
+        .map_err(|e| format!("Cannot read JWKs file: {}", e))
+        .and_then(|contents| {
+            serde_json::from_str::<JwksResponse>(&contents)
+                .map_err(|e| format!("Cannot parse JWKs file: {}", e))
+        })
+        .map_err(|e| (e, None))
+        .and_then(|jwks| {
+            let intent_scope = IntentScope::try_from(args.intent_scope)
+                .map_err(|_| (format!("Invalid intent scope: {}", args.intent_scope), None))?;
+            let request = VerifyRequest {
+                signature: args.signature,
+                bytes: args.bytes,
+                intent_scope,
+                author: args.author,
+                network: Some(args.network),
+                curr_epoch: Some(args.epoch),
+                sponsor: None,
+                format: None,
+                encoding: None,
+                policy: None,
+                jwk: None,
+                historical_epoch: None,
+                intent: None,
+            };
+            let jwks = jwks.jwks.into_iter().map(|e| e.into_parts()).collect();
+            let (_, env) = args.network.get_params();
+            // Verify errors are reported with their code, as by the server.
+            verify_request(&request, jwks, env, args.epoch, VerifyPolicy::default())
+                .map_err(|e| (e.to_string(), Some(e.code())))
+        });
+
+    match result {
+        Ok(res) => {
+            println!("{}", serde_json::to_string(&res).unwrap());
+            ExitCode::SUCCESS
+        }
+        Err((error, code)) => {
+            let mut failure = serde_json::json!({ "is_verified": false, "error": error });
+            if let Some(code) = code {
+                failure["code"] = serde_json::json!(code);
+            }
+            println!("{}", failure);
+            ExitCode::FAILURE
+        }
+    }
 }
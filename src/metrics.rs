@@ -0,0 +1,242 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Prometheus metrics of the verifier, served at `GET /metrics`.
+
+use crate::{
+    config::MetricsConfig, server::unix_secs, AppState, ErrorCode, VerifyError, VerifyResponse,
+};
+use axum::extract::State;
+use prometheus::{
+    register_gauge_vec_with_registry, register_histogram_vec_with_registry,
+    register_int_counter_vec_with_registry, register_int_counter_with_registry,
+    register_int_gauge_vec_with_registry, register_int_gauge_with_registry, Encoder, GaugeVec,
+    HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Registry, TextEncoder,
+};
+use std::{sync::Arc, time::SystemTime};
+
+/// The metrics of the verifier, registered to their own registry.
+#[derive(Clone, Debug)]
+pub struct Metrics {
+    pub registry: Registry,
+    /// JWK fetches by provider and outcome, one of "modified",
+    /// "not_modified" or "error".
+    pub jwk_fetches: IntCounterVec,
+    /// The number of times the cached JWKs of each provider have changed.
+    pub jwk_generation: IntGaugeVec,
+    /// End-to-end latency of verify requests by provider, intent scope and
+    /// outcome, one of "verified", "rejected" or "error".
+    pub verify_latency: HistogramVec,
+    /// Verify results by outcome as for `verify_latency`, and reason, e.g.
+    /// "unknown_jwk" or "proof_invalid", to tell failures of the verifier or
+    /// its dependencies apart from invalid signatures.
+    pub verify_results: IntCounterVec,
+    /// Latency of fetching the current epoch by network and outcome, one of
+    /// "ok" or "error".
+    pub epoch_fetch_latency: HistogramVec,
+    /// The number of background fetches of the epoch of each tracked
+    /// network that failed since the last successful one.
+    pub epoch_fetch_failures: IntGaugeVec,
+    /// Verify requests answered from the verification cache.
+    pub verify_cache_hits: IntCounter,
+    /// Requests shed because the concurrency limit and its queue were full.
+    pub requests_shed: IntCounter,
+    /// The number of proofs verified in parallel.
+    pub verify_pool_size: IntGauge,
+    /// The number of verifications waiting for a thread of the pool, as of
+    /// the latest scrape.
+    pub verify_pool_queue_depth: IntGauge,
+    /// The number of configured providers without JWKs, which keep the
+    /// verifier unready while non-zero.
+    pub providers_missing_jwks: IntGauge,
+    /// The number of JWKs that differ from the on-chain JWKs by network and
+    /// kind, one of "only_fetched", "only_on_chain" or "mismatched".
+    pub jwk_drift: IntGaugeVec,
+    /// Verify requests counted against each SLO by outcome, one of "good"
+    /// or "bad".
+    pub slo_requests: IntCounterVec,
+    /// The burn rate of each SLO over each of its windows, as of the latest
+    /// scrape.
+    pub slo_burn_rate: GaugeVec,
+    /// Access log entries dropped because the writer fell behind.
+    pub access_log_dropped: IntCounter,
+}
+
+impl Metrics {
+    /// Registers the metrics to the registry, with the histogram buckets of
+    /// the config.
+    pub fn new(registry: Registry, config: &MetricsConfig) -> Self {
+        Self {
+            jwk_fetches: register_int_counter_vec_with_registry!(
+                "zklogin_verifier_jwk_fetches",
+                "JWK fetches by provider and outcome",
+                &["provider", "outcome"],
+                registry,
+            )
+            .unwrap(),
+            jwk_generation: register_int_gauge_vec_with_registry!(
+                "zklogin_verifier_jwk_generation",
+                "Number of times the cached JWKs of a provider have changed",
+                &["provider"],
+                registry,
+            )
+            .unwrap(),
+            verify_latency: register_histogram_vec_with_registry!(
+                "zklogin_verifier_verify_latency_seconds",
+                "Latency of verify requests by provider, intent scope and outcome",
+                &["provider", "intent_scope", "outcome"],
+                config.verify_latency_buckets.clone(),
+                registry,
+            )
+            .unwrap(),
+            verify_results: register_int_counter_vec_with_registry!(
+                "zklogin_verifier_verify_results",
+                "Verify results by outcome and reason",
+                &["outcome", "reason"],
+                registry,
+            )
+            .unwrap(),
+            epoch_fetch_latency: register_histogram_vec_with_registry!(
+                "zklogin_verifier_epoch_fetch_latency_seconds",
+                "Latency of fetching the current epoch by network and outcome",
+                &["network", "outcome"],
+                config.epoch_fetch_latency_buckets.clone(),
+                registry,
+            )
+            .unwrap(),
+            epoch_fetch_failures: register_int_gauge_vec_with_registry!(
+                "zklogin_verifier_epoch_fetch_failures",
+                "Background fetches of the epoch of a network failed since the last successful one",
+                &["network"],
+                registry,
+            )
+            .unwrap(),
+            verify_cache_hits: register_int_counter_with_registry!(
+                "zklogin_verifier_verify_cache_hits",
+                "Verify requests answered from the verification cache",
+                registry,
+            )
+            .unwrap(),
+            requests_shed: register_int_counter_with_registry!(
+                "zklogin_verifier_requests_shed",
+                "Requests shed because the concurrency limit and its queue were full",
+                registry,
+            )
+            .unwrap(),
+            verify_pool_size: register_int_gauge_with_registry!(
+                "zklogin_verifier_verify_pool_size",
+                "Number of proofs verified in parallel",
+                registry,
+            )
+            .unwrap(),
+            verify_pool_queue_depth: register_int_gauge_with_registry!(
+                "zklogin_verifier_verify_pool_queue_depth",
+                "Number of verifications waiting for a thread of the pool",
+                registry,
+            )
+            .unwrap(),
+            providers_missing_jwks: register_int_gauge_with_registry!(
+                "zklogin_verifier_providers_missing_jwks",
+                "Number of configured providers without JWKs",
+                registry,
+            )
+            .unwrap(),
+            jwk_drift: register_int_gauge_vec_with_registry!(
+                "zklogin_verifier_jwk_drift",
+                "Number of JWKs that differ from the on-chain JWKs by network and kind",
+                &["network", "kind"],
+                registry,
+            )
+            .unwrap(),
+            slo_requests: register_int_counter_vec_with_registry!(
+                "zklogin_verifier_slo_requests",
+                "Verify requests counted against each SLO by outcome",
+                &["slo", "outcome"],
+                registry,
+            )
+            .unwrap(),
+            slo_burn_rate: register_gauge_vec_with_registry!(
+                "zklogin_verifier_slo_burn_rate",
+                "Burn rate of the error budget of each SLO over each of its windows",
+                &["slo", "window"],
+                registry,
+            )
+            .unwrap(),
+            access_log_dropped: register_int_counter_with_registry!(
+                "zklogin_verifier_access_log_dropped",
+                "Access log entries dropped because the writer fell behind",
+                registry,
+            )
+            .unwrap(),
+            registry,
+        }
+    }
+}
+
+/// Returns the outcome of a verify result, one of "verified", "rejected" or
+/// "error", and its reason.
+pub(crate) fn verify_outcome(
+    result: &Result<VerifyResponse, VerifyError>,
+) -> (&'static str, &'static str) {
+    let code = match result {
+        Ok(_) => return ("verified", "verified"),
+        Err(e) => e.code(),
+    };
+    let reason = match code {
+        ErrorCode::UnknownJwk => "unknown_jwk",
+        ErrorCode::SignatureExpired => "epoch_expired",
+        ErrorCode::MaxEpochTooFar | ErrorCode::InvalidHistoricalEpoch => "epoch_out_of_range",
+        ErrorCode::ProofVerifyFailed => "proof_invalid",
+        ErrorCode::AuthorMismatch | ErrorCode::VerifyFailed => "signature_invalid",
+        ErrorCode::InvalidSignatureEncoding | ErrorCode::InvalidBytes => "parse_error",
+        ErrorCode::NotZkLoginSignature
+        | ErrorCode::UnsupportedIntentScope
+        | ErrorCode::IntentScopeMismatch
+        | ErrorCode::MissingAuthor
+        | ErrorCode::InvalidRequest
+        | ErrorCode::BatchTooLarge
+        | ErrorCode::PayloadTooLarge => "invalid_request",
+        ErrorCode::InlineJwkNotAllowed | ErrorCode::IssuerNotAllowed => "not_allowed",
+        ErrorCode::EpochFetchFailed => "epoch_fetch_error",
+        ErrorCode::RateLimited => "rate_limited",
+        _ => "other",
+    };
+    // Only failures of the fullnodes are errors of the verifier, the rest
+    // are the client's.
+    let outcome = if code == ErrorCode::EpochFetchFailed {
+        "error"
+    } else {
+        "rejected"
+    };
+    (outcome, reason)
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new(Registry::new(), &MetricsConfig::default())
+    }
+}
+
+/// Returns the metrics in the Prometheus text format.
+pub async fn get_metrics(State(state): State<Arc<AppState>>) -> String {
+    state
+        .metrics
+        .verify_pool_size
+        .set(state.compute_pool.size() as i64);
+    state
+        .metrics
+        .verify_pool_queue_depth
+        .set(state.compute_pool.queue_depth() as i64);
+    for burn_rate in state.slos.burn_rates(unix_secs(SystemTime::now())) {
+        state
+            .metrics
+            .slo_burn_rate
+            .with_label_values(&[&burn_rate.slo, &format!("{}s", burn_rate.window_secs)])
+            .set(burn_rate.rate);
+    }
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&state.metrics.registry.gather(), &mut buffer)
+        .unwrap();
+    String::from_utf8(buffer).unwrap()
+}
@@ -0,0 +1,174 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Streaming batch verification of newline-delimited JSON at
+//! `/verify_stream`, for backfills too large to buffer as one batch on
+//! either side. Each line is a request as sent over `/verify_ws`, and each
+//! result is written back as a line as soon as it is ready.
+
+use crate::{
+    config::TenantConfig,
+    tenant::{current_tenant, with_tenant},
+    ws::{check_rate_limit, handle_message, invalid_request, WsVerifyResponse},
+    AppState,
+};
+use axum::{
+    body::{Bytes, StreamBody},
+    extract::{BodyStream, ConnectInfo, State},
+    http::{header::CONTENT_TYPE, HeaderMap},
+    response::{IntoResponse, Response},
+};
+use futures::{channel::mpsc::Sender, SinkExt, StreamExt};
+use std::{
+    collections::VecDeque,
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+use tokio::sync::mpsc;
+use tracing::{info, Instrument, Span};
+
+/// The content type of requests and responses.
+pub const NDJSON: &str = "application/x-ndjson";
+
+/// Verifies the requests of the body, one per line, and streams their results
+/// back, one per line, in the order they complete.
+pub async fn verify_stream(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    body: BodyStream,
+) -> Response {
+    let ip = state.rate_limiter.client_ip(&headers, peer);
+    let tenant = current_tenant();
+    let window = state.config.load().ws_max_in_flight.max(1);
+    let (sender, receiver) = futures::channel::mpsc::channel(window);
+    tokio::spawn(
+        with_tenant(tenant.clone(), async move {
+            info!(%ip, "verify_stream started");
+            serve_stream(state, body, sender, ip, tenant).await;
+            info!(%ip, "verify_stream finished");
+        })
+        .instrument(Span::current()),
+    );
+    ([(CONTENT_TYPE, NDJSON)], StreamBody::new(receiver)).into_response()
+}
+
+/// Verifies the lines of the body concurrently, with at most
+/// `ws_max_in_flight` of them pending. Once the window is full, no more of
+/// the body is read, so that a client sending faster than the verifier
+/// keeps up, or reading the results slower, is slowed down by TCP flow
+/// control instead of growing a queue.
+async fn serve_stream(
+    state: Arc<AppState>,
+    mut body: BodyStream,
+    mut sender: Sender<Result<Bytes, Infallible>>,
+    ip: IpAddr,
+    tenant: Option<Arc<TenantConfig>>,
+) {
+    let window = state.config.load().ws_max_in_flight.max(1);
+    let max_line_bytes = state.config.load().max_body_bytes;
+    let (tx, mut rx) = mpsc::channel(window);
+    let mut in_flight = 0;
+    // The bytes of the line being read, and the lines read but not started.
+    let mut partial = Vec::new();
+    let mut lines = VecDeque::new();
+    let mut body_done = false;
+    loop {
+        while in_flight < window {
+            let Some(line) = lines.pop_front() else {
+                break;
+            };
+            let text = match String::from_utf8(line) {
+                Ok(text) => text,
+                Err(_) => {
+                    let response = invalid_request("Line is not UTF-8".to_string());
+                    if send(&mut sender, &response).await.is_err() {
+                        return;
+                    }
+                    continue;
+                }
+            };
+            if let Some(response) = check_rate_limit(&state, ip, tenant.as_deref(), &text) {
+                if send(&mut sender, &response).await.is_err() {
+                    return;
+                }
+                continue;
+            }
+            in_flight += 1;
+            let (state, tx) = (state.clone(), tx.clone());
+            tokio::spawn(
+                with_tenant(tenant.clone(), async move {
+                    // The receiver is gone only if the stream ended.
+                    let _ = tx.send(handle_message(&state, &text).await).await;
+                })
+                .instrument(Span::current()),
+            );
+        }
+        if body_done && lines.is_empty() && in_flight == 0 {
+            return;
+        }
+
+        tokio::select! {
+            chunk = body.next(), if !body_done && lines.is_empty() => match chunk {
+                Some(Ok(chunk)) => {
+                    split_lines(&mut partial, &chunk, &mut lines);
+                    if partial.len() > max_line_bytes
+                        || lines.iter().any(|line| line.len() > max_line_bytes)
+                    {
+                        let response = invalid_request(format!(
+                            "Line is longer than {} bytes",
+                            max_line_bytes
+                        ));
+                        let _ = send(&mut sender, &response).await;
+                        return;
+                    }
+                }
+                // The client aborted the request.
+                Some(Err(_)) => return,
+                None => {
+                    body_done = true;
+                    push_line(std::mem::take(&mut partial), &mut lines);
+                }
+            },
+            Some(response) = rx.recv() => {
+                in_flight -= 1;
+                if send(&mut sender, &response).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Appends the chunk to the partial line, and moves the lines it completes
+/// to `lines`.
+fn split_lines(partial: &mut Vec<u8>, chunk: &[u8], lines: &mut VecDeque<Vec<u8>>) {
+    let mut rest = chunk;
+    while let Some(end) = rest.iter().position(|&b| b == b'\n') {
+        partial.extend_from_slice(&rest[..end]);
+        push_line(std::mem::take(partial), lines);
+        rest = &rest[end + 1..];
+    }
+    partial.extend_from_slice(rest);
+}
+
+/// Queues the line, without its carriage return, unless it is blank.
+fn push_line(mut line: Vec<u8>, lines: &mut VecDeque<Vec<u8>>) {
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    if !line.iter().all(u8::is_ascii_whitespace) {
+        lines.push_back(line);
+    }
+}
+
+/// Writes the result as a line, failing if the client is gone.
+async fn send(
+    sender: &mut Sender<Result<Bytes, Infallible>>,
+    response: &WsVerifyResponse,
+) -> Result<(), futures::channel::mpsc::SendError> {
+    let mut line = serde_json::to_vec(response).expect("response is serializable");
+    line.push(b'\n');
+    sender.send(Ok(Bytes::from(line))).await
+}
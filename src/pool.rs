@@ -0,0 +1,104 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! A bounded pool of blocking threads for proof verification, which is CPU
+//! bound and would otherwise stall the async workers serving requests.
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread::available_parallelism,
+};
+use tokio::sync::Semaphore;
+use tracing::Span;
+
+/// Runs verifications on the blocking threads of tokio, at most `size` at a
+/// time. Verifications beyond that queue for a free slot.
+#[derive(Clone, Debug)]
+pub struct ComputePool {
+    size: usize,
+    permits: Arc<Semaphore>,
+    queued: Arc<AtomicUsize>,
+}
+
+impl ComputePool {
+    /// Creates a pool running up to `size` verifications at a time, or if
+    /// `size` is not set, one per available CPU less `reserved_cpus`, which
+    /// are left to serving requests. The pool runs at least one.
+    pub fn new(size: Option<usize>, reserved_cpus: usize) -> Self {
+        let size = size
+            .unwrap_or_else(|| {
+                available_parallelism()
+                    .map_or(1, |n| n.get())
+                    .saturating_sub(reserved_cpus)
+            })
+            .max(1);
+        Self {
+            size,
+            permits: Arc::new(Semaphore::new(size)),
+            queued: Default::default(),
+        }
+    }
+
+    /// The number of verifications run at a time.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The number of verifications waiting for a free slot.
+    pub fn queue_depth(&self) -> usize {
+        self.queued.load(Ordering::SeqCst)
+    }
+
+    /// Runs `f` on the pool once a slot is free and returns its result. The
+    /// slot is held until `f` returns, even if the returned future is
+    /// dropped before, as the blocking thread cannot be stopped.
+    pub async fn run<T, F>(&self, f: F) -> T
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let queued = Queued::new(&self.queued);
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("never closed");
+        drop(queued);
+        // Logs of the verification stay in the span of its request.
+        let span = Span::current();
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            let _entered = span.enter();
+            f()
+        })
+        .await
+        .expect("verification panicked")
+    }
+}
+
+impl Default for ComputePool {
+    fn default() -> Self {
+        Self::new(None, 0)
+    }
+}
+
+/// A verification waiting for a slot, counted until dropped, so that
+/// requests dropped while waiting leave the queue.
+struct Queued<'a>(&'a AtomicUsize);
+
+impl<'a> Queued<'a> {
+    fn new(queued: &'a AtomicUsize) -> Self {
+        queued.fetch_add(1, Ordering::SeqCst);
+        Self(queued)
+    }
+}
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
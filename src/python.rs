@@ -0,0 +1,69 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Python bindings to the network-free verifier, so Python tooling can
+//! verify zkLogin signatures locally given JWKs and an epoch, instead of
+//! calling the HTTP server.
+
+use crate::{
+    verifier::{parse_zklogin_signature, verify_request},
+    JwkEntry, JwksResponse, VerifyPolicy, VerifyRequest,
+};
+use pyo3::{create_exception, exceptions::PyException, prelude::*};
+use pythonize::{depythonize, pythonize};
+use sui_types::committee::EpochId;
+
+create_exception!(
+    zklogin_verifier,
+    VerifyError,
+    PyException,
+    "A signature failed to verify or to parse. Its args are the message and the error code, e.g. \"SIGNATURE_EXPIRED\"."
+);
+
+/// Converts the error to a `VerifyError` exception carrying its code.
+fn to_py_err(error: crate::VerifyError) -> PyErr {
+    let code = serde_json::to_value(error.code())
+        .ok()
+        .and_then(|code| code.as_str().map(str::to_string))
+        .unwrap_or_default();
+    VerifyError::new_err((error.to_string(), code))
+}
+
+/// Verifies the signature in `request` against `jwks` at `curr_epoch`.
+/// `request` is a dict as the body of `POST /verify` and `jwks` a dict as
+/// returned by `GET /jwks`. Returns the response as a dict, or raises
+/// `VerifyError`.
+#[pyfunction]
+fn verify(
+    py: Python<'_>,
+    request: &PyAny,
+    jwks: &PyAny,
+    curr_epoch: EpochId,
+) -> PyResult<PyObject> {
+    let request: VerifyRequest = depythonize(request)?;
+    let jwks: JwksResponse = depythonize(jwks)?;
+    let (_, env) = request.network.unwrap_or_default().get_params();
+    let jwks = jwks.jwks.into_iter().map(JwkEntry::into_parts).collect();
+    // Other Python threads can run while the proof is verified.
+    let res = py
+        .allow_threads(|| verify_request(&request, jwks, env, curr_epoch, VerifyPolicy::default()))
+        .map_err(to_py_err)?;
+    Ok(pythonize(py, &res)?)
+}
+
+/// Parses a Base64 encoded zkLogin signature and returns its components as a
+/// dict, without verifying it.
+#[pyfunction]
+fn parse_signature(py: Python<'_>, signature: &str) -> PyResult<PyObject> {
+    let res = parse_zklogin_signature(signature).map_err(to_py_err)?;
+    Ok(pythonize(py, &res)?)
+}
+
+/// The `zklogin_verifier` Python module.
+#[pymodule]
+fn zklogin_verifier(py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(verify, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_signature, m)?)?;
+    m.add("VerifyError", py.get_type::<VerifyError>())?;
+    Ok(())
+}
@@ -0,0 +1,53 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Consumes verification jobs from a NATS subject and publishes their
+//! results, for pipelines that cannot call the HTTP API synchronously.
+
+use crate::{config::QueueConfig, ws::handle_message, AppState};
+use futures::StreamExt;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tracing::{info, warn};
+
+/// Verifies the jobs published on `config.subject` until the subscription
+/// ends. A job is a message as sent to `/verify_ws`, and its result is
+/// published to the reply subject of the message if set, and otherwise to
+/// `config.response_subject`.
+pub async fn run_consumer(state: Arc<AppState>, config: QueueConfig) -> Result<(), String> {
+    let client = async_nats::connect(&config.url)
+        .await
+        .map_err(|e| format!("Cannot connect to NATS at {}: {}", config.url, e))?;
+    let mut subscriber = match &config.queue_group {
+        Some(group) => {
+            client
+                .queue_subscribe(config.subject.clone(), group.clone())
+                .await
+        }
+        None => client.subscribe(config.subject.clone()).await,
+    }
+    .map_err(|e| format!("Cannot subscribe to {}: {}", config.subject, e))?;
+    info!(subject = %config.subject, "Consuming verification jobs from NATS");
+
+    // Stop taking jobs while `max_in_flight` are verifying, leaving the rest
+    // to other consumers of the queue group.
+    let permits = Arc::new(Semaphore::new(config.max_in_flight.max(1)));
+    while let Some(message) = subscriber.next().await {
+        let permit = permits.clone().acquire_owned().await.expect("never closed");
+        let (state, client) = (state.clone(), client.clone());
+        let response_subject = message.reply.clone().or(config.response_subject.clone());
+        tokio::spawn(async move {
+            let response = handle_message(&state, &String::from_utf8_lossy(&message.payload)).await;
+            drop(permit);
+            let Some(subject) = response_subject else {
+                warn!(id = %response.id, "Job has no reply subject, dropping its result");
+                return;
+            };
+            let payload = serde_json::to_vec(&response).expect("response is serializable");
+            if let Err(e) = client.publish(subject.clone(), payload.into()).await {
+                warn!(id = %response.id, "Cannot publish result to {}: {}", subject, e);
+            }
+        });
+    }
+    Err(format!("Subscription to {} ended", config.subject))
+}
@@ -0,0 +1,239 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Token-bucket rate limiting of the verification endpoints, globally, per
+//! client IP and per zkLogin address.
+
+use crate::{
+    config::{BucketConfig, RateLimitConfig, TenantConfig},
+    reload::Reloadable,
+    server::error_body,
+    AppState, ErrorCode,
+};
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{header::RETRY_AFTER, HeaderMap, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use lru::LruCache;
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    net::{IpAddr, SocketAddr},
+    num::NonZeroUsize,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use sui_types::base_types::SuiAddress;
+
+/// The number of client IPs or addresses whose buckets are kept. The bucket
+/// of the one seen least recently is dropped for a new one.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// A token bucket, holding the tokens left as of `updated`.
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(config: &BucketConfig, now: Instant) -> Self {
+        Self {
+            tokens: config.burst as f64,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, config: &BucketConfig, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * config.per_second).min(config.burst as f64);
+        self.updated = now;
+    }
+
+    /// Takes a token, or returns how long until one is available.
+    fn try_acquire(&mut self, config: &BucketConfig, now: Instant) -> Result<(), Duration> {
+        self.refill(config, now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - self.tokens) / config.per_second,
+            ))
+        }
+    }
+
+    /// Returns a token taken for a request rejected by another limit.
+    fn release(&mut self, config: &BucketConfig) {
+        self.tokens = (self.tokens + 1.0).min(config.burst as f64);
+    }
+}
+
+/// The buckets of the [MAX_TRACKED_CLIENTS] keys seen last. Clients choose
+/// keys freely, e.g. the address of a forged signature, so that a flood of
+/// new keys must neither grow the map nor make a lookup scan it.
+#[derive(Debug)]
+struct Buckets<K: Hash + Eq>(LruCache<K, TokenBucket>);
+
+impl<K: Hash + Eq> Default for Buckets<K> {
+    fn default() -> Self {
+        Self(LruCache::new(
+            NonZeroUsize::new(MAX_TRACKED_CLIENTS).expect("not zero"),
+        ))
+    }
+}
+
+/// The rate limiter state shared by all requests.
+#[derive(Clone, Debug, Default)]
+pub struct RateLimiter {
+    config: Reloadable<RateLimitConfig>,
+    global: Arc<Mutex<Option<TokenBucket>>>,
+    per_ip: Arc<Mutex<Buckets<IpAddr>>>,
+    per_address: Arc<Mutex<Buckets<SuiAddress>>>,
+    per_tenant: Arc<Mutex<HashMap<String, TokenBucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config: config.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Replaces the limits, starting all clients with a full bucket.
+    pub fn reconfigure(&self, config: RateLimitConfig) {
+        self.config.store(config);
+        self.global.lock().take();
+        self.per_ip.lock().0.clear();
+        self.per_address.lock().0.clear();
+        self.per_tenant.lock().clear();
+    }
+
+    /// Takes a token for a request from `ip`, or returns how long the client
+    /// should wait before retrying.
+    pub fn check(&self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        let limits = self.config.load();
+        if let Some(config) = &limits.per_ip {
+            try_acquire_for(&mut self.per_ip.lock(), ip, config, now)?;
+        }
+        if let Some(config) = &limits.global {
+            let acquired = self
+                .global
+                .lock()
+                .get_or_insert_with(|| TokenBucket::new(config, now))
+                .try_acquire(config, now);
+            if acquired.is_err() {
+                // A request rejected by the global limit does not count
+                // against its client.
+                if let Some(config) = &limits.per_ip {
+                    if let Some(bucket) = self.per_ip.lock().0.peek_mut(&ip) {
+                        bucket.release(config);
+                    }
+                }
+            }
+            acquired?;
+        }
+        Ok(())
+    }
+
+    /// Takes a token for a request from `ip` and one of its tenant, if any,
+    /// as each item of a batch or stream is charged, or returns how long the
+    /// client should wait before retrying.
+    pub fn check_with_tenant(
+        &self,
+        ip: IpAddr,
+        tenant: Option<&TenantConfig>,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        self.check(ip, now)?;
+        match tenant {
+            Some(tenant) => self.check_tenant(tenant, now),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether requests are rate limited by the zkLogin address signing them.
+    pub fn limits_addresses(&self) -> bool {
+        self.config.load().per_address.is_some()
+    }
+
+    /// Takes a token for a request signed for `address`, or returns how long
+    /// the client should wait before retrying. The address is taken from the
+    /// signature before verifying it, so this shares capacity fairly among
+    /// honest clients behind one IP, but does not stop a client choosing a
+    /// new address for each request, or spending the tokens of another
+    /// address with signatures that fail.
+    pub fn check_address(&self, address: SuiAddress, now: Instant) -> Result<(), Duration> {
+        match &self.config.load().per_address {
+            Some(config) => try_acquire_for(&mut self.per_address.lock(), address, config, now),
+            None => Ok(()),
+        }
+    }
+
+    /// Takes a token for a request of the tenant, if it has a rate limit, or
+    /// returns how long the tenant should wait before retrying.
+    pub fn check_tenant(&self, tenant: &TenantConfig, now: Instant) -> Result<(), Duration> {
+        match &tenant.rate_limit {
+            Some(config) => self
+                .per_tenant
+                .lock()
+                .entry(tenant.name.clone())
+                .or_insert_with(|| TokenBucket::new(config, now))
+                .try_acquire(config, now),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the IP of the client, from the configured header if present
+    /// and otherwise the peer address.
+    pub(crate) fn client_ip(&self, headers: &HeaderMap, peer: SocketAddr) -> IpAddr {
+        self.config
+            .load()
+            .client_ip_header
+            .as_ref()
+            .and_then(|name| headers.get(name.as_str()))
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(',').next())
+            .and_then(|ip| ip.trim().parse().ok())
+            .unwrap_or(peer.ip())
+    }
+}
+
+/// Takes a token from the bucket of `key`, dropping the bucket of the key
+/// seen least recently if too many are tracked.
+fn try_acquire_for<K: Eq + Hash>(
+    buckets: &mut Buckets<K>,
+    key: K,
+    config: &BucketConfig,
+    now: Instant,
+) -> Result<(), Duration> {
+    buckets
+        .0
+        .get_or_insert_mut(key, || TokenBucket::new(config, now))
+        .try_acquire(config, now)
+}
+
+/// Middleware that rejects requests over the rate limit with 429 and a
+/// Retry-After header.
+pub async fn rate_limit<B>(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let ip = state.rate_limiter.client_ip(request.headers(), peer);
+    if let Err(retry_after) = state.rate_limiter.check(ip, Instant::now()) {
+        let retry_after = retry_after.as_secs_f64().ceil() as u64;
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(RETRY_AFTER, retry_after.to_string())],
+            error_body("Too many requests", ErrorCode::RateLimited),
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
@@ -0,0 +1,137 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Reloading the providers, issuer allowlist, rate limits and log level
+//! without a restart, on SIGHUP or `POST /admin/reload`. In-flight requests
+//! complete with the settings they started with.
+
+use crate::{config::Config, AppState};
+use arc_swap::ArcSwap;
+use std::{fmt, sync::Arc};
+use tracing::{info, warn};
+
+/// A value that can be replaced while it is read, without locking readers.
+#[derive(Debug, Default)]
+pub struct Reloadable<T>(Arc<ArcSwap<T>>);
+
+impl<T> Reloadable<T> {
+    /// Returns the current value.
+    pub fn load(&self) -> Arc<T> {
+        self.0.load_full()
+    }
+
+    /// Replaces the value for all later reads.
+    pub fn store(&self, value: T) {
+        self.0.store(Arc::new(value))
+    }
+}
+
+impl<T> Clone for Reloadable<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> From<T> for Reloadable<T> {
+    fn from(value: T) -> Self {
+        Self(Arc::new(ArcSwap::from_pointee(value)))
+    }
+}
+
+type LoadConfig = dyn Fn() -> Result<Config, String> + Send + Sync;
+type SetLogLevel = dyn Fn(&str) -> Result<(), String> + Send + Sync;
+
+/// Reloads the config from the sources it was loaded from at startup, and
+/// applies log levels. Both are provided by the binary, and reloading is
+/// disabled without them.
+#[derive(Clone, Default)]
+pub struct Reloader {
+    load_config: Option<Arc<LoadConfig>>,
+    set_log_level: Option<Arc<SetLogLevel>>,
+    /// Serializes reloads.
+    lock: Arc<parking_lot::Mutex<()>>,
+}
+
+impl fmt::Debug for Reloader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reloader")
+            .field("enabled", &self.load_config.is_some())
+            .finish()
+    }
+}
+
+impl Reloader {
+    pub fn new(
+        load_config: impl Fn() -> Result<Config, String> + Send + Sync + 'static,
+        set_log_level: impl Fn(&str) -> Result<(), String> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            load_config: Some(Arc::new(load_config)),
+            set_log_level: Some(Arc::new(set_log_level)),
+            lock: Default::default(),
+        }
+    }
+}
+
+/// Reloads the config and applies its reloadable settings. Returns the names
+/// of the settings that changed.
+pub fn reload_config(state: &AppState) -> Result<Vec<&'static str>, String> {
+    let load_config = state
+        .reloader
+        .load_config
+        .as_ref()
+        .ok_or_else(|| "Config reloading is not enabled".to_string())?;
+    apply_config(state, load_config()?)
+}
+
+/// Applies the providers, allowed issuers, rate limits, webhooks and log
+/// level of `new`, keeping all other settings, which need a restart to
+/// change. Returns the names of the settings that changed.
+pub fn apply_config(state: &AppState, new: Config) -> Result<Vec<&'static str>, String> {
+    let _guard = state.reloader.lock.lock();
+    new.validate()?;
+    let old = state.config.load();
+    let mut config = Config::clone(&old);
+    let mut changed = Vec::new();
+    if new.providers != old.providers {
+        config.providers = new.providers.clone();
+        changed.push("providers");
+    }
+    if new.allowed_issuers != old.allowed_issuers {
+        config.allowed_issuers = new.allowed_issuers.clone();
+        changed.push("allowed_issuers");
+    }
+    if new.rate_limit != old.rate_limit {
+        config.rate_limit = new.rate_limit.clone();
+        changed.push("rate_limit");
+    }
+    if new.webhooks != old.webhooks {
+        config.webhooks = new.webhooks.clone();
+        changed.push("webhooks");
+    }
+    if new.log_level != old.log_level {
+        let set_log_level = state
+            .reloader
+            .set_log_level
+            .as_ref()
+            .ok_or_else(|| "Log level reloading is not enabled".to_string())?;
+        set_log_level(&new.log_level)?;
+        config.log_level = new.log_level.clone();
+        changed.push("log_level");
+    }
+    if config != new {
+        warn!("Config changes other than providers, allowed_issuers, rate_limit, webhooks and log_level need a restart");
+    }
+
+    let providers = config.oidc_providers()?;
+    let rate_limit = config.rate_limit.clone();
+    state.config.store(config);
+    if changed.contains(&"providers") {
+        state.set_providers(providers);
+    }
+    if changed.contains(&"rate_limit") {
+        state.rate_limiter.reconfigure(rate_limit);
+    }
+    info!(?changed, "Config reloaded");
+    Ok(changed)
+}
@@ -0,0 +1,61 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Request IDs, taken from the `X-Request-Id` header or generated, to
+//! correlate a request across the logs of all replicas and its response.
+
+use axum::{
+    http::{HeaderName, HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use tracing::{info_span, Instrument};
+
+/// The header a request ID is read from and returned in.
+pub const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Maximum length of a request ID taken from a client.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    /// The ID of the request being handled by the current task.
+    pub(crate) static REQUEST_ID: String;
+}
+
+/// Returns the ID of the request being handled, if any.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Middleware that assigns the request an ID, propagated from the
+/// `X-Request-Id` header if valid and generated otherwise. The ID is recorded
+/// on the span of the request, available through [current_request_id] while
+/// handling it and returned in the `X-Request-Id` response header.
+pub async fn request_id<B>(request: Request<B>, next: Next<B>) -> Response {
+    let id = request
+        .headers()
+        .get(&X_REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LEN
+                && id.bytes().all(|b| b.is_ascii_graphic())
+        })
+        .map(|id| id.to_string())
+        .unwrap_or_else(|| format!("{:032x}", rand::random::<u128>()));
+
+    let span = info_span!(
+        "request",
+        request_id = %id,
+        method = %request.method(),
+        path = %request.uri().path(),
+    );
+    let mut response = REQUEST_ID
+        .scope(id.clone(), next.run(request).instrument(span))
+        .await;
+    // The ID only holds visible ASCII, so it is a valid header value.
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(X_REQUEST_ID, value);
+    }
+    response
+}
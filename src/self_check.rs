@@ -0,0 +1,72 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! A startup self-check of the zkLogin verifying keys against a known test
+//! vector.
+
+use crate::{verify_zklogin_signature, VerifyError, VerifyOptions};
+use fastcrypto::encoding::{Base64, Encoding};
+use fastcrypto_zkp::bn254::{
+    zk_login::{JwkId, JWK},
+    zk_login_api::ZkLoginEnv,
+};
+use shared_crypto::intent::IntentScope;
+use sui_types::{crypto::ToFromBytes, signature::GenericSignature};
+
+/// A zkLogin signature over [TEST_BYTES] issued by Twitch for Test env with max_epoch 10.
+pub(crate) const TEST_SIGNATURE: &str = "BQNNMTczMTgwODkxMjU5NTI0MjE3MzYzNDIyNjM3MTc5MzI3MTk0Mzc3MTc4NDQyODI0MTAxODc5NTc5ODQ3NTE5Mzk5NDI4OTgyNTEyNTBNMTEzNzM5NjY2NDU0NjkxMjI1ODIwNzQwODIyOTU5ODUzODgyNTg4NDA2ODE2MTgyNjg1OTM5NzY2OTczMjU4OTIyODA5MTU2ODEyMDcBMQMCTDU5Mzk4NzExNDczNDg4MzQ5OTczNjE3MjAxMjIyMzg5ODAxNzcxNTIzMDMyNzQzMTEwNDcyNDk5MDU5NDIzODQ5MTU3Njg2OTA4OTVMNDUzMzU2ODI3MTEzNDc4NTI3ODczMTIzNDU3MDM2MTQ4MjY1MTk5Njc0MDc5MTg4ODI4NTg2NDk2Njg4NDAzMjcxNzA0OTgxMTcwOAJNMTA1NjQzODcyODUwNzE1NTU0Njk3NTM5OTA2NjE0MTA4NDAxMTg2MzU5MjU0NjY1OTcwMzcwMTgwNTg3NzAwNDEzNDc1MTg0NjEzNjhNMTI1OTczMjM1NDcyNzc1NzkxNDQ2OTg0OTYzNzIyNDI2MTUzNjgwODU4MDEzMTMzNDMxNTU3MzU1MTEzMzAwMDM4ODQ3Njc5NTc4NTQCATEBMANNMTU3OTE1ODk0NzI1NTY4MjYyNjMyMzE2NDQ3Mjg4NzMzMzc2MjkwMTUyNjk5ODQ2OTk0MDQwNzM2MjM2MDMzNTI1Mzc2Nzg4MTMxNzFMNDU0Nzg2NjQ5OTI0ODg4MTQ0OTY3NjE2MTE1ODAyNDc0ODA2MDQ4NTM3MzI1MDAyOTQyMzkwNDExMzAxNzQyMjUzOTAzNzE2MjUyNwExMXdpYVhOeklqb2lhSFIwY0hNNkx5OXBaQzUwZDJsMFkyZ3VkSFl2YjJGMWRHZ3lJaXcCMmV5SmhiR2NpT2lKU1V6STFOaUlzSW5SNWNDSTZJa3BYVkNJc0ltdHBaQ0k2SWpFaWZRTTIwNzk0Nzg4NTU5NjIwNjY5NTk2MjA2NDU3MDIyOTY2MTc2OTg2Njg4NzI3ODc2MTI4MjIzNjI4MTEzOTE2MzgwOTI3NTAyNzM3OTExCgAAAAAAAABhAG6Bf8BLuaIEgvF8Lx2jVoRWKKRIlaLlEJxgvqwq5nDX+rvzJxYAUFd7KeQBd9upNx+CHpmINkfgj26jcHbbqAy5xu4WMO8+cRFEpkjbBruyKE9ydM++5T/87lA8waSSAA==";
+
+/// BCS encoded TransactionData signed by [TEST_SIGNATURE].
+pub(crate) const TEST_BYTES: &str = "AAABACACAgICAgICAgICAgICAgICAgICAgICAgICAgICAgICAgEBAQABAAAcpgUkGBwS5nPO79YXkjMyvaRjGS57hqxzfyd2yGtejwGbB4FfBEl+LgXSLKw6oGFBCyCGjMYZFUxCocYb6ZAnFwEAAAAAAAAAIJZw7UpW1XHubORIOaY8d2+WyBNwoJ+FEAxlsa7h7JHrHKYFJBgcEuZzzu/WF5IzMr2kYxkue4asc38ndshrXo8BAAAAAAAAABAnAAAAAAAAAA==";
+
+/// The Twitch JWK that the test signature was issued against.
+pub(crate) fn test_jwk() -> (JwkId, JWK) {
+    (
+        JwkId::new("https://id.twitch.tv/oauth2".to_string(), "1".to_string()),
+        JWK {
+            alg: "RS256".to_string(),
+            e: "AQAB".to_string(),
+            kty: "RSA".to_string(),
+            n: "6lq9MQ-q6hcxr7kOUp-tHlHtdcDsVLwVIw13iXUCvuDOeCi0VSuxCCUY6UmMjy53dX00ih2E4Y4UvlrmmurK0eG26b-HMNNAvCGsVXHU3RcRhVoHDaOwHwU72j7bpHn9XbP3Q3jebX6KIfNbei2MiR0Wyb8RZHE-aZhRYO8_-k9G2GycTpvc-2GBsP8VHLUKKfAs2B6sW3q3ymU6M0L-cFXkZ9fHkn9ejs-sqZPhMJxtBPBxoUIUQFTgv4VXTSv914f_YkNw-EjuwbgwXMvpyr06EyfImxHoxsZkFYB-qBYHtaMxTnFsZBr6fn8Ha2JqT1hoP7Z5r5wxDu3GQhKkHw".to_string(),
+        },
+    )
+}
+
+/// Verifies the test vector, which loads and prepares the Groth16 verifying
+/// keys of both envs so the first request doesn't pay for it, and checks that
+/// they behave as expected. The test vector verifies against the Test key,
+/// and must fail the proof check, and only that, against the Prod key.
+pub fn self_check() -> Result<(), String> {
+    let (jwk_id, jwk) = test_jwk();
+    let bytes = Base64::decode(TEST_BYTES).map_err(|e| e.to_string())?;
+    let sig = Base64::decode(TEST_SIGNATURE)
+        .ok()
+        .and_then(|sig| GenericSignature::from_bytes(&sig).ok())
+        .ok_or("Cannot decode the test signature")?;
+    let options = VerifyOptions {
+        jwks: vec![(jwk_id, jwk)].into_iter().collect(),
+        env: ZkLoginEnv::Test,
+        curr_epoch: 1,
+        ..Default::default()
+    };
+
+    verify_zklogin_signature(&bytes, &sig, IntentScope::TransactionData, options.clone())
+        .map_err(|e| format!("Test verifying key rejected the test vector: {:?}", e))?;
+
+    let res = verify_zklogin_signature(
+        &bytes,
+        &sig,
+        IntentScope::TransactionData,
+        VerifyOptions {
+            env: ZkLoginEnv::Prod,
+            ..options
+        },
+    );
+    match res {
+        Err(VerifyError::ProofVerifyFailed(_)) => Ok(()),
+        res => Err(format!(
+            "Unexpected result of the test vector with the Prod verifying key: {:?}",
+            res
+        )),
+    }
+}
@@ -0,0 +1,1647 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! The axum handlers and application state of the verifier HTTP server.
+
+use crate::{
+    access_log::{access_log, AccessLog},
+    attestation::{get_attestation, sign_response, Attestation},
+    cache::VerifyCache,
+    challenge::ChallengeKey,
+    concurrency::{concurrency_limit, ConcurrencyLimiter},
+    config::Config,
+    deadline,
+    drift::{get_jwk_drift, JwkDriftReport},
+    epoch::{epoch_end, get_current_epoch, tracked_epoch, EpochFetchStatus, EpochStatus},
+    jwks::{fetch_missing_jwks, FetchStatus, JwkMetadata},
+    metrics::{get_metrics, verify_outcome, Metrics},
+    ndjson::NDJSON,
+    pool::ComputePool,
+    rate_limit::{rate_limit, RateLimiter},
+    reload::{reload_config, Reloadable, Reloader},
+    request_id::{current_request_id, request_id},
+    slo::SloTracker,
+    tenant::{current_tenant, tenant_auth, with_tenant},
+    verifier::{
+        check_len, debug_zklogin_signatures, decode_binary, decode_signature,
+        derive_zklogin_addresses, parse_zklogin_signature, preflight_zklogin_signature,
+        validate_request, verify_request_ephemeral, verify_transaction_signatures,
+        verify_validated_generic_request, verify_validated_request, zklogin_addresses,
+        zklogin_jwk_ids, VerifyOptions, MAX_BYTES_LEN,
+    },
+    AdminJwksRequest, AdminJwksResponse, AdminRefreshRequest, AdminReloadResponse, BatchResult,
+    BinaryEncoding, DeriveAddressRequest, DeriveAddressResponse, DryRunResult,
+    EphemeralSignatureResponse, ErrorCode, JwkEntry, JwkSource, JwksResponse,
+    ParseSignatureRequest, ParseSignatureResponse, PayloadFormat, PreflightRequest,
+    PreflightResponse, StaleData, SuiEnv, VerifyBatchRequest, VerifyBatchResponse,
+    VerifyDebugResponse, VerifyDigestRequest, VerifyDigestResponse, VerifyError, VerifyPolicy,
+    VerifyRequest, VerifyResponse, VerifyTransactionRequest, VerifyTransactionResponse,
+};
+use arc_swap::ArcSwap;
+use axum::response::{IntoResponse, Response};
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::{ConnectInfo, DefaultBodyLimit, State},
+    http::{
+        header::{AUTHORIZATION, RETRY_AFTER},
+        Extensions, HeaderMap, Request, Version,
+    },
+    middleware::{self, Next},
+    routing::{get, post},
+    BoxError, Json, Router,
+};
+use fastcrypto::{
+    encoding::{Encoding, Hex},
+    hash::{HashFunction, Sha256},
+};
+use fastcrypto_zkp::bn254::{
+    zk_login::{JwkId, OIDCProvider, JWK},
+    zk_login_api::ZkLoginEnv,
+};
+use im::hashmap::HashMap as ImHashMap;
+use prometheus::Registry;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::SocketAddr,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use subtle::ConstantTimeEq;
+use sui_sdk::{
+    rpc_types::{
+        SuiExecutionStatus, SuiTransactionBlockEffectsAPI, SuiTransactionBlockResponseOptions,
+    },
+    SuiClient, SuiClientBuilder,
+};
+use sui_types::{
+    committee::EpochId,
+    digests::TransactionDigest,
+    signature::GenericSignature,
+    transaction::{SenderSignedData, TransactionData},
+};
+use tokio::{
+    sync::{Notify, OnceCell},
+    task::AbortHandle,
+};
+use tower_http::{
+    compression::{
+        predicate::{DefaultPredicate, NotForContentType, Predicate},
+        CompressionLayer,
+    },
+    decompression::RequestDecompressionLayer,
+    map_request_body::MapRequestBodyLayer,
+};
+use tracing::{info, warn, Instrument, Span};
+
+/// Application state that contains the seed and JWKs.
+#[derive(Clone, Debug, Default)]
+pub struct AppState {
+    /// This is the latest JWKs stored in a mapping from iss -> (kid -> JWK).
+    /// Swapped as a whole on updates, so that verifying never takes a lock.
+    pub jwks: Arc<ArcSwap<ImHashMap<JwkId, JWK>>>,
+    /// Serializes updates of `jwks`.
+    pub jwks_writer: Arc<parking_lot::Mutex<()>>,
+    /// The OIDC providers whose JWKs are fetched by the updater task.
+    pub providers: Reloadable<Vec<OIDCProvider>>,
+    /// Notifies the updater task that `providers` changed.
+    pub providers_changed: Arc<Notify>,
+    /// The server configuration.
+    pub config: Reloadable<Config>,
+    /// Reloads `config` on SIGHUP or `POST /admin/reload`.
+    pub reloader: Reloader,
+    /// Sui clients shared across requests, used to fetch the current epoch.
+    pub sui_clients: SuiClients,
+    /// The current epoch of the networks tracked in the background.
+    pub epochs: Arc<parking_lot::Mutex<HashMap<SuiEnv, EpochStatus>>>,
+    /// The outcome of the background fetches of the epoch of each tracked
+    /// network.
+    pub epoch_fetch_status: Arc<parking_lot::Mutex<HashMap<SuiEnv, EpochFetchStatus>>>,
+    /// The latest JWK drift check of each network of `jwk_drift_networks`.
+    pub jwk_drift: Arc<parking_lot::Mutex<HashMap<SuiEnv, JwkDriftReport>>>,
+    /// HTTP client used to fetch JWKs from the providers.
+    pub http_client: reqwest::Client,
+    /// Where each cached JWK came from and when it was last returned.
+    pub jwk_metadata: Arc<parking_lot::Mutex<HashMap<JwkId, JwkMetadata>>>,
+    /// The outcome of the latest JWK fetches of each provider.
+    pub fetch_status: Arc<parking_lot::Mutex<HashMap<OIDCProvider, FetchStatus>>>,
+    /// The Prometheus metrics of the server.
+    pub metrics: Metrics,
+    /// The burn rates of the SLOs of verify requests.
+    pub slos: SloTracker,
+    /// The access log, if configured.
+    pub access_log: Option<AccessLog>,
+    /// Rate limiter of the verification endpoints.
+    pub rate_limiter: RateLimiter,
+    /// Concurrency limit of the verification endpoints.
+    pub concurrency_limiter: ConcurrencyLimiter,
+    /// Cache of successful verifications.
+    pub verify_cache: VerifyCache,
+    /// The threads proofs are verified on.
+    pub compute_pool: ComputePool,
+    /// Triggers an immediate refresh of the JWKs of each provider.
+    pub refresh_triggers: Arc<parking_lot::Mutex<HashMap<OIDCProvider, Arc<Notify>>>>,
+    /// When each provider was last fetched on demand for an unknown kid.
+    pub on_demand_fetches: Arc<parking_lot::Mutex<HashMap<OIDCProvider, Instant>>>,
+    /// The key verify responses are signed with, if attestation is enabled.
+    pub attestation: Option<Arc<Attestation>>,
+    /// The key ownership challenges are tagged with.
+    pub challenge_key: ChallengeKey,
+}
+
+/// Sui clients keyed by fullnode URL, built lazily on first use so that
+/// requests reuse their connections. Each URL is built on its own, so that a
+/// fullnode that does not answer only holds up the requests that use it.
+#[derive(Clone, Default)]
+pub struct SuiClients(Arc<parking_lot::Mutex<HashMap<String, Arc<OnceCell<SuiClient>>>>>);
+
+impl std::fmt::Debug for SuiClients {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("SuiClients").finish()
+    }
+}
+
+impl SuiClients {
+    /// Returns the client for the fullnode URL, building it if needed within
+    /// `timeout`. Concurrent calls for the URL wait for the same build, and
+    /// the next call builds again if it failed.
+    pub async fn get(&self, url: &str, timeout: Duration) -> Result<SuiClient, VerifyError> {
+        let cell = self.0.lock().entry(url.to_string()).or_default().clone();
+        cell.get_or_try_init(|| async {
+            match tokio::time::timeout(timeout, SuiClientBuilder::default().build(url)).await {
+                Ok(Ok(client)) => Ok(client),
+                Ok(Err(e)) => Err(VerifyError::GetEpochError(Some(Box::new(e)))),
+                Err(e) => Err(VerifyError::GetEpochError(Some(Box::new(e)))),
+            }
+        })
+        .await
+        .cloned()
+    }
+}
+
+impl AppState {
+    /// Creates the state of a server with the config, which must be valid.
+    pub fn new(config: Config) -> Self {
+        let metrics = Metrics::new(Registry::new(), &config.metrics);
+        let state = Self {
+            providers: config
+                .oidc_providers()
+                .expect("providers are validated")
+                .into(),
+            rate_limiter: RateLimiter::new(config.rate_limit.clone()),
+            concurrency_limiter: ConcurrencyLimiter::new(config.concurrency_limit.as_ref()),
+            verify_cache: VerifyCache::new(config.verify_cache_size),
+            slos: SloTracker::new(&config.metrics.slos),
+            compute_pool: ComputePool::new(
+                config.verify_pool_size,
+                config.verify_pool_reserved_cpus,
+            ),
+            attestation: config
+                .attestation
+                .then(|| Arc::new(Attestation::generate())),
+            challenge_key: config
+                .challenge_secret
+                .as_ref()
+                .map(|secret| ChallengeKey::new(secret.as_bytes()))
+                .unwrap_or_default(),
+            access_log: config.access_log.as_ref().map(|access_log| {
+                AccessLog::open(access_log, metrics.access_log_dropped.clone())
+                    .expect("failed to open the access log")
+            }),
+            metrics,
+            config: config.into(),
+            ..Default::default()
+        };
+        state
+            .metrics
+            .providers_missing_jwks
+            .set(state.providers.load().len() as i64);
+        info!(
+            "Verifying up to {} proofs in parallel",
+            state.compute_pool.size()
+        );
+        if let Some(attestation) = &state.attestation {
+            info!(
+                public_key = %attestation.public_key(),
+                "Signing verify responses for attestation"
+            );
+        }
+        state
+    }
+
+    /// Returns the current epoch of the network, as tracked in the
+    /// background while that is current, or else fetched from its fullnodes.
+    pub async fn get_epoch(&self, network: SuiEnv) -> Result<EpochId, VerifyError> {
+        if let Some(status) = tracked_epoch(self, network) {
+            return Ok(status.epoch);
+        }
+        self.fetch_epoch_status(network)
+            .await
+            .map(|status| status.epoch)
+    }
+
+    /// Fetches the current epoch of the network, failing over to the next
+    /// configured fullnode when one errors.
+    pub async fn fetch_epoch_status(&self, network: SuiEnv) -> Result<EpochStatus, VerifyError> {
+        let start = Instant::now();
+        let result = self.fetch_epoch(network).await;
+        self.metrics
+            .epoch_fetch_latency
+            .with_label_values(&[
+                &format!("{:?}", network),
+                if result.is_ok() { "ok" } else { "error" },
+            ])
+            .observe(start.elapsed().as_secs_f64());
+        result
+    }
+
+    async fn fetch_epoch(&self, network: SuiEnv) -> Result<EpochStatus, VerifyError> {
+        let mut last_error = None;
+        for url in self.config.load().fullnode_urls(network) {
+            let timeout = Duration::from_secs(self.config.load().fullnode_timeout_secs);
+            let sui_client = match self.sui_clients.get(&url, timeout).await {
+                Ok(sui_client) => sui_client,
+                Err(e) => {
+                    warn!("Cannot connect to fullnode {}", url);
+                    last_error = Some(e);
+                    continue;
+                }
+            };
+            match tokio::time::timeout(
+                timeout,
+                sui_client.governance_api().get_latest_sui_system_state(),
+            )
+            .await
+            {
+                Ok(Ok(state)) => {
+                    return Ok(EpochStatus {
+                        epoch: state.epoch,
+                        fetched_at: SystemTime::now(),
+                        next_epoch_at: epoch_end(
+                            state.epoch_start_timestamp_ms,
+                            state.epoch_duration_ms,
+                        ),
+                    })
+                }
+                Ok(Err(e)) => {
+                    warn!("Cannot get epoch from fullnode {}: {:?}", url, e);
+                    last_error = Some(VerifyError::GetEpochError(Some(Box::new(e))));
+                }
+                Err(e) => {
+                    warn!("Timed out getting epoch from fullnode {}", url);
+                    last_error = Some(VerifyError::GetEpochError(Some(Box::new(e))));
+                }
+            }
+        }
+        Err(last_error.unwrap_or(VerifyError::GetEpochError(None)))
+    }
+
+    /// Fetches a transaction with its signatures, and the epoch it was
+    /// executed in, failing over to the next configured fullnode when one
+    /// errors.
+    pub async fn fetch_transaction(
+        &self,
+        network: SuiEnv,
+        digest: TransactionDigest,
+    ) -> Result<(SenderSignedData, EpochId), VerifyError> {
+        let error = |source: Option<Box<dyn std::error::Error + Send + Sync>>| {
+            VerifyError::GetTransactionError { digest, source }
+        };
+        let options = SuiTransactionBlockResponseOptions::new()
+            .with_raw_input()
+            .with_effects();
+        let mut last_error = None;
+        for url in self.config.load().fullnode_urls(network) {
+            let timeout = Duration::from_secs(self.config.load().fullnode_timeout_secs);
+            let sui_client = match self.sui_clients.get(&url, timeout).await {
+                Ok(sui_client) => sui_client,
+                Err(e) => {
+                    warn!("Cannot connect to fullnode {}", url);
+                    last_error = Some(error(Some(Box::new(e))));
+                    continue;
+                }
+            };
+            match tokio::time::timeout(
+                timeout,
+                sui_client
+                    .read_api()
+                    .get_transaction_with_options(digest, options.clone()),
+            )
+            .await
+            {
+                Ok(Ok(response)) => {
+                    let data: SenderSignedData = bcs::from_bytes(&response.raw_transaction)
+                        .map_err(|e| error(Some(Box::new(e))))?;
+                    let executed_epoch = response
+                        .effects
+                        .as_ref()
+                        .map(|effects| effects.executed_epoch())
+                        .ok_or_else(|| error(None))?;
+                    return Ok((data, executed_epoch));
+                }
+                Ok(Err(e)) => {
+                    warn!(
+                        "Cannot get transaction {} from fullnode {}: {:?}",
+                        digest, url, e
+                    );
+                    last_error = Some(error(Some(Box::new(e))));
+                }
+                Err(e) => {
+                    warn!(
+                        "Timed out getting transaction {} from fullnode {}",
+                        digest, url
+                    );
+                    last_error = Some(error(Some(Box::new(e))));
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| error(None)))
+    }
+
+    /// Dry runs a transaction, failing over to the next configured fullnode
+    /// when one errors.
+    pub async fn dry_run(
+        &self,
+        network: SuiEnv,
+        tx_data: TransactionData,
+    ) -> Result<DryRunResult, VerifyError> {
+        let mut last_error = None;
+        for url in self.config.load().fullnode_urls(network) {
+            let timeout = Duration::from_secs(self.config.load().fullnode_timeout_secs);
+            let sui_client = match self.sui_clients.get(&url, timeout).await {
+                Ok(sui_client) => sui_client,
+                Err(e) => {
+                    warn!("Cannot connect to fullnode {}", url);
+                    last_error = Some(VerifyError::DryRunError(Some(Box::new(e))));
+                    continue;
+                }
+            };
+            match tokio::time::timeout(
+                timeout,
+                sui_client
+                    .read_api()
+                    .dry_run_transaction_block(tx_data.clone()),
+            )
+            .await
+            {
+                Ok(Ok(response)) => {
+                    return Ok(match response.effects.status() {
+                        SuiExecutionStatus::Success => DryRunResult {
+                            success: true,
+                            error: None,
+                        },
+                        SuiExecutionStatus::Failure { error } => DryRunResult {
+                            success: false,
+                            error: Some(error.clone()),
+                        },
+                    })
+                }
+                Ok(Err(e)) => {
+                    warn!("Cannot dry run transaction on fullnode {}: {:?}", url, e);
+                    last_error = Some(VerifyError::DryRunError(Some(Box::new(e))));
+                }
+                Err(e) => {
+                    warn!("Timed out dry running transaction on fullnode {}", url);
+                    last_error = Some(VerifyError::DryRunError(Some(Box::new(e))));
+                }
+            }
+        }
+        Err(last_error.unwrap_or(VerifyError::DryRunError(None)))
+    }
+
+    /// Returns the trigger the updater of the provider waits on to refresh
+    /// its JWKs before the refresh interval has passed.
+    pub fn refresh_trigger(&self, provider: &OIDCProvider) -> Arc<Notify> {
+        self.refresh_triggers
+            .lock()
+            .entry(provider.clone())
+            .or_default()
+            .clone()
+    }
+
+    /// Applies `f` to a copy of the JWKs and publishes the result. Updates are
+    /// serialized while readers keep using the previous JWKs until then. The
+    /// verify cache is only cleared if the JWKs changed, so that refetching
+    /// the same JWKs keeps the cached results.
+    pub fn update_jwks<R>(&self, f: impl FnOnce(&mut ImHashMap<JwkId, JWK>) -> R) -> R {
+        let _guard = self.jwks_writer.lock();
+        let previous = self.jwks.load_full();
+        let mut jwks = ImHashMap::clone(&previous);
+        let result = f(&mut jwks);
+        if jwks != *previous {
+            self.jwks.store(Arc::new(jwks));
+            self.verify_cache.clear();
+        }
+        self.metrics
+            .providers_missing_jwks
+            .set(self.missing_providers().len() as i64);
+        result
+    }
+
+    /// Returns the configured providers that have no JWK in the cache yet.
+    pub fn missing_providers(&self) -> Vec<OIDCProvider> {
+        let jwks = self.jwks.load();
+        self.providers
+            .load()
+            .iter()
+            .filter(|p| {
+                let iss = p.get_config().iss;
+                !jwks.keys().any(|id| id.iss == iss)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Replaces the providers, restarting the JWK updates for the new list.
+    /// The JWKs of removed providers are dropped, so that their signatures
+    /// no longer verify.
+    pub fn set_providers(&self, providers: Vec<OIDCProvider>) {
+        let removed: Vec<OIDCProvider> = self
+            .providers
+            .load()
+            .iter()
+            .filter(|p| !providers.contains(p))
+            .cloned()
+            .collect();
+        self.providers.store(providers);
+        let removed_iss: Vec<String> = removed.iter().map(|p| p.get_config().iss).collect();
+        self.update_jwks(|jwks| jwks.retain(|id, _| !removed_iss.contains(&id.iss)));
+        self.jwk_metadata
+            .lock()
+            .retain(|id, _| !removed_iss.contains(&id.iss));
+        self.fetch_status.lock().retain(|p, _| !removed.contains(p));
+        self.providers_changed.notify_one();
+    }
+
+    /// Waits until JWKs have been fetched for all configured providers, or
+    /// until the timeout elapses, and returns the providers still missing.
+    pub async fn wait_for_jwks(&self, timeout: Duration) -> Vec<OIDCProvider> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let missing = self.missing_providers();
+            if missing.is_empty() || Instant::now() >= deadline {
+                return missing;
+            }
+            tokio::time::sleep(JWK_WAIT_POLL_INTERVAL).await;
+        }
+    }
+}
+
+/// How often [AppState::wait_for_jwks] checks for the JWKs.
+const JWK_WAIT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The OIDC providers fetched by default.
+pub fn default_providers() -> Vec<OIDCProvider> {
+    vec![
+        OIDCProvider::Facebook,
+        OIDCProvider::Google,
+        OIDCProvider::Twitch,
+        OIDCProvider::Kakao,
+        OIDCProvider::Apple,
+        OIDCProvider::Slack,
+    ]
+}
+
+/// Response to the readiness probe.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReadyResponse {
+    /// Whether JWKs have been fetched for all configured providers.
+    pub ready: bool,
+    /// The configured providers that have no JWK in the cache yet.
+    pub missing_providers: Vec<String>,
+}
+
+/// Response to get the version.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VersionResponse {
+    /// The version of the crate.
+    pub version: String,
+    /// The git commit the verifier was built from, or "unknown".
+    pub git_commit: String,
+    /// When the verifier was built, in seconds since the Unix epoch.
+    pub build_timestamp: u64,
+    /// The versions of the crypto dependencies, with the commit for git
+    /// dependencies, e.g. "0.1.2+ea66012b".
+    pub dependencies: BTreeMap<String, String>,
+}
+
+impl VersionResponse {
+    /// The version of this build, as recorded by the build script.
+    pub fn current() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            git_commit: env!("ZKLOGIN_VERIFIER_GIT_COMMIT").to_string(),
+            build_timestamp: env!("ZKLOGIN_VERIFIER_BUILD_TIMESTAMP")
+                .parse()
+                .unwrap_or_default(),
+            dependencies: BTreeMap::from([
+                (
+                    "fastcrypto".to_string(),
+                    env!("ZKLOGIN_VERIFIER_FASTCRYPTO_VERSION").to_string(),
+                ),
+                (
+                    "fastcrypto-zkp".to_string(),
+                    env!("ZKLOGIN_VERIFIER_FASTCRYPTO_ZKP_VERSION").to_string(),
+                ),
+                (
+                    "sui-types".to_string(),
+                    env!("ZKLOGIN_VERIFIER_SUI_TYPES_VERSION").to_string(),
+                ),
+            ]),
+        }
+    }
+}
+
+/// What a deployment supports and the policy in effect, so that client SDKs
+/// can feature-detect instead of assuming.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CapabilitiesResponse {
+    /// The version of the verifier.
+    pub version: String,
+    /// The intent scopes that can be verified, by name.
+    pub intent_scopes: Vec<String>,
+    /// The networks signatures can be verified on.
+    pub networks: Vec<SuiEnv>,
+    /// The network of requests that don't set one.
+    pub default_network: SuiEnv,
+    /// The networks whose current epoch is tracked in the background.
+    pub tracked_networks: Vec<SuiEnv>,
+    /// The enabled OIDC providers, by name, e.g. "Google".
+    pub providers: Vec<String>,
+    /// The iss values whose signatures are accepted, or none if any iss is.
+    pub allowed_issuers: Option<Vec<String>>,
+    /// The accepted formats of `bytes`.
+    pub formats: Vec<PayloadFormat>,
+    /// The accepted text encodings of signatures and bytes.
+    pub encodings: Vec<BinaryEncoding>,
+    /// The verification policy, which requests can only tighten.
+    pub verify_policy: VerifyPolicy,
+    /// The number of epochs a fetched current epoch may be off by.
+    pub epoch_tolerance: u64,
+    /// Whether requests may carry the JWK to verify against.
+    pub allow_inline_jwks: bool,
+    /// Whether `/verify_debug` is served.
+    pub debug_endpoint: bool,
+    /// The maximum number of requests in a batch verify.
+    pub max_batch_size: usize,
+    /// Whether verify responses are signed and `/attestation` is served.
+    pub attestation: bool,
+}
+
+/// Response to get the JWK status.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JwksStatusResponse {
+    /// The status of each configured provider.
+    pub providers: Vec<ProviderJwksStatus>,
+}
+
+/// The freshness of the JWKs of a provider.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProviderJwksStatus {
+    /// The name of the provider, e.g. "Google".
+    pub provider: String,
+    /// The iss of the provider.
+    pub iss: String,
+    /// When the JWKs were last fetched successfully, in Unix seconds.
+    pub last_success: Option<u64>,
+    /// The error of the latest fetch, if it failed.
+    pub last_error: Option<String>,
+    /// The number of JWKs of the provider in the cache.
+    pub keys: usize,
+    /// Whether the JWKs have not been fetched successfully within the refresh
+    /// interval, with some slack.
+    pub overdue: bool,
+}
+
+/// Returns the JSON body of an error response, with the ID of the request
+/// being handled if any.
+pub(crate) fn error_body(error: impl Into<String>, code: ErrorCode) -> Json<serde_json::Value> {
+    let mut body = json!({
+        "error": error.into(),
+        "code": code,
+    });
+    if let Some(request_id) = current_request_id() {
+        body["request_id"] = request_id.into();
+    }
+    Json(body)
+}
+
+impl IntoResponse for VerifyError {
+    fn into_response(self) -> Response {
+        let code = self.code();
+        // Lets clients tell users which kind of signature they sent, or
+        // which fields to fix.
+        let (scheme, fields) = match &self {
+            VerifyError::NotZkLoginSignature(scheme) => (Some(scheme.clone()), None),
+            VerifyError::InvalidFields(fields) => (None, Some(fields.clone())),
+            _ => (None, None),
+        };
+        let retry_after = match &self {
+            VerifyError::RateLimited { retry_after, .. } => {
+                Some(retry_after.as_secs_f64().ceil() as u64)
+            }
+            _ => None,
+        };
+        let (status, error_message) = self.status_and_message();
+        let mut body = error_body(error_message, code);
+        if let Some(scheme) = scheme {
+            body.0["scheme"] = scheme.into();
+        }
+        if let Some(fields) = fields {
+            body.0["fields"] = json!(fields);
+        }
+        match retry_after {
+            Some(retry_after) => {
+                (status, [(RETRY_AFTER, retry_after.to_string())], body).into_response()
+            }
+            None => (status, body).into_response(),
+        }
+    }
+}
+
+impl VerifyError {
+    /// Returns the HTTP status and the message of the error.
+    pub(crate) fn status_and_message(self) -> (StatusCode, String) {
+        let status = match self {
+            VerifyError::BatchTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            // Also when validating finds an oversized field first.
+            _ if self.code() == ErrorCode::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            VerifyError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            // The fullnodes failed, not the client, so it may retry.
+            VerifyError::GetEpochError(_)
+            | VerifyError::GetTransactionError { .. }
+            | VerifyError::DryRunError(_) => StatusCode::BAD_GATEWAY,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        (status, self.to_string())
+    }
+}
+
+/// Returns the routes of the verifier with their middleware, serving `state`.
+pub fn router(state: Arc<AppState>) -> Router {
+    let mut app = Router::new()
+        .route("/verify", post(verify))
+        .route("/verify_generic", post(verify_generic))
+        .route("/verify_ephemeral", post(verify_ephemeral))
+        .route("/verify_transaction", post(verify_transaction))
+        .route("/verify_digest", post(verify_digest))
+        .route(
+            "/verify_batch",
+            post(verify_batch).layer(DefaultBodyLimit::max(
+                state.config.load().max_batch_body_bytes,
+            )),
+        )
+        .route("/parse_signature", post(parse_signature))
+        .route("/derive_address", post(derive_address))
+        .route("/preflight", post(preflight))
+        .route("/challenge", post(crate::challenge::challenge))
+        .route(
+            "/verify_challenge",
+            post(crate::challenge::verify_challenge),
+        )
+        .route("/verify_ws", get(crate::ws::verify_ws))
+        .route("/verify_stream", post(crate::ndjson::verify_stream))
+        .route("/epoch", get(get_current_epoch));
+    if state.config.load().debug_endpoint {
+        app = app.route("/verify_debug", post(verify_debug));
+    }
+    #[cfg(feature = "graphql")]
+    {
+        app = app.route_service("/graphql", crate::graphql::service(state.clone()));
+    }
+    if state.config.load().attestation {
+        app = app.route_layer(middleware::from_fn_with_state(state.clone(), sign_response));
+    }
+    if state.config.load().concurrency_limit.is_some() {
+        app = app.route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            concurrency_limit,
+        ));
+    }
+    let mut app = app
+        // Only the routes above are rate limited and need the API key of a
+        // tenant, if tenants are configured.
+        .route_layer(middleware::from_fn_with_state(state.clone(), rate_limit))
+        .route_layer(middleware::from_fn_with_state(state.clone(), tenant_auth))
+        .route("/", get(ping))
+        .route("/health", get(health))
+        .route("/version", get(get_version))
+        .route("/capabilities", get(get_capabilities))
+        .route("/attestation", get(get_attestation))
+        .route("/ready", get(ready))
+        .route("/jwks", get(get_jwks))
+        .route("/jwks/status", get(get_jwks_status))
+        .route("/jwks/drift", get(get_jwk_drift))
+        .route("/metrics", get(get_metrics));
+    if state.config.load().admin_token.is_some() {
+        app = app
+            .route("/admin/jwks", post(admin_jwks))
+            .route("/admin/jwks/refresh", post(admin_refresh_jwks))
+            .route("/admin/reload", post(admin_reload));
+    }
+    let compression = state.config.load().http.compression;
+    let mut app = app
+        .layer(MapRequestBodyLayer::new(into_body))
+        .layer(DefaultBodyLimit::max(state.config.load().max_body_bytes))
+        .layer(
+            RequestDecompressionLayer::new()
+                .gzip(compression)
+                .br(compression),
+        )
+        .layer(
+            CompressionLayer::new()
+                .gzip(compression)
+                .br(compression)
+                // The encoder would hold streamed results back until its
+                // buffer fills.
+                .compress_when(
+                    DefaultPredicate::new()
+                        .and(NotForContentType::new(NDJSON))
+                        .and(not_upgrade),
+                ),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            request_timeout,
+        ));
+    if state.access_log.is_some() {
+        // Inside the request ID middleware, whose ID it logs, and outside the
+        // request timeout, whose 503s it logs.
+        app = app.layer(middleware::from_fn_with_state(state.clone(), access_log));
+    }
+    let mut app = app.layer(middleware::from_fn(request_id));
+    if let Some(cors) = &state.config.load().cors {
+        app = app.layer(cors.layer().expect("CORS config is validated"));
+    }
+    app.with_state(state)
+}
+
+/// Turns the decompressed request body back into a [Body], so that
+/// middleware reading it, see [sign_response], can pass it on.
+fn into_body<B>(body: B) -> Body
+where
+    B: HttpBody<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    Body::wrap_stream(futures::stream::unfold(
+        Box::pin(body),
+        |mut body| async move { body.data().await.map(|chunk| (chunk, body)) },
+    ))
+}
+
+/// Whether the response is not a protocol upgrade, e.g. to a WebSocket,
+/// which must not be compressed.
+fn not_upgrade(status: StatusCode, _: Version, _: &HeaderMap, _: &Extensions) -> bool {
+    status != StatusCode::SWITCHING_PROTOCOLS
+}
+
+async fn ping() -> &'static str {
+    "Pong!"
+}
+
+/// Middleware that answers requests not handled within the request timeout
+/// with 503, so that a hung dependency cannot hold them open indefinitely.
+/// Requests not handled by the deadline set by the caller are answered with
+/// 504 instead, and stop being worked on.
+pub async fn request_timeout<B>(
+    State(state): State<Arc<AppState>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let timeout = Duration::from_secs(state.config.load().request_timeout_secs);
+    let time_left = match deadline::time_left(request.headers(), SystemTime::now()) {
+        Ok(time_left) => time_left,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                error_body(e, ErrorCode::InvalidRequest),
+            )
+                .into_response()
+        }
+    };
+    let deadline_exceeded = || {
+        (
+            StatusCode::GATEWAY_TIMEOUT,
+            error_body("Request deadline exceeded", ErrorCode::DeadlineExceeded),
+        )
+            .into_response()
+    };
+    match time_left {
+        Some(time_left) if time_left.is_zero() => {
+            warn!("Request deadline passed before handling");
+            deadline_exceeded()
+        }
+        Some(time_left) if time_left < timeout => {
+            match tokio::time::timeout(time_left, next.run(request)).await {
+                Ok(response) => response,
+                Err(_) => {
+                    warn!("Request deadline exceeded after {:?}", time_left);
+                    deadline_exceeded()
+                }
+            }
+        }
+        _ => match tokio::time::timeout(timeout, next.run(request)).await {
+            Ok(response) => response,
+            Err(_) => {
+                warn!("Request timed out after {:?}", timeout);
+                (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    error_body("Request timed out", ErrorCode::RequestTimeout),
+                )
+                    .into_response()
+            }
+        },
+    }
+}
+
+pub async fn verify(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<VerifyRequest>,
+) -> Result<Json<VerifyResponse>, VerifyError> {
+    verify_observed(&state, &payload, false).await.map(Json)
+}
+
+/// Verifies a signature of any scheme, e.g. Ed25519, Secp256k1, Secp256r1,
+/// multisig or zkLogin, for the author of the intent. The request is as for
+/// `/verify`.
+pub async fn verify_generic(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<VerifyRequest>,
+) -> Result<Json<VerifyResponse>, VerifyError> {
+    verify_observed(&state, &payload, true).await.map(Json)
+}
+
+/// Verifies the request as `/verify` does, and returns the result next to the
+/// decoded public inputs of its zkLogin signatures and the JWKs selected for
+/// them. Failures are part of the response, which is always a 200.
+pub async fn verify_debug(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<VerifyRequest>,
+) -> Json<VerifyDebugResponse> {
+    let network = request_network(&state, payload.network);
+    let (_, env) = network.get_params();
+    let result = verify_observed(&state, &payload, false).await;
+    // After verifying, which may have fetched missing JWKs.
+    let signatures = debug_zklogin_signatures(&payload, &state.jwks.load()).unwrap_or_default();
+    Json(VerifyDebugResponse {
+        result: BatchResult::from(result),
+        network,
+        env: format!("{:?}", env),
+        signatures,
+    })
+}
+
+/// Verifies the payload, logging the request and recording its latency.
+async fn verify_observed(
+    state: &AppState,
+    payload: &VerifyRequest,
+    any_scheme: bool,
+) -> Result<VerifyResponse, VerifyError> {
+    let jwk_ids = request_jwk_ids(payload);
+    info!(
+        signature_hash = %short_hash(&payload.signature),
+        signature_len = payload.signature.len(),
+        bytes_hash = %short_hash(&payload.bytes),
+        bytes_len = payload.bytes.len(),
+        ?jwk_ids,
+        any_scheme,
+        "verify called"
+    );
+    if state.config.load().log_payloads {
+        info!("verify payload: {:?}", payload);
+    }
+
+    let start = Instant::now();
+    let result = verify_payload_with(state, payload, &jwk_ids, any_scheme).await;
+    let provider = jwk_ids
+        .first()
+        .and_then(|id| OIDCProvider::from_iss(&id.iss).ok())
+        .map_or("unknown".to_string(), |p| p.to_string());
+    let (outcome, reason) = verify_outcome(&result);
+    if let Err(e) = &result {
+        // Marked as failed so that log sampling keeps it.
+        info!(failed = true, outcome, reason, "verify failed: {}", e);
+    }
+    let latency = start.elapsed();
+    state
+        .metrics
+        .verify_latency
+        .with_label_values(&[&provider, &format!("{:?}", payload.intent_scope), outcome])
+        .observe(latency.as_secs_f64());
+    state.slos.record(
+        &state.metrics,
+        latency,
+        outcome,
+        unix_secs(SystemTime::now()),
+    );
+    result
+}
+
+/// Verifies the payload of a verify request, fetching the current epoch and
+/// missing JWKs as needed.
+pub(crate) async fn verify_payload(
+    state: &AppState,
+    payload: &VerifyRequest,
+    jwk_ids: &[JwkId],
+) -> Result<VerifyResponse, VerifyError> {
+    verify_payload_with(state, payload, jwk_ids, false).await
+}
+
+/// Like [verify_payload], also accepting signatures of other schemes than
+/// zkLogin if `any_scheme` is set. Counts the result by its reason.
+async fn verify_payload_with(
+    state: &AppState,
+    payload: &VerifyRequest,
+    jwk_ids: &[JwkId],
+    any_scheme: bool,
+) -> Result<VerifyResponse, VerifyError> {
+    let result = verify_payload_inner(state, payload, jwk_ids, any_scheme).await;
+    let (outcome, reason) = verify_outcome(&result);
+    state
+        .metrics
+        .verify_results
+        .with_label_values(&[outcome, reason])
+        .inc();
+    result
+}
+
+async fn verify_payload_inner(
+    state: &AppState,
+    payload: &VerifyRequest,
+    jwk_ids: &[JwkId],
+    any_scheme: bool,
+) -> Result<VerifyResponse, VerifyError> {
+    // Reject malformed requests before fetching anything for them. The
+    // signature and bytes decoded by validating are used from then on.
+    let decoded = Arc::new(validate_request(payload)?);
+    check_allowed_issuers(state, jwk_ids)?;
+    check_address_rate_limit(state, &decoded.0)?;
+
+    let network = request_network(state, payload.network);
+    let (_, env) = network.get_params();
+
+    // Use payload.curr_epoch if provided, otherwise fetch current epoch
+    // from payload.network (default to the default network if not provided). A
+    // historical epoch, which is validated not to come with curr_epoch, is
+    // checked against the current epoch.
+    let (curr_epoch, mut chain_epoch) = match (payload.historical_epoch, payload.curr_epoch) {
+        (Some(historical_epoch), _) => {
+            let chain_epoch = state.get_epoch(network).await?;
+            if historical_epoch > chain_epoch {
+                return Err(VerifyError::InvalidHistoricalEpoch(format!(
+                    "Historical epoch {} is after the current epoch {}",
+                    historical_epoch, chain_epoch
+                )));
+            }
+            (historical_epoch, Some(chain_epoch))
+        }
+        (None, Some(curr_epoch)) => (curr_epoch, None),
+        (None, None) => (state.get_epoch(network).await?, None),
+    };
+    let fetched_epoch = payload.curr_epoch.is_none() && payload.historical_epoch.is_none();
+    info!("curr_epoch: {:?}", curr_epoch);
+
+    if payload.jwk.is_some() {
+        if !state.config.load().allow_inline_jwks {
+            return Err(VerifyError::InlineJwkNotAllowed);
+        }
+    } else {
+        // Fetch the JWKs of a provider that rotated in a key since the last
+        // scheduled refresh, instead of failing until the next one.
+        fetch_missing_jwks(state, jwk_ids).await;
+    }
+
+    let mut result = verify_at_epoch(state, payload, &decoded, env, curr_epoch, any_scheme).await;
+    // A fetched epoch may be behind or ahead of the epoch the client signed
+    // at around an epoch change, so retry at the nearest epoch within the
+    // tolerance that the signature verifies at.
+    if fetched_epoch {
+        let tolerance = state.config.load().epoch_tolerance;
+        if let Some(epoch) = result
+            .as_ref()
+            .err()
+            .and_then(|e| tolerated_epoch(e, tolerance))
+        {
+            info!(curr_epoch, epoch, "Retrying within the epoch tolerance");
+            result = verify_at_epoch(state, payload, &decoded, env, epoch, any_scheme).await;
+            chain_epoch = Some(curr_epoch);
+        }
+    }
+    let mut response = result?;
+    response.chain_epoch = chain_epoch;
+    // Inline JWKs and epochs of the request are as fresh as the client made
+    // them.
+    response.stale_data = stale_data(
+        state,
+        response.iss.as_deref().filter(|_| payload.jwk.is_none()),
+        payload.curr_epoch.is_none().then_some(network),
+    );
+    Ok(response)
+}
+
+/// Returns the ages of the JWKs of the provider with `iss` and of the
+/// tracked epoch of `network` that are over their staleness thresholds.
+fn stale_data(state: &AppState, iss: Option<&str>, network: Option<SuiEnv>) -> Option<StaleData> {
+    let config = state.config.load();
+    let now = SystemTime::now();
+    let age = |time: SystemTime| now.duration_since(time).unwrap_or_default().as_secs();
+    let jwks_age_secs = config.stale_jwks_after_secs.and_then(|threshold| {
+        let provider = OIDCProvider::from_iss(iss?).ok()?;
+        let last_success = state.fetch_status.lock().get(&provider)?.last_success?;
+        Some(age(last_success)).filter(|age| *age > threshold)
+    });
+    let epoch_age_secs = config.stale_epoch_after_secs.and_then(|threshold| {
+        let fetched_at = state.epochs.lock().get(&network?)?.fetched_at;
+        Some(age(fetched_at)).filter(|age| *age > threshold)
+    });
+    (jwks_age_secs.is_some() || epoch_age_secs.is_some()).then_some(StaleData {
+        jwks_age_secs,
+        epoch_age_secs,
+    })
+}
+
+/// Verifies the payload, with its signature and bytes as decoded by
+/// validating it, at `curr_epoch`, answering from the verification cache if
+/// possible.
+async fn verify_at_epoch(
+    state: &AppState,
+    payload: &VerifyRequest,
+    decoded: &Arc<(GenericSignature, Vec<u8>)>,
+    env: ZkLoginEnv,
+    curr_epoch: EpochId,
+    any_scheme: bool,
+) -> Result<VerifyResponse, VerifyError> {
+    let cache_key = VerifyCache::key(
+        payload,
+        env,
+        curr_epoch,
+        state.config.load().verify_policy,
+        any_scheme,
+    );
+    if let Some(response) = state.verify_cache.get(&cache_key) {
+        state.metrics.verify_cache_hits.inc();
+        return Ok(response);
+    }
+    let parsed: ImHashMap<JwkId, JWK> = ImHashMap::clone(&state.jwks.load());
+    let (payload, decoded) = (payload.clone(), decoded.clone());
+    let policy = state.config.load().verify_policy;
+    let verify = if any_scheme {
+        verify_validated_generic_request
+    } else {
+        verify_validated_request
+    };
+    let response = state
+        .compute_pool
+        .run(move || {
+            let (sig, bytes) = &*decoded;
+            verify(&payload, sig, bytes, parsed, env, curr_epoch, policy)
+        })
+        .await?;
+    state.verify_cache.insert(cache_key, response.clone());
+    Ok(response)
+}
+
+/// Returns the epoch to retry at if the error is only due to the current
+/// epoch, and the signature verifies at an epoch at most `tolerance` epochs
+/// away from it.
+fn tolerated_epoch(error: &VerifyError, tolerance: u64) -> Option<EpochId> {
+    match *error {
+        VerifyError::SignatureExpired {
+            max_epoch,
+            curr_epoch,
+        } if curr_epoch - max_epoch <= tolerance => Some(max_epoch),
+        VerifyError::MaxEpochTooFar {
+            max_epoch,
+            curr_epoch,
+            max_delta,
+        } if max_epoch - max_delta - curr_epoch <= tolerance => Some(max_epoch - max_delta),
+        _ => None,
+    }
+}
+
+/// Returns the ids of the JWKs the signature of the request refers to, or
+/// none if it cannot be decoded.
+pub(crate) fn request_jwk_ids(payload: &VerifyRequest) -> Vec<JwkId> {
+    decode_signature(&payload.signature, payload.encoding.unwrap_or_default())
+        .map(|sig| zklogin_jwk_ids(&sig))
+        .unwrap_or_default()
+}
+
+/// Verifies a batch of requests in parallel on the compute pool, returning a
+/// result per request in the order of the batch.
+pub async fn verify_batch(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(payload): Json<VerifyBatchRequest>,
+) -> Result<Json<VerifyBatchResponse>, VerifyError> {
+    let len = payload.requests.len();
+    if len > state.config.load().max_batch_size {
+        return Err(VerifyError::BatchTooLarge {
+            len,
+            max: state.config.load().max_batch_size,
+        });
+    }
+    info!(len, "verify_batch called");
+
+    let ip = state.rate_limiter.client_ip(&headers, peer);
+    let tenant = current_tenant();
+    let now = Instant::now();
+    let tasks: Vec<_> = payload
+        .requests
+        .into_iter()
+        .enumerate()
+        .map(|(i, request)| {
+            // The rate limit middleware took a token for the first request,
+            // and each other one takes a token as a request of its own would.
+            if i > 0
+                && state
+                    .rate_limiter
+                    .check_with_tenant(ip, tenant.as_deref(), now)
+                    .is_err()
+            {
+                return None;
+            }
+            let state = state.clone();
+            Some(tokio::spawn(
+                with_tenant(tenant.clone(), async move {
+                    let jwk_ids = request_jwk_ids(&request);
+                    verify_payload(&state, &request, &jwk_ids).await
+                })
+                .instrument(Span::current()),
+            ))
+        })
+        .collect();
+    // Stop verifying the batch if the request is dropped, e.g. past its
+    // deadline.
+    let _abort = AbortOnDrop(
+        tasks
+            .iter()
+            .flatten()
+            .map(|task| task.abort_handle())
+            .collect(),
+    );
+    let mut results = Vec::with_capacity(len);
+    for task in tasks {
+        results.push(match task {
+            Some(task) => BatchResult::from(task.await.expect("verification panicked")),
+            None => BatchResult::Failed {
+                error: "Too many requests".to_string(),
+                code: ErrorCode::RateLimited,
+            },
+        });
+    }
+    Ok(Json(VerifyBatchResponse { results }))
+}
+
+/// Aborts the tasks when dropped.
+struct AbortOnDrop(Vec<AbortHandle>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.iter().for_each(|task| task.abort());
+    }
+}
+
+/// Checks that all JWKs are of an allowed iss, if an allowlist is configured.
+fn check_allowed_issuers(state: &AppState, jwk_ids: &[JwkId]) -> Result<(), VerifyError> {
+    let tenant = current_tenant();
+    let allowlists = [
+        state.config.load().allowed_issuers.clone(),
+        tenant.and_then(|tenant| tenant.allowed_issuers.clone()),
+    ];
+    for allowed_issuers in allowlists.iter().flatten() {
+        if let Some(id) = jwk_ids.iter().find(|id| !allowed_issuers.contains(&id.iss)) {
+            return Err(VerifyError::IssuerNotAllowed(id.iss.clone()));
+        }
+    }
+    Ok(())
+}
+
+/// Takes a token for each zkLogin address signing the request, standalone or
+/// as a multisig participant, if addresses are rate limited.
+fn check_address_rate_limit(
+    state: &AppState,
+    signature: &GenericSignature,
+) -> Result<(), VerifyError> {
+    if !state.rate_limiter.limits_addresses() {
+        return Ok(());
+    }
+    let now = Instant::now();
+    for address in zklogin_addresses(signature) {
+        state
+            .rate_limiter
+            .check_address(address, now)
+            .map_err(|retry_after| VerifyError::RateLimited {
+                address,
+                retry_after,
+            })?;
+    }
+    Ok(())
+}
+
+/// Returns the network of a request, defaulting to the network of the
+/// tenant and then to the configured default network.
+pub(crate) fn request_network(state: &AppState, network: Option<SuiEnv>) -> SuiEnv {
+    network
+        .or_else(|| current_tenant().and_then(|tenant| tenant.default_network))
+        .unwrap_or(state.config.load().default_network)
+}
+
+/// Verifies every signature on a transaction, of any scheme, and returns a
+/// verdict per signature.
+pub async fn verify_transaction(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<VerifyTransactionRequest>,
+) -> Result<Json<VerifyTransactionResponse>, VerifyError> {
+    let encoding = payload.encoding.unwrap_or_default();
+    let signatures = payload
+        .signatures
+        .iter()
+        .map(|sig| decode_signature(sig, encoding))
+        .collect::<Result<Vec<_>, _>>()?;
+    check_len("bytes", &payload.bytes, MAX_BYTES_LEN)?;
+    let bytes =
+        decode_binary(&payload.bytes, encoding).map_err(|source| VerifyError::InvalidEncoding {
+            field: "bytes",
+            source,
+        })?;
+    let jwk_ids: Vec<JwkId> = signatures.iter().flat_map(zklogin_jwk_ids).collect();
+    info!(
+        bytes_hash = %short_hash(&payload.bytes),
+        signatures = signatures.len(),
+        ?jwk_ids,
+        "verify_transaction called"
+    );
+    check_allowed_issuers(&state, &jwk_ids)?;
+
+    let network = request_network(&state, payload.network);
+    let (_, env) = network.get_params();
+    let curr_epoch = match payload.curr_epoch {
+        Some(curr_epoch) => curr_epoch,
+        None => state.get_epoch(network).await?,
+    };
+    fetch_missing_jwks(&state, &jwk_ids).await;
+
+    let options = VerifyOptions {
+        jwks: ImHashMap::clone(&state.jwks.load()),
+        env,
+        curr_epoch,
+        author: None,
+        sponsor: false,
+        policy: state.config.load().verify_policy,
+        intent: None,
+    };
+    let dry_run_bytes = payload.dry_run.unwrap_or_default().then(|| bytes.clone());
+    let mut response = state
+        .compute_pool
+        .run(move || verify_transaction_signatures(&bytes, &signatures, options))
+        .await?;
+    // Only a transaction validators would accept is worth dry running.
+    if let Some(bytes) = dry_run_bytes.filter(|_| response.is_verified) {
+        let tx_data = bcs::from_bytes(&bytes).map_err(VerifyError::BcsDecodeError)?;
+        response.dry_run = Some(state.dry_run(network, tx_data).await?);
+    }
+    Ok(Json(response))
+}
+
+/// Fetches a transaction by its digest and verifies all its signatures as
+/// `/verify_transaction` does, at the epoch it was executed in unless
+/// `curr_epoch` is given.
+pub async fn verify_digest(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<VerifyDigestRequest>,
+) -> Result<Json<VerifyDigestResponse>, VerifyError> {
+    let network = request_network(&state, payload.network);
+    info!(digest = %payload.digest, ?network, "verify_digest called");
+    let (data, executed_epoch) = state.fetch_transaction(network, payload.digest).await?;
+    let signatures = data.tx_signatures().to_vec();
+    let jwk_ids: Vec<JwkId> = signatures.iter().flat_map(zklogin_jwk_ids).collect();
+    check_allowed_issuers(&state, &jwk_ids)?;
+    let bytes = bcs::to_bytes(data.transaction_data()).map_err(VerifyError::BcsEncodeError)?;
+
+    let (_, env) = network.get_params();
+    let curr_epoch = payload.curr_epoch.unwrap_or(executed_epoch);
+    fetch_missing_jwks(&state, &jwk_ids).await;
+    let options = VerifyOptions {
+        jwks: ImHashMap::clone(&state.jwks.load()),
+        env,
+        curr_epoch,
+        author: None,
+        sponsor: false,
+        policy: state.config.load().verify_policy,
+        intent: None,
+    };
+    let transaction = state
+        .compute_pool
+        .run(move || verify_transaction_signatures(&bytes, &signatures, options))
+        .await?;
+    Ok(Json(VerifyDigestResponse {
+        executed_epoch,
+        curr_epoch,
+        transaction,
+    }))
+}
+
+/// Returns the time in Unix seconds.
+pub(crate) fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// Returns a short hash of `data`, to correlate requests in the logs without
+/// logging their contents.
+fn short_hash(data: &str) -> String {
+    Hex::encode(&Sha256::digest(data.as_bytes()).digest[..8])
+}
+
+/// Inserts the JWKs in the request into the cache. Requires the configured
+/// admin token as a bearer token.
+pub async fn admin_jwks(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<AdminJwksRequest>,
+) -> Result<Json<AdminJwksResponse>, (StatusCode, Json<serde_json::Value>)> {
+    check_admin_token(&state, &headers)?;
+
+    let keys = payload.into_jwks();
+    let inserted = keys.len();
+    let now = Instant::now();
+    state.update_jwks(|jwks| {
+        let mut metadata = state.jwk_metadata.lock();
+        for (jwk_id, jwk) in keys {
+            info!("{:?} JWK inserted by admin: {:?}", &jwk_id, jwk);
+            if jwks.get(&jwk_id) == Some(&jwk) {
+                metadata
+                    .entry(jwk_id)
+                    .and_modify(|m| m.confirm(JwkSource::Admin, now))
+                    .or_insert_with(|| JwkMetadata::new(JwkSource::Admin, now));
+            } else {
+                jwks.insert(jwk_id.clone(), jwk);
+                metadata.insert(jwk_id, JwkMetadata::new(JwkSource::Admin, now));
+            }
+        }
+    });
+    Ok(Json(AdminJwksResponse { inserted }))
+}
+
+/// Triggers an immediate refresh of the JWKs of a configured provider.
+/// Requires the configured admin token as a bearer token.
+pub async fn admin_refresh_jwks(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<AdminRefreshRequest>,
+) -> Result<StatusCode, (StatusCode, Json<serde_json::Value>)> {
+    check_admin_token(&state, &headers)?;
+
+    match OIDCProvider::from_str(&payload.provider) {
+        Ok(provider) if state.providers.load().contains(&provider) => {
+            info!("Refresh of provider {:?} requested by admin", provider);
+            state.refresh_trigger(&provider).notify_one();
+            Ok(StatusCode::ACCEPTED)
+        }
+        _ => Err((
+            StatusCode::BAD_REQUEST,
+            error_body(
+                format!("Provider not enabled: {}", payload.provider),
+                ErrorCode::UnknownProvider,
+            ),
+        )),
+    }
+}
+
+/// Reloads the config from the file and flags it was loaded from, applying
+/// the providers, allowed issuers, rate limits and log level. Requires the
+/// configured admin token as a bearer token.
+pub async fn admin_reload(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<AdminReloadResponse>, (StatusCode, Json<serde_json::Value>)> {
+    check_admin_token(&state, &headers)?;
+    info!("Config reload requested by admin");
+    match reload_config(&state) {
+        Ok(changed) => Ok(Json(AdminReloadResponse {
+            changed: changed.into_iter().map(str::to_string).collect(),
+        })),
+        Err(e) => Err((
+            StatusCode::BAD_REQUEST,
+            error_body(
+                format!("Cannot reload config: {}", e),
+                ErrorCode::InvalidConfig,
+            ),
+        )),
+    }
+}
+
+/// Checks that the request carries the configured admin token as a bearer
+/// token.
+fn check_admin_token(
+    state: &AppState,
+    headers: &HeaderMap,
+) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+    // Compared in constant time, so that timing does not reveal how much of
+    // the token matched.
+    let authorized = match (&state.config.load().admin_token, headers.get(AUTHORIZATION)) {
+        (Some(token), Some(value)) => bool::from(
+            value
+                .as_bytes()
+                .ct_eq(format!("Bearer {}", token).as_bytes()),
+        ),
+        _ => false,
+    };
+    if !authorized {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            error_body("Unauthorized", ErrorCode::Unauthorized),
+        ));
+    }
+    Ok(())
+}
+
+/// Returns all JWKs currently cached by the verifier.
+pub async fn get_jwks(State(state): State<Arc<AppState>>) -> Json<JwksResponse> {
+    let metadata = state.jwk_metadata.lock().clone();
+    let mut jwks: Vec<JwkEntry> = state
+        .jwks
+        .load()
+        .iter()
+        .map(|(id, jwk)| match metadata.get(id) {
+            Some(m) => JwkEntry {
+                source: Some(m.source),
+                first_seen: Some(unix_secs(m.first_seen)),
+                last_confirmed: Some(unix_secs(m.last_confirmed)),
+                ..JwkEntry::new(id, jwk)
+            },
+            None => JwkEntry::new(id, jwk),
+        })
+        .collect();
+    jwks.sort_by(|a, b| (&a.iss, &a.kid).cmp(&(&b.iss, &b.kid)));
+    Json(JwksResponse { jwks })
+}
+
+/// Verifies only the ephemeral signature of the zkLogin signature in the
+/// request, as a quick check before full verification.
+pub async fn verify_ephemeral(
+    Json(payload): Json<VerifyRequest>,
+) -> Result<Json<EphemeralSignatureResponse>, VerifyError> {
+    verify_request_ephemeral(&payload).map(Json)
+}
+
+/// Parses a zkLogin signature and returns its components without verifying it.
+pub async fn parse_signature(
+    Json(payload): Json<ParseSignatureRequest>,
+) -> Result<Json<ParseSignatureResponse>, VerifyError> {
+    parse_zklogin_signature(&payload.signature).map(Json)
+}
+
+/// Checks whether the verifier holds the JWKs of a zkLogin signature and its
+/// max epoch is in range of the current epoch, without verifying the proof,
+/// so that wallets can tell cheaply whether submitting it would fail on
+/// either.
+pub async fn preflight(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<PreflightRequest>,
+) -> Result<Json<PreflightResponse>, VerifyError> {
+    let signature = decode_signature(&payload.signature, payload.encoding.unwrap_or_default())?;
+    check_allowed_issuers(&state, &zklogin_jwk_ids(&signature))?;
+    let curr_epoch = match payload.curr_epoch {
+        Some(curr_epoch) => curr_epoch,
+        None => {
+            state
+                .get_epoch(request_network(&state, payload.network))
+                .await?
+        }
+    };
+    preflight_zklogin_signature(
+        &signature,
+        &state.jwks.load(),
+        curr_epoch,
+        &state.config.load().verify_policy,
+    )
+    .map(Json)
+}
+
+/// Derives the address and legacy address of the signer of a zkLogin
+/// signature, and whether signatures verify for the legacy address.
+pub async fn derive_address(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<DeriveAddressRequest>,
+) -> Result<Json<DeriveAddressResponse>, VerifyError> {
+    derive_zklogin_addresses(
+        &payload.signature,
+        payload.encoding.unwrap_or_default(),
+        &state.config.load().verify_policy,
+    )
+    .map(Json)
+}
+
+/// Slack on top of the refresh interval before a provider's JWKs count as
+/// overdue, covering slow fetches.
+const OVERDUE_SLACK: Duration = Duration::from_secs(300);
+
+/// Returns the freshness of the JWKs of each configured provider.
+pub async fn get_jwks_status(State(state): State<Arc<AppState>>) -> Json<JwksStatusResponse> {
+    let now = SystemTime::now();
+    let fetch_status = state.fetch_status.lock().clone();
+    let jwks = state.jwks.load();
+    let providers = state
+        .providers
+        .load()
+        .iter()
+        .map(|p| {
+            let iss = p.get_config().iss;
+            let status = fetch_status.get(p).cloned().unwrap_or_default();
+            let overdue = match status.last_success {
+                Some(last_success) => {
+                    now.duration_since(last_success).unwrap_or_default()
+                        > state.config.load().jwk_refresh_interval(p) + OVERDUE_SLACK
+                }
+                None => true,
+            };
+            ProviderJwksStatus {
+                provider: p.to_string(),
+                keys: jwks.keys().filter(|id| id.iss == iss).count(),
+                iss,
+                last_success: status
+                    .last_success
+                    .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                    .map(|d| d.as_secs()),
+                last_error: status.last_error,
+                overdue,
+            }
+        })
+        .collect();
+    Json(JwksStatusResponse { providers })
+}
+
+/// Returns what the verifier supports and the policy in effect.
+pub async fn get_capabilities(State(state): State<Arc<AppState>>) -> Json<CapabilitiesResponse> {
+    let config = state.config.load();
+    Json(CapabilitiesResponse {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        intent_scopes: vec!["TransactionData".to_string(), "PersonalMessage".to_string()],
+        networks: vec![
+            SuiEnv::Mainnet,
+            SuiEnv::Testnet,
+            SuiEnv::Devnet,
+            SuiEnv::Localnet,
+        ],
+        default_network: request_network(&state, None),
+        tracked_networks: config.tracked_networks(),
+        providers: state
+            .providers
+            .load()
+            .iter()
+            .map(|p| p.to_string())
+            .collect(),
+        allowed_issuers: config.allowed_issuers.clone(),
+        formats: vec![PayloadFormat::Bcs, PayloadFormat::Json],
+        encodings: vec![
+            BinaryEncoding::Base64,
+            BinaryEncoding::Base64Url,
+            BinaryEncoding::Hex,
+        ],
+        verify_policy: config.verify_policy,
+        epoch_tolerance: config.epoch_tolerance,
+        allow_inline_jwks: config.allow_inline_jwks,
+        debug_endpoint: config.debug_endpoint,
+        max_batch_size: config.max_batch_size,
+        attestation: config.attestation,
+    })
+}
+
+/// Returns the version of the verifier and of the crypto it links, to tell
+/// which build each instance runs.
+pub async fn get_version() -> Json<VersionResponse> {
+    Json(VersionResponse::current())
+}
+
+/// Liveness probe, returns 200 as long as the server is up.
+pub async fn health() -> StatusCode {
+    StatusCode::OK
+}
+
+/// Readiness probe, returns 200 only once JWKs have been fetched for all
+/// configured providers, and 503 otherwise.
+pub async fn ready(State(state): State<Arc<AppState>>) -> (StatusCode, Json<ReadyResponse>) {
+    let missing_providers: Vec<String> = state
+        .missing_providers()
+        .iter()
+        .map(|p| p.to_string())
+        .collect();
+    let ready = missing_providers.is_empty();
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status,
+        Json(ReadyResponse {
+            ready,
+            missing_providers,
+        }),
+    )
+}
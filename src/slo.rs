@@ -0,0 +1,126 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Burn rates of the configured SLOs of verify requests, so that alerts can
+//! fire on a fast or sustained loss of the error budget without recording
+//! rules.
+
+use crate::{config::SloConfig, metrics::Metrics};
+use std::{collections::VecDeque, sync::Arc, time::Duration};
+
+/// Seconds of requests counted together in the windows of an SLO.
+const SLOT_SECS: u64 = 10;
+
+/// Counts good and bad verify requests of each SLO over its windows.
+#[derive(Clone, Debug, Default)]
+pub struct SloTracker {
+    slos: Arc<Vec<Slo>>,
+}
+
+#[derive(Debug)]
+struct Slo {
+    config: SloConfig,
+    /// The counts of the slots of the longest window, oldest first.
+    slots: parking_lot::Mutex<VecDeque<Slot>>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Slot {
+    start: u64,
+    good: u64,
+    bad: u64,
+}
+
+/// The burn rate of an SLO over one of its windows.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BurnRate {
+    pub slo: String,
+    pub window_secs: u64,
+    /// The fraction of bad requests over the fraction the objective allows.
+    /// 1 spends the error budget exactly over the SLO period, and 0 if there
+    /// were no requests.
+    pub rate: f64,
+}
+
+impl SloTracker {
+    /// Creates a tracker of the SLOs.
+    pub fn new(configs: &[SloConfig]) -> Self {
+        Self {
+            slos: Arc::new(
+                configs
+                    .iter()
+                    .map(|config| Slo {
+                        config: config.clone(),
+                        slots: Default::default(),
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Counts a verify request that took `latency` against each SLO, at
+    /// `now` in Unix seconds. Requests with the "error" outcome are bad.
+    pub fn record(&self, metrics: &Metrics, latency: Duration, outcome: &str, now: u64) {
+        let start = now - now % SLOT_SECS;
+        for slo in self.slos.iter() {
+            let good = outcome != "error"
+                && slo
+                    .config
+                    .latency_threshold_secs
+                    .map_or(true, |threshold| latency.as_secs_f64() <= threshold);
+            metrics
+                .slo_requests
+                .with_label_values(&[&slo.config.name, if good { "good" } else { "bad" }])
+                .inc();
+            let mut slots = slo.slots.lock();
+            let longest = slo.config.windows_secs.iter().max().copied().unwrap_or(0);
+            while slots
+                .front()
+                .is_some_and(|slot| slot.start + longest <= start)
+            {
+                slots.pop_front();
+            }
+            if slots.back().map_or(true, |slot| slot.start != start) {
+                slots.push_back(Slot {
+                    start,
+                    good: 0,
+                    bad: 0,
+                });
+            }
+            let slot = slots.back_mut().expect("not empty");
+            if good {
+                slot.good += 1;
+            } else {
+                slot.bad += 1;
+            }
+        }
+    }
+
+    /// Returns the burn rate of each SLO over each of its windows ending at
+    /// `now` in Unix seconds, rounded to whole slots.
+    pub fn burn_rates(&self, now: u64) -> Vec<BurnRate> {
+        let mut rates = Vec::new();
+        for slo in self.slos.iter() {
+            let slots = slo.slots.lock();
+            for window_secs in &slo.config.windows_secs {
+                let (good, bad) = slots
+                    .iter()
+                    .filter(|slot| slot.start + window_secs > now)
+                    .fold((0, 0), |(good, bad), slot| {
+                        (good + slot.good, bad + slot.bad)
+                    });
+                let rate = if good + bad == 0 {
+                    0.0
+                } else {
+                    bad as f64 / (good + bad) as f64 / (1.0 - slo.config.objective)
+                };
+                rates.push(BurnRate {
+                    slo: slo.config.name.clone(),
+                    window_secs: *window_secs,
+                    rate,
+                });
+            }
+        }
+        rates
+    }
+}
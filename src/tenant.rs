@@ -0,0 +1,76 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Multi-tenant mode, where each application calling the verifier has its
+//! own API key, sent in the `X-Api-Key` header, and its own issuer
+//! allowlist, rate limit and default network.
+
+use crate::{config::TenantConfig, server::error_body, AppState, ErrorCode};
+use axum::{
+    extract::State,
+    http::{header::RETRY_AFTER, HeaderName, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::{future::Future, sync::Arc, time::Instant};
+
+/// The header the API key of the tenant is read from.
+pub const X_API_KEY: HeaderName = HeaderName::from_static("x-api-key");
+
+tokio::task_local! {
+    /// The tenant of the request being handled by the current task.
+    pub(crate) static TENANT: Arc<TenantConfig>;
+}
+
+/// Returns the tenant of the request being handled, if tenants are
+/// configured.
+pub fn current_tenant() -> Option<Arc<TenantConfig>> {
+    TENANT.try_with(|tenant| tenant.clone()).ok()
+}
+
+/// Runs `f` for the tenant, e.g. in a task spawned to handle part of its
+/// request.
+pub(crate) async fn with_tenant<F: Future>(tenant: Option<Arc<TenantConfig>>, f: F) -> F::Output {
+    match tenant {
+        Some(tenant) => TENANT.scope(tenant, f).await,
+        None => f.await,
+    }
+}
+
+/// Middleware that, if tenants are configured, rejects requests without the
+/// API key of a tenant and applies the rate limit of the tenant. The tenant
+/// is available through [current_tenant] while handling the request.
+pub async fn tenant_auth<B>(
+    State(state): State<Arc<AppState>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let config = state.config.load();
+    if config.tenants.is_empty() {
+        return next.run(request).await;
+    }
+    let tenant = request
+        .headers()
+        .get(X_API_KEY)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|api_key| config.tenant(api_key));
+    let Some(tenant) = tenant else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            error_body("Missing or unknown API key", ErrorCode::Unauthorized),
+        )
+            .into_response();
+    };
+    if let Err(retry_after) = state.rate_limiter.check_tenant(tenant, Instant::now()) {
+        let retry_after = retry_after.as_secs_f64().ceil() as u64;
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(RETRY_AFTER, retry_after.to_string())],
+            error_body("Too many requests", ErrorCode::RateLimited),
+        )
+            .into_response();
+    }
+    TENANT
+        .scope(Arc::new(tenant.clone()), next.run(request))
+        .await
+}
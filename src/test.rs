@@ -1,11 +1,106 @@
 // Copyright (c) Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{verify, AppState, VerifyError, VerifyRequest};
-use axum::{extract::State, Json};
-use fastcrypto_zkp::bn254::zk_login::{JwkId, OIDCProvider, JWK};
-use shared_crypto::intent::IntentScope;
-use std::{collections::HashMap, sync::Arc};
+use crate::{
+    access_log::{AccessLog, AccessLogEntry},
+    admin_jwks, admin_refresh_jwks,
+    cache::VerifyCache,
+    challenge::{challenge, verify_challenge, Challenge, ChallengeKey},
+    concurrency::{ConcurrencyLimiter, Overloaded},
+    config::{BucketConfig, ConcurrencyLimitConfig, Config, RateLimitConfig, SloConfig},
+    deadline::{time_left, GRPC_TIMEOUT, X_REQUEST_DEADLINE},
+    default_providers, derive_address,
+    drift::{compare_jwks, JwkDrift},
+    epoch::EpochStatus,
+    get_capabilities, get_jwks, get_jwks_status, get_version,
+    jwks::{apply_provider_jwks, backoff_delay, FetchStatus},
+    log_sampling::LogSampler,
+    metrics::Metrics,
+    parse_signature,
+    pool::ComputePool,
+    preflight,
+    rate_limit::RateLimiter,
+    ready,
+    reload::{apply_config, reload_config},
+    request_id::REQUEST_ID,
+    self_check::{self_check, test_jwk, TEST_BYTES, TEST_SIGNATURE},
+    server::error_body,
+    slo::SloTracker,
+    test_vector::{generate_test_vector, TestVectorParams},
+    verifier::{
+        parse_zklogin_signature, personal_message_intent, validate_request, MAX_BYTES_LEN,
+        MAX_SIGNATURE_LEN,
+    },
+    verify, verify_batch, verify_debug, verify_ephemeral, verify_generic, verify_personal_message,
+    verify_transaction, verify_transaction_data, verify_zklogin_signature,
+    ws::handle_message,
+    zklogin_jwk_ids, AdminJwksRequest, AdminRefreshRequest, AppState, BatchResult, BinaryEncoding,
+    ChallengeRequest, DeriveAddressRequest, ErrorCode, FieldError, JwkEntry, JwkSource,
+    ParseSignatureRequest, PayloadFormat, PreflightRequest, VerifyBatchRequest,
+    VerifyChallengeRequest, VerifyError, VerifyOptions, VerifyPolicy, VerifyPolicyOverride,
+    VerifyRequest, VerifyTransactionRequest,
+};
+use axum::{
+    body::HttpBody,
+    extract::{ConnectInfo, State},
+    http::{
+        header::{AUTHORIZATION, RETRY_AFTER},
+        HeaderMap, StatusCode,
+    },
+    response::IntoResponse,
+    Json,
+};
+use fastcrypto::{
+    ed25519::Ed25519KeyPair,
+    encoding::{Base64, Encoding, Hex},
+    traits::EncodeDecodeBase64,
+};
+use fastcrypto_zkp::bn254::{
+    zk_login::{JwkId, OIDCProvider, JWK},
+    zk_login_api::ZkLoginEnv,
+};
+use im::hashmap::HashMap as ImHashMap;
+use prometheus::Registry;
+use shared_crypto::intent::{
+    AppId, Intent, IntentMessage, IntentScope, IntentVersion, PersonalMessage,
+};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+use sui_types::{
+    base_types::{ObjectDigest, ObjectID, SequenceNumber, SuiAddress},
+    crypto::{get_key_pair, PublicKey, Signature, SuiKeyPair, ToFromBytes},
+    error::SuiError,
+    multisig::{MultiSig, MultiSigPublicKey},
+    signature::GenericSignature,
+    transaction::{TransactionData, TransactionDataAPI},
+};
+use tracing_subscriber::{layer::Context, prelude::*, Layer};
+
+/// A request that verifies the test signature successfully against the test JWK.
+fn test_request() -> VerifyRequest {
+    VerifyRequest {
+        signature: TEST_SIGNATURE.to_string(),
+        bytes: TEST_BYTES.to_string(),
+        intent_scope: IntentScope::TransactionData,
+        author: None,
+        network: Some(crate::SuiEnv::Devnet),
+        curr_epoch: Some(1),
+        sponsor: None,
+        format: None,
+        encoding: None,
+        policy: None,
+        jwk: None,
+        historical_epoch: None,
+        intent: None,
+    }
+}
 
 #[tokio::test]
 async fn test_verify() {
@@ -15,94 +110,2389 @@ async fn test_verify() {
         vec!["rs1bh065i9ya4ydvifixl4kss0uhpt".to_string()],
     );
 
-    let state = Arc::new(AppState {
-        jwks: Default::default(),
+    let state = Arc::new(AppState::default());
+    state.update_jwks(|jwks| {
+        let (jwk_id, jwk) = test_jwk();
+        jwks.insert(jwk_id, jwk);
     });
-    let state_clone = state.clone();
-    {
-        let mut oauth_provider_jwk = state_clone.jwks.write();
-        oauth_provider_jwk.insert(JwkId::new("https://id.twitch.tv/oauth2".to_string(), "1".to_string()), JWK {
-            alg: "RS256".to_string(),
-            e: "AQAB".to_string(),
-            kty: "RSA".to_string(),
-            n: "6lq9MQ-q6hcxr7kOUp-tHlHtdcDsVLwVIw13iXUCvuDOeCi0VSuxCCUY6UmMjy53dX00ih2E4Y4UvlrmmurK0eG26b-HMNNAvCGsVXHU3RcRhVoHDaOwHwU72j7bpHn9XbP3Q3jebX6KIfNbei2MiR0Wyb8RZHE-aZhRYO8_-k9G2GycTpvc-2GBsP8VHLUKKfAs2B6sW3q3ymU6M0L-cFXkZ9fHkn9ejs-sqZPhMJxtBPBxoUIUQFTgv4VXTSv914f_YkNw-EjuwbgwXMvpyr06EyfImxHoxsZkFYB-qBYHtaMxTnFsZBr6fn8Ha2JqT1hoP7Z5r5wxDu3GQhKkHw".to_string(),
-        });
-    }
-    let sig = "BQNNMTczMTgwODkxMjU5NTI0MjE3MzYzNDIyNjM3MTc5MzI3MTk0Mzc3MTc4NDQyODI0MTAxODc5NTc5ODQ3NTE5Mzk5NDI4OTgyNTEyNTBNMTEzNzM5NjY2NDU0NjkxMjI1ODIwNzQwODIyOTU5ODUzODgyNTg4NDA2ODE2MTgyNjg1OTM5NzY2OTczMjU4OTIyODA5MTU2ODEyMDcBMQMCTDU5Mzk4NzExNDczNDg4MzQ5OTczNjE3MjAxMjIyMzg5ODAxNzcxNTIzMDMyNzQzMTEwNDcyNDk5MDU5NDIzODQ5MTU3Njg2OTA4OTVMNDUzMzU2ODI3MTEzNDc4NTI3ODczMTIzNDU3MDM2MTQ4MjY1MTk5Njc0MDc5MTg4ODI4NTg2NDk2Njg4NDAzMjcxNzA0OTgxMTcwOAJNMTA1NjQzODcyODUwNzE1NTU0Njk3NTM5OTA2NjE0MTA4NDAxMTg2MzU5MjU0NjY1OTcwMzcwMTgwNTg3NzAwNDEzNDc1MTg0NjEzNjhNMTI1OTczMjM1NDcyNzc1NzkxNDQ2OTg0OTYzNzIyNDI2MTUzNjgwODU4MDEzMTMzNDMxNTU3MzU1MTEzMzAwMDM4ODQ3Njc5NTc4NTQCATEBMANNMTU3OTE1ODk0NzI1NTY4MjYyNjMyMzE2NDQ3Mjg4NzMzMzc2MjkwMTUyNjk5ODQ2OTk0MDQwNzM2MjM2MDMzNTI1Mzc2Nzg4MTMxNzFMNDU0Nzg2NjQ5OTI0ODg4MTQ0OTY3NjE2MTE1ODAyNDc0ODA2MDQ4NTM3MzI1MDAyOTQyMzkwNDExMzAxNzQyMjUzOTAzNzE2MjUyNwExMXdpYVhOeklqb2lhSFIwY0hNNkx5OXBaQzUwZDJsMFkyZ3VkSFl2YjJGMWRHZ3lJaXcCMmV5SmhiR2NpT2lKU1V6STFOaUlzSW5SNWNDSTZJa3BYVkNJc0ltdHBaQ0k2SWpFaWZRTTIwNzk0Nzg4NTU5NjIwNjY5NTk2MjA2NDU3MDIyOTY2MTc2OTg2Njg4NzI3ODc2MTI4MjIzNjI4MTEzOTE2MzgwOTI3NTAyNzM3OTExCgAAAAAAAABhAG6Bf8BLuaIEgvF8Lx2jVoRWKKRIlaLlEJxgvqwq5nDX+rvzJxYAUFd7KeQBd9upNx+CHpmINkfgj26jcHbbqAy5xu4WMO8+cRFEpkjbBruyKE9ydM++5T/87lA8waSSAA==";
-    let bytes = "AAABACACAgICAgICAgICAgICAgICAgICAgICAgICAgICAgICAgEBAQABAAAcpgUkGBwS5nPO79YXkjMyvaRjGS57hqxzfyd2yGtejwGbB4FfBEl+LgXSLKw6oGFBCyCGjMYZFUxCocYb6ZAnFwEAAAAAAAAAIJZw7UpW1XHubORIOaY8d2+WyBNwoJ+FEAxlsa7h7JHrHKYFJBgcEuZzzu/WF5IzMr2kYxkue4asc38ndshrXo8BAAAAAAAAABAnAAAAAAAAAA==";
 
+    let res = verify(State(state.clone()), Json(test_request())).await;
+    assert!(res.is_ok());
+    let res = res.unwrap().0;
+    assert!(res.is_verified);
+    assert_eq!(res.iss.as_deref(), Some("https://id.twitch.tv/oauth2"));
+    assert_eq!(res.provider.as_deref(), Some("Twitch"));
+    assert_eq!(res.kid.as_deref(), Some("1"));
+    assert_eq!(res.max_epoch, Some(10));
+    assert_eq!(res.curr_epoch, Some(1));
+    assert_eq!(res.epochs_remaining, Some(9));
+    assert!(res.address.is_some());
+    assert_eq!(
+        state
+            .metrics
+            .verify_latency
+            .with_label_values(&["Twitch", "TransactionData", "verified"])
+            .get_sample_count(),
+        1
+    );
+
+    // Wrong network fails to verify.
     let res = verify(
         State(state.clone()),
         Json(VerifyRequest {
-            signature: sig.to_string(),
-            bytes: bytes.to_string(),
-            intent_scope: IntentScope::TransactionData,
-            author: None,
-            network: Some(crate::SuiEnv::Devnet),
-            curr_epoch: Some(1),
+            network: Some(crate::SuiEnv::Mainnet),
+            ..test_request()
+        }),
+    )
+    .await;
+    let err = res.unwrap_err();
+    assert!(matches!(err, VerifyError::ProofVerifyFailed(_)));
+    assert_eq!(err.code(), ErrorCode::ProofVerifyFailed);
+    // The error of sui-types is kept as the source.
+    assert!(std::error::Error::source(&err)
+        .and_then(|source| source.downcast_ref::<SuiError>())
+        .is_some());
+    let results = &state.metrics.verify_results;
+    assert_eq!(
+        results.with_label_values(&["verified", "verified"]).get(),
+        1
+    );
+    assert_eq!(
+        results
+            .with_label_values(&["rejected", "proof_invalid"])
+            .get(),
+        1
+    );
+
+    // The test transaction is not sponsored, so the gas owner is the sender.
+    // See test_verify_sponsored for a sponsored one.
+    let res = verify(
+        State(state.clone()),
+        Json(VerifyRequest {
+            sponsor: Some(true),
+            ..test_request()
         }),
     )
     .await;
-    assert!(res.is_ok());
     assert!(res.unwrap().0.is_verified);
 
-    // Wrong network fails to verify.
+    // The JSON representation of the transaction verifies the same way.
+    let tx_data: TransactionData = bcs::from_bytes(&Base64::decode(TEST_BYTES).unwrap()).unwrap();
     let res = verify(
         State(state.clone()),
         Json(VerifyRequest {
-            signature: sig.to_string(),
-            bytes: bytes.to_string(),
-            intent_scope: IntentScope::TransactionData,
-            author: None,
-            network: Some(crate::SuiEnv::Mainnet),
-            curr_epoch: Some(1),
+            bytes: serde_json::to_string(&tx_data).unwrap(),
+            format: Some(PayloadFormat::Json),
+            ..test_request()
         }),
     )
     .await;
-    assert!(matches!(res.unwrap_err(), VerifyError::GenericError(..)));
+    assert!(res.unwrap().0.is_verified);
+
+    // Validating decodes the signature, and the bytes it is over from either
+    // format, once for the whole request.
+    let (sig, bytes) = validate_request(&VerifyRequest {
+        bytes: serde_json::to_string(&tx_data).unwrap(),
+        format: Some(PayloadFormat::Json),
+        ..test_request()
+    })
+    .unwrap();
+    assert!(matches!(sig, GenericSignature::ZkLoginAuthenticator(_)));
+    assert_eq!(bytes, Base64::decode(TEST_BYTES).unwrap());
 
     // Wrong epoch fails to verify.
     let res = verify(
         State(state.clone()),
         Json(VerifyRequest {
-            signature: sig.to_string(),
-            bytes: bytes.to_string(),
-            intent_scope: IntentScope::TransactionData,
-            author: None,
-            network: Some(crate::SuiEnv::Devnet),
             curr_epoch: Some(11),
+            ..test_request()
+        }),
+    )
+    .await;
+    assert_eq!(
+        res.unwrap_err(),
+        VerifyError::SignatureExpired {
+            max_epoch: 10,
+            curr_epoch: 11
+        }
+    );
+
+    // A signature by another address than the author fails before its proof
+    // is verified.
+    let res = verify(
+        State(state.clone()),
+        Json(VerifyRequest {
+            intent_scope: IntentScope::PersonalMessage,
+            author: Some(SuiAddress::ZERO),
+            ..test_request()
         }),
     )
     .await;
-    assert!(matches!(res.unwrap_err(), VerifyError::GenericError(..)));
+    assert_eq!(res.unwrap_err().code(), ErrorCode::AuthorMismatch);
 
     // Wrong intent scope fails to verify.
     let res = verify(
         State(state.clone()),
         Json(VerifyRequest {
-            signature: sig.to_string(),
-            bytes: bytes.to_string(),
             intent_scope: IntentScope::PersonalMessage,
-            author: None,
-            network: Some(crate::SuiEnv::Devnet),
-            curr_epoch: Some(1),
+            ..test_request()
         }),
     )
     .await;
-    assert_eq!(res.unwrap_err(), VerifyError::ParsingError);
+    assert_eq!(
+        res.unwrap_err(),
+        VerifyError::InvalidFields(vec![FieldError {
+            field: "author".to_string(),
+            code: ErrorCode::MissingAuthor,
+            error: "author is required for PersonalMessage".to_string(),
+        }])
+    );
 
-    // Bad ephemeral signature fails to verify
+    // All invalid fields are reported, with the code of the first.
     let res = verify(
         State(state.clone()),
         Json(VerifyRequest {
             signature: "badsig".to_string(),
-            bytes: bytes.to_string(),
+            bytes: String::new(),
             intent_scope: IntentScope::PersonalMessage,
-            author: None,
-            network: Some(crate::SuiEnv::Devnet),
-            curr_epoch: Some(1),
+            ..test_request()
+        }),
+    )
+    .await;
+    let err = res.unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidSignatureEncoding);
+    let res = err.into_response();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let body = res.into_body().data().await.unwrap().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let fields: Vec<FieldError> = serde_json::from_value(body["fields"].clone()).unwrap();
+    assert_eq!(
+        fields.iter().map(|f| f.field.as_str()).collect::<Vec<_>>(),
+        ["signature", "bytes", "author"]
+    );
+    assert_eq!(fields[0].error, "signature is not valid Base64");
+    assert_eq!(fields[1].code, ErrorCode::InvalidBytes);
+
+    // Bytes that are not TransactionData fail to parse.
+    let res = verify(
+        State(state.clone()),
+        Json(VerifyRequest {
+            bytes: Base64::encode([1, 2, 3]),
+            ..test_request()
+        }),
+    )
+    .await;
+    let err = res.unwrap_err();
+    assert!(matches!(err, VerifyError::BcsDecodeError(_)));
+    // The error of bcs is kept as the source.
+    assert!(std::error::Error::source(&err)
+        .and_then(|source| source.downcast_ref::<bcs::Error>())
+        .is_some());
+
+    // The signature and bytes can be sent in other encodings.
+    let signature = Base64::decode(TEST_SIGNATURE).unwrap();
+    let bytes = Base64::decode(TEST_BYTES).unwrap();
+    let res = verify(
+        State(state.clone()),
+        Json(VerifyRequest {
+            signature: format!("0x{}", Hex::encode(&signature)),
+            bytes: Hex::encode(&bytes),
+            encoding: Some(BinaryEncoding::Hex),
+            ..test_request()
+        }),
+    )
+    .await;
+    assert!(res.unwrap().0.is_verified);
+    let res = verify(
+        State(state.clone()),
+        Json(VerifyRequest {
+            signature: TEST_SIGNATURE.replace('+', "-").replace('/', "_"),
+            bytes: TEST_BYTES.replace('+', "-").replace('/', "_"),
+            encoding: Some(BinaryEncoding::Base64Url),
+            ..test_request()
+        }),
+    )
+    .await;
+    assert!(res.unwrap().0.is_verified);
+
+    // A historical epoch cannot be combined with the current epoch.
+    let res = verify(
+        State(state.clone()),
+        Json(VerifyRequest {
+            historical_epoch: Some(1),
+            intent: None,
+            ..test_request()
+        }),
+    )
+    .await;
+    assert_eq!(res.unwrap_err().code(), ErrorCode::InvalidHistoricalEpoch);
+
+    // Client errors are 4xx, failures of the fullnodes 5xx.
+    assert_eq!(
+        VerifyError::BcsDecodeError(bcs::Error::Eof)
+            .into_response()
+            .status(),
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(
+        VerifyError::GetEpochError(None).into_response().status(),
+        StatusCode::BAD_GATEWAY
+    );
+}
+
+#[tokio::test]
+async fn test_payload_too_large() {
+    let state = Arc::new(AppState::default());
+
+    // An oversized signature is rejected before it is decoded.
+    let signature = "A".repeat(MAX_SIGNATURE_LEN + 1);
+    assert_eq!(
+        parse_zklogin_signature(&signature).unwrap_err(),
+        VerifyError::PayloadTooLarge {
+            field: "signature",
+            len: MAX_SIGNATURE_LEN + 1,
+            max: MAX_SIGNATURE_LEN,
+        }
+    );
+    let err = verify(
+        State(state.clone()),
+        Json(VerifyRequest {
+            signature,
+            ..test_request()
+        }),
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::PayloadTooLarge);
+    assert_eq!(err.into_response().status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    // So are oversized bytes.
+    let err = verify(
+        State(state),
+        Json(VerifyRequest {
+            bytes: "A".repeat(MAX_BYTES_LEN + 1),
+            ..test_request()
+        }),
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::PayloadTooLarge);
+    assert_eq!(err.into_response().status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn test_not_zklogin_signature() {
+    let state = Arc::new(AppState::default());
+    let tx_data: TransactionData = bcs::from_bytes(&Base64::decode(TEST_BYTES).unwrap()).unwrap();
+    let (_, kp): (_, Ed25519KeyPair) = get_key_pair();
+    let signature =
+        Signature::new_secure(&IntentMessage::new(Intent::sui_transaction(), tx_data), &kp);
+    let request = VerifyRequest {
+        signature: Base64::encode(signature.as_ref()),
+        ..test_request()
+    };
+    let err = verify(State(state), Json(request)).await.unwrap_err();
+    assert_eq!(err, VerifyError::NotZkLoginSignature("ED25519".to_string()));
+
+    // The scheme is named in the message and on its own.
+    let res = err.into_response();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let body = res.into_body().data().await.unwrap().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["code"], "NOT_ZKLOGIN_SIGNATURE");
+    assert_eq!(body["scheme"], "ED25519");
+    assert!(body["error"]
+        .as_str()
+        .unwrap()
+        .contains("found scheme ED25519"));
+}
+
+#[tokio::test]
+async fn test_verify_generic() {
+    let state = Arc::new(AppState::default());
+    state.update_jwks(|jwks| {
+        let (jwk_id, jwk) = test_jwk();
+        jwks.insert(jwk_id, jwk);
+    });
+
+    let res = verify_generic(State(state.clone()), Json(test_request()))
+        .await
+        .unwrap()
+        .0;
+    assert!(res.is_verified);
+    assert_eq!(res.scheme.as_deref(), Some("ZkLoginAuthenticator"));
+    assert_eq!(res.max_epoch, Some(10));
+
+    // A plain Ed25519 signature over a PersonalMessage by the author.
+    let (address, kp): (_, Ed25519KeyPair) = get_key_pair();
+    let message = b"hello";
+    let signature = Signature::new_secure(&personal_message_intent(message), &kp);
+    let request = VerifyRequest {
+        signature: Base64::encode(signature.as_ref()),
+        bytes: Base64::encode(message),
+        intent_scope: IntentScope::PersonalMessage,
+        author: Some(address),
+        ..test_request()
+    };
+    let res = verify_generic(State(state.clone()), Json(request.clone()))
+        .await
+        .unwrap()
+        .0;
+    assert!(res.is_verified);
+    assert_eq!(res.address, Some(address));
+    assert_eq!(res.scheme.as_deref(), Some("ED25519"));
+    assert_eq!(res.iss, None);
+    assert_eq!(res.provider, None);
+
+    // Another author.
+    let res = verify_generic(
+        State(state.clone()),
+        Json(VerifyRequest {
+            author: Some(SuiAddress::ZERO),
+            ..request.clone()
+        }),
+    )
+    .await;
+    assert_eq!(res.unwrap_err().code(), ErrorCode::VerifyFailed);
+
+    // A signature with another app ID only verifies with that intent.
+    let intent = Intent {
+        scope: IntentScope::PersonalMessage,
+        version: IntentVersion::V0,
+        app_id: AppId::Narwhal,
+    };
+    let signature = Signature::new_secure(
+        &IntentMessage::new(
+            intent.clone(),
+            PersonalMessage {
+                message: message.to_vec(),
+            },
+        ),
+        &kp,
+    );
+    let custom_request = VerifyRequest {
+        signature: Base64::encode(signature.as_ref()),
+        intent: Some(intent),
+        ..request.clone()
+    };
+    let res = verify_generic(State(state.clone()), Json(custom_request.clone()))
+        .await
+        .unwrap()
+        .0;
+    assert!(res.is_verified);
+    let res = verify_generic(
+        State(state.clone()),
+        Json(VerifyRequest {
+            intent: None,
+            ..custom_request.clone()
+        }),
+    )
+    .await;
+    assert_eq!(res.unwrap_err().code(), ErrorCode::VerifyFailed);
+    let res = verify_generic(
+        State(state.clone()),
+        Json(VerifyRequest {
+            intent: Some(Intent::sui_transaction()),
+            ..custom_request
+        }),
+    )
+    .await;
+    assert_eq!(res.unwrap_err().code(), ErrorCode::IntentScopeMismatch);
+
+    // /verify still only accepts zkLogin signatures, cached or not.
+    let err = verify(State(state), Json(request)).await.unwrap_err();
+    assert_eq!(err, VerifyError::NotZkLoginSignature("ED25519".to_string()));
+}
+
+#[tokio::test]
+async fn test_verify_sponsored() {
+    let state = Arc::new(AppState::default());
+    // A transaction whose gas is paid by a sponsor other than the sender.
+    let (sender, sender_kp): (_, Ed25519KeyPair) = get_key_pair();
+    let (sponsor, sponsor_kp): (_, Ed25519KeyPair) = get_key_pair();
+    let gas = (
+        ObjectID::random(),
+        SequenceNumber::new(),
+        ObjectDigest::random(),
+    );
+    let mut tx_data =
+        TransactionData::new_transfer_sui(sender, sender, None, gas, 10_000_000, 1000);
+    tx_data.gas_data_mut().owner = sponsor;
+    let bytes = Base64::encode(bcs::to_bytes(&tx_data).unwrap());
+    let intent_msg = IntentMessage::new(Intent::sui_transaction(), tx_data);
+    let sign =
+        |kp: &Ed25519KeyPair| Base64::encode(Signature::new_secure(&intent_msg, kp).as_ref());
+    let request = |signature: String, sponsor: bool| VerifyRequest {
+        signature,
+        bytes: bytes.clone(),
+        sponsor: Some(sponsor),
+        ..test_request()
+    };
+
+    // With `sponsor`, the signature of the gas owner verifies and that of
+    // the sender does not, and the other way around without it.
+    let res = verify_generic(State(state.clone()), Json(request(sign(&sponsor_kp), true)))
+        .await
+        .unwrap()
+        .0;
+    assert!(res.is_verified);
+    assert_eq!(res.address, Some(sponsor));
+    let res = verify_generic(State(state.clone()), Json(request(sign(&sender_kp), true))).await;
+    assert_eq!(res.unwrap_err().code(), ErrorCode::VerifyFailed);
+    let res = verify_generic(State(state.clone()), Json(request(sign(&sender_kp), false)))
+        .await
+        .unwrap()
+        .0;
+    assert_eq!(res.address, Some(sender));
+    let res = verify_generic(
+        State(state.clone()),
+        Json(request(sign(&sponsor_kp), false)),
+    )
+    .await;
+    assert_eq!(res.unwrap_err().code(), ErrorCode::VerifyFailed);
+
+    // The transaction needs the signatures of both.
+    let verify_signatures = |signatures: Vec<String>| {
+        verify_transaction(
+            State(state.clone()),
+            Json(VerifyTransactionRequest {
+                bytes: bytes.clone(),
+                signatures,
+                network: Some(crate::SuiEnv::Devnet),
+                curr_epoch: Some(1),
+                encoding: None,
+                dry_run: None,
+            }),
+        )
+    };
+    let res = verify_signatures(vec![sign(&sender_kp), sign(&sponsor_kp)])
+        .await
+        .unwrap()
+        .0;
+    assert!(res.is_verified);
+    assert_eq!(res.signatures[0].signer, Some(sender));
+    assert_eq!(res.signatures[1].signer, Some(sponsor));
+    let res = verify_signatures(vec![sign(&sender_kp)]).await.unwrap().0;
+    assert!(!res.is_verified);
+}
+
+#[test]
+fn test_deadline_time_left() {
+    let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+    let headers = |pairs: &[(&axum::http::HeaderName, &str)]| {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, value.parse().unwrap());
+        }
+        headers
+    };
+    assert_eq!(time_left(&headers(&[]), now), Ok(None));
+    assert_eq!(
+        time_left(&headers(&[(&X_REQUEST_DEADLINE, "1002500")]), now),
+        Ok(Some(Duration::from_millis(2500)))
+    );
+    // A passed deadline leaves no time.
+    assert_eq!(
+        time_left(&headers(&[(&X_REQUEST_DEADLINE, "999000")]), now),
+        Ok(Some(Duration::ZERO))
+    );
+    assert_eq!(
+        time_left(&headers(&[(&GRPC_TIMEOUT, "1500m")]), now),
+        Ok(Some(Duration::from_millis(1500)))
+    );
+    assert_eq!(
+        time_left(&headers(&[(&GRPC_TIMEOUT, "2S")]), now),
+        Ok(Some(Duration::from_secs(2)))
+    );
+    // The earlier of both applies.
+    assert_eq!(
+        time_left(
+            &headers(&[(&X_REQUEST_DEADLINE, "1002500"), (&GRPC_TIMEOUT, "1S")]),
+            now
+        ),
+        Ok(Some(Duration::from_secs(1)))
+    );
+    for value in ["", "S", "1500", "1500x", "123456789m", "-1S"] {
+        assert!(time_left(&headers(&[(&GRPC_TIMEOUT, value)]), now).is_err());
+    }
+    assert!(time_left(&headers(&[(&X_REQUEST_DEADLINE, "soon")]), now).is_err());
+}
+
+#[tokio::test]
+async fn test_get_jwks() {
+    let state = Arc::new(AppState::default());
+    assert!(get_jwks(State(state.clone())).await.0.jwks.is_empty());
+
+    let (jwk_id, jwk) = test_jwk();
+    state.update_jwks(|jwks| jwks.insert(jwk_id.clone(), jwk.clone()));
+    let res = get_jwks(State(state.clone())).await.0;
+    assert_eq!(res.jwks.len(), 1);
+    assert_eq!(res.jwks[0].iss, jwk_id.iss);
+    assert_eq!(res.jwks[0].kid, jwk_id.kid);
+    assert_eq!(res.jwks[0].n, jwk.n);
+    assert_eq!(res.jwks[0].e, jwk.e);
+    assert_eq!(res.jwks[0].source, None);
+
+    // Keys returned by the provider are listed with their provenance.
+    let iss = jwk_id.iss.clone();
+    apply_provider_jwks(&state, &iss, vec![(jwk_id, jwk)], Instant::now());
+    let res = get_jwks(State(state.clone())).await.0;
+    let entry = &res.jwks[0];
+    assert_eq!(entry.source, Some(JwkSource::Provider));
+    assert!(entry.first_seen.is_some());
+    assert!(entry.last_confirmed >= entry.first_seen);
+}
+
+#[tokio::test]
+async fn test_derive_address() {
+    let request = || {
+        Json(DeriveAddressRequest {
+            signature: TEST_SIGNATURE.to_string(),
+            encoding: None,
+        })
+    };
+    let state = Arc::new(AppState::default());
+    let res = derive_address(State(state.clone()), request())
+        .await
+        .unwrap()
+        .0;
+    // The address the test signature verifies for.
+    let expected: SuiAddress = "0x1ca60524181c12e673ceefd617923332bda463192e7b86ac737f2776c86b5e8f"
+        .parse()
+        .unwrap();
+    assert_eq!(res.address, expected);
+    // The address seed of the test signature has no leading zero bytes.
+    assert_eq!(res.legacy_address, expected);
+    assert!(res.legacy_address_accepted);
+
+    let state = Arc::new(AppState::new(Config {
+        verify_policy: VerifyPolicy {
+            verify_legacy_address: false,
+            ..Default::default()
+        },
+        ..Default::default()
+    }));
+    let res = derive_address(State(state.clone()), request())
+        .await
+        .unwrap()
+        .0;
+    assert_eq!(res.address, expected);
+    assert!(!res.legacy_address_accepted);
+
+    let res = derive_address(
+        State(state),
+        Json(DeriveAddressRequest {
+            signature: "AA==".to_string(),
+            encoding: None,
+        }),
+    )
+    .await;
+    assert_eq!(res.unwrap_err().code(), ErrorCode::InvalidSignatureEncoding);
+}
+
+#[tokio::test]
+async fn test_verify_debug() {
+    let state = Arc::new(AppState::default());
+    state.update_jwks(|jwks| {
+        let (jwk_id, jwk) = test_jwk();
+        jwks.insert(jwk_id, jwk);
+    });
+    let res = verify_debug(State(state), Json(test_request())).await.0;
+    assert!(matches!(res.result, BatchResult::Verified(_)));
+    assert_eq!(res.network, crate::SuiEnv::Devnet);
+    assert_eq!(res.env, "Test");
+    assert_eq!(res.signatures.len(), 1);
+    let debug = &res.signatures[0];
+    assert_eq!(debug.signature.kid, "1");
+    assert_eq!(debug.header["kid"], "1");
+    assert!(!debug.iss_base64_details.value.is_empty());
+    assert_eq!(debug.jwk, Some(test_jwk().1));
+
+    // Failures come with the inputs too, here without a JWK to verify against.
+    let res = verify_debug(State(Arc::new(AppState::default())), Json(test_request()))
+        .await
+        .0;
+    assert!(matches!(
+        res.result,
+        BatchResult::Failed {
+            code: ErrorCode::UnknownJwk,
+            ..
+        }
+    ));
+    assert_eq!(res.signatures[0].signature.kid, "1");
+    assert_eq!(res.signatures[0].jwk, None);
+}
+
+#[tokio::test]
+async fn test_preflight() {
+    let request = |curr_epoch| {
+        Json(PreflightRequest {
+            signature: TEST_SIGNATURE.to_string(),
+            encoding: None,
+            network: None,
+            curr_epoch: Some(curr_epoch),
+        })
+    };
+    let state = Arc::new(AppState::default());
+    state.update_jwks(|jwks| {
+        let (jwk_id, jwk) = test_jwk();
+        jwks.insert(jwk_id, jwk);
+    });
+    let res = preflight(State(state.clone()), request(1)).await.unwrap().0;
+    assert!(res.ready);
+    assert_eq!(res.curr_epoch, 1);
+    assert_eq!(res.signatures.len(), 1);
+    let sig = &res.signatures[0];
+    assert_eq!(
+        (sig.iss.as_str(), sig.kid.as_str()),
+        ("https://id.twitch.tv/oauth2", "1")
+    );
+    assert!(sig.jwk_known && sig.epoch_in_range);
+    assert_eq!(sig.error, None);
+
+    // The test signature expires at the end of epoch 10.
+    let res = preflight(State(state), request(11)).await.unwrap().0;
+    assert!(!res.ready);
+    assert!(res.signatures[0].jwk_known);
+    assert!(!res.signatures[0].epoch_in_range);
+    assert_eq!(res.signatures[0].code, Some(ErrorCode::SignatureExpired));
+
+    let res = preflight(State(Arc::new(AppState::default())), request(1))
+        .await
+        .unwrap()
+        .0;
+    assert!(!res.ready);
+    assert!(!res.signatures[0].jwk_known);
+    assert_eq!(res.signatures[0].code, Some(ErrorCode::UnknownJwk));
+}
+
+#[tokio::test]
+async fn test_challenge() {
+    let state = Arc::new(AppState::default());
+    state.update_jwks(|jwks| {
+        let (jwk_id, jwk) = test_jwk();
+        jwks.insert(jwk_id, jwk);
+    });
+    let address = verify(State(state.clone()), Json(test_request()))
+        .await
+        .unwrap()
+        .0
+        .address
+        .unwrap();
+    // Challenges are verified at the current epoch of the network.
+    state.epochs.lock().insert(
+        crate::SuiEnv::Devnet,
+        EpochStatus {
+            epoch: 1,
+            fetched_at: SystemTime::now(),
+            next_epoch_at: SystemTime::now() + Duration::from_secs(3600),
+        },
+    );
+    let issue = |address, domain: Option<&str>| {
+        challenge(
+            State(state.clone()),
+            Json(ChallengeRequest {
+                address,
+                domain: domain.map(str::to_string),
+            }),
+        )
+    };
+    let verify_signed = |challenge: String| {
+        verify_challenge(
+            State(state.clone()),
+            Json(VerifyChallengeRequest {
+                challenge,
+                signature: TEST_SIGNATURE.to_string(),
+                network: Some(crate::SuiEnv::Devnet),
+            }),
+        )
+    };
+
+    let res = issue(address, Some("exchange.example")).await.unwrap().0;
+    let parsed = Challenge::parse(&res.challenge).unwrap();
+    assert_eq!(parsed.address, address);
+    assert_eq!(parsed.domain.as_deref(), Some("exchange.example"));
+    assert_eq!(parsed.nonce, res.nonce);
+    assert_eq!(parsed.expires_at, res.expires_at);
+    state.challenge_key.check(&parsed).unwrap();
+
+    // Other forms of the same fields are rejected.
+    let hex_address = address.to_string();
+    for challenge in [
+        format!("{}\n", res.challenge),
+        res.challenge.replace('\n', "\r\n"),
+        res.challenge
+            .replace(&hex_address, hex_address.trim_start_matches("0x")),
+        res.challenge.replace("Domain: ", "Domain:  "),
+    ] {
+        assert_eq!(
+            Challenge::parse(&challenge).unwrap_err().code(),
+            ErrorCode::InvalidChallenge
+        );
+    }
+    let err = issue(address, Some("exchange example")).await.unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidChallenge);
+
+    // Challenges not issued by the verifier, or changed since, are rejected.
+    let extended = res.challenge.replace(
+        &format!("Expires At: {}", res.expires_at),
+        &format!("Expires At: {}", res.expires_at + 3600),
+    );
+    let err = verify_signed(extended).await.unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidChallenge);
+    let forged = Challenge::new(
+        &ChallengeKey::default(),
+        address,
+        None,
+        Duration::from_secs(60),
+        SystemTime::now(),
+    )
+    .unwrap();
+    let err = verify_signed(forged.to_message()).await.unwrap_err();
+    assert_eq!(err.code(), ErrorCode::InvalidChallenge);
+
+    // The test signature is by the address, but not over the challenge.
+    let err = verify_signed(res.challenge).await.unwrap_err();
+    assert_eq!(err.code(), ErrorCode::VerifyFailed);
+
+    // A challenge for another address fails before verifying the proof.
+    let other = issue(SuiAddress::ZERO, None).await.unwrap().0;
+    let err = verify_signed(other.challenge).await.unwrap_err();
+    assert_eq!(err.code(), ErrorCode::AuthorMismatch);
+
+    let expired = Challenge::new(
+        &state.challenge_key,
+        address,
+        None,
+        Duration::from_secs(60),
+        SystemTime::now() - Duration::from_secs(61),
+    )
+    .unwrap();
+    let err = verify_signed(expired.to_message()).await.unwrap_err();
+    assert_eq!(err.code(), ErrorCode::ChallengeExpired);
+}
+
+#[tokio::test]
+async fn test_parse_signature() {
+    let res = parse_signature(Json(ParseSignatureRequest {
+        signature: TEST_SIGNATURE.to_string(),
+    }))
+    .await
+    .unwrap()
+    .0;
+    assert_eq!(res.iss, "https://id.twitch.tv/oauth2");
+    assert_eq!(res.kid, "1");
+    assert_eq!(res.max_epoch, 10);
+    assert_eq!(res.ephemeral_scheme, "ED25519");
+    assert!(!res.address_seed.is_empty());
+
+    let res = parse_signature(Json(ParseSignatureRequest {
+        signature: "badsig".to_string(),
+    }))
+    .await;
+    assert_eq!(res.unwrap_err().code(), ErrorCode::InvalidSignatureEncoding);
+}
+
+#[tokio::test]
+async fn test_ready() {
+    let state = Arc::new(AppState {
+        jwks: Default::default(),
+        providers: vec![OIDCProvider::Twitch].into(),
+        ..Default::default()
+    });
+    let (status, res) = ready(State(state.clone())).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(res.0.missing_providers, vec!["Twitch".to_string()]);
+
+    let (jwk_id, jwk) = test_jwk();
+    state.update_jwks(|jwks| jwks.insert(jwk_id, jwk));
+    let (status, res) = ready(State(state.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(res.0.ready);
+
+    // Providers without any fetched JWK keep the service unready.
+    let state = Arc::new(AppState {
+        jwks: state.jwks.clone(),
+        providers: default_providers().into(),
+        ..Default::default()
+    });
+    let (status, _) = ready(State(state)).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[tokio::test]
+async fn test_wait_for_jwks() {
+    let state = Arc::new(AppState {
+        providers: vec![OIDCProvider::Twitch].into(),
+        ..Default::default()
+    });
+    assert_eq!(
+        state.wait_for_jwks(Duration::from_millis(200)).await,
+        vec![OIDCProvider::Twitch]
+    );
+
+    tokio::spawn({
+        let state = state.clone();
+        async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            let (jwk_id, jwk) = test_jwk();
+            state.update_jwks(|jwks| jwks.insert(jwk_id, jwk));
+        }
+    });
+    assert!(state
+        .wait_for_jwks(Duration::from_secs(10))
+        .await
+        .is_empty());
+    assert_eq!(state.metrics.providers_missing_jwks.get(), 0);
+}
+
+#[tokio::test]
+async fn test_verify_multisig() {
+    let state = Arc::new(AppState::default());
+    let (jwk_id, jwk) = test_jwk();
+    state.update_jwks(|jwks| jwks.insert(jwk_id, jwk));
+
+    // Wrap the zkLogin signature as the single participant of a 1-of-1 multisig.
+    let zk_sig = GenericSignature::from_bytes(&Base64::decode(TEST_SIGNATURE).unwrap()).unwrap();
+    let GenericSignature::ZkLoginAuthenticator(zk) = &zk_sig else {
+        panic!("expected a zkLogin signature");
+    };
+    let multisig_pk = MultiSigPublicKey::new(
+        vec![PublicKey::from_zklogin_inputs(&zk.inputs).unwrap()],
+        vec![1],
+        1,
+    )
+    .unwrap();
+    let multisig = MultiSig::combine(vec![zk_sig.clone()], multisig_pk).unwrap();
+
+    // The JWK ids are collected from the multisig participants.
+    let (jwk_id, _) = test_jwk();
+    assert_eq!(zklogin_jwk_ids(&zk_sig), vec![jwk_id.clone()]);
+    assert_eq!(
+        zklogin_jwk_ids(&GenericSignature::MultiSig(multisig.clone())),
+        vec![jwk_id]
+    );
+
+    // The multisig is accepted for verification, but fails before its proof
+    // is verified since the transaction sender is the zkLogin address and
+    // not the multisig address.
+    let res = verify(
+        State(state.clone()),
+        Json(VerifyRequest {
+            signature: Base64::encode(GenericSignature::MultiSig(multisig.clone()).as_ref()),
+            ..test_request()
         }),
     )
     .await;
-    assert_eq!(res.unwrap_err(), VerifyError::ParsingError);
+    assert_eq!(
+        res.unwrap_err(),
+        VerifyError::AuthorMismatch {
+            author: SuiAddress::try_from_unpadded(&zk.inputs).unwrap(),
+            signer: SuiAddress::from(multisig.get_pk()),
+        }
+    );
+}
+
+#[test]
+fn test_verify_zklogin_signature() {
+    let (jwk_id, jwk) = test_jwk();
+    let bytes = Base64::decode(TEST_BYTES).unwrap();
+    let sig = GenericSignature::from_bytes(&Base64::decode(TEST_SIGNATURE).unwrap()).unwrap();
+    let options = VerifyOptions {
+        jwks: vec![(jwk_id, jwk)].into_iter().collect(),
+        env: ZkLoginEnv::Test,
+        curr_epoch: 1,
+        author: None,
+        sponsor: false,
+        policy: VerifyPolicy::default(),
+        intent: None,
+    };
+
+    let res = verify_zklogin_signature(&bytes, &sig, IntentScope::TransactionData, options.clone());
+    assert!(res.unwrap().is_verified);
+
+    // The test signature's max epoch of 10 is too far ahead with a delta of 5.
+    let res = verify_zklogin_signature(
+        &bytes,
+        &sig,
+        IntentScope::TransactionData,
+        VerifyOptions {
+            policy: VerifyPolicy {
+                max_epoch_upper_bound_delta: Some(5),
+                ..Default::default()
+            },
+            ..options.clone()
+        },
+    );
+    assert_eq!(
+        res.unwrap_err(),
+        VerifyError::MaxEpochTooFar {
+            max_epoch: 10,
+            curr_epoch: 1,
+            max_delta: 5
+        }
+    );
+
+    // Missing JWK fails to verify, naming the kids known for the iss.
+    let (jwk_id, jwk) = test_jwk();
+    let other_kid = JwkId::new(jwk_id.iss.clone(), "2".to_string());
+    let res = verify_zklogin_signature(
+        &bytes,
+        &sig,
+        IntentScope::TransactionData,
+        VerifyOptions {
+            jwks: vec![(other_kid, jwk)].into_iter().collect(),
+            ..options
+        },
+    );
+    assert_eq!(
+        res.unwrap_err(),
+        VerifyError::JwkNotFound {
+            iss: jwk_id.iss,
+            kid: jwk_id.kid,
+            known_kids: vec!["2".to_string()],
+        }
+    );
+}
+
+#[test]
+fn test_verify_transaction_data_and_personal_message() {
+    let (jwk_id, jwk) = test_jwk();
+    let jwks: ImHashMap<JwkId, JWK> = vec![(jwk_id, jwk)].into_iter().collect();
+    let bytes = Base64::decode(TEST_BYTES).unwrap();
+    let sig = GenericSignature::from_bytes(&Base64::decode(TEST_SIGNATURE).unwrap()).unwrap();
+
+    let res = verify_transaction_data(&bytes, &sig, 1, jwks.clone(), ZkLoginEnv::Test).unwrap();
+    assert!(res.is_verified);
+    let tx_data: TransactionData = bcs::from_bytes(&bytes).unwrap();
+    assert_eq!(res.tx_digest, Some(tx_data.digest()));
+    let address = res.address.unwrap();
+    assert_eq!(
+        verify_transaction_data(&bytes, &sig, 11, jwks.clone(), ZkLoginEnv::Test).unwrap_err(),
+        VerifyError::SignatureExpired {
+            max_epoch: 10,
+            curr_epoch: 11
+        }
+    );
+
+    // The test signature is over the transaction, not a personal message of
+    // its bytes.
+    let res = verify_personal_message(&bytes, address, &sig, 1, jwks.clone(), ZkLoginEnv::Test);
+    assert_eq!(res.unwrap_err().code(), ErrorCode::VerifyFailed);
+    let res = verify_personal_message(&bytes, SuiAddress::ZERO, &sig, 1, jwks, ZkLoginEnv::Test);
+    assert_eq!(res.unwrap_err().code(), ErrorCode::AuthorMismatch);
+}
+
+#[test]
+fn test_config() {
+    let toml = r#"
+        bind = "127.0.0.1:8080"
+        providers = ["Google", "Twitch"]
+
+        [fullnode_urls]
+        Mainnet = ["http://localhost:9000", "http://localhost:9001"]
+    "#;
+    let config = Config::from_toml(toml).unwrap();
+    assert_eq!(config.bind, "127.0.0.1:8080".parse().unwrap());
+    assert_eq!(
+        config.oidc_providers().unwrap(),
+        vec![OIDCProvider::Google, OIDCProvider::Twitch]
+    );
+    assert_eq!(
+        config.fullnode_urls(crate::SuiEnv::Mainnet),
+        vec!["http://localhost:9000", "http://localhost:9001"]
+    );
+    assert_eq!(
+        config.fullnode_urls(crate::SuiEnv::Testnet),
+        vec!["https://fullnode.testnet.sui.io:443"]
+    );
+    // Unset fields take their defaults.
+    assert_eq!(config.jwk_refresh_interval_secs, 3600);
+    assert_eq!(config.verify_policy, VerifyPolicy::default());
+    assert_eq!(config.max_body_bytes, 256 * 1024);
+    assert_eq!(config.request_timeout_secs, 30);
+    // Zero timeouts would fail every request, or every fullnode call.
+    for toml in ["request_timeout_secs = 0", "fullnode_timeout_secs = 0"] {
+        assert!(Config::from_toml(toml).unwrap().validate().is_err());
+    }
+
+    let yaml = "bind: 127.0.0.1:8080\nproviders: [Google, Twitch]\nfullnode_urls:\n  Mainnet: [\"http://localhost:9000\", \"http://localhost:9001\"]\n";
+    assert_eq!(Config::from_yaml(yaml).unwrap(), config);
+
+    // The default network and those with fullnode URLs are tracked, unless
+    // set otherwise.
+    let config = Config::from_toml(
+        "default_network = \"Testnet\"\n[fullnode_urls]\nMainnet = [\"http://localhost:9000\"]\nTestnet = [\"http://localhost:9001\"]",
+    )
+    .unwrap();
+    assert_eq!(
+        config.tracked_networks(),
+        vec![crate::SuiEnv::Testnet, crate::SuiEnv::Mainnet]
+    );
+    let config = Config::from_toml("track_epoch_networks = []").unwrap();
+    assert!(config.tracked_networks().is_empty());
+
+    // Refresh intervals can be set per provider.
+    let config = Config::from_toml(
+        "jwk_refresh_interval_secs = 600\n[provider_refresh_interval_secs]\nGoogle = 60",
+    )
+    .unwrap();
+    assert_eq!(
+        config.jwk_refresh_interval(&OIDCProvider::Google),
+        Duration::from_secs(60)
+    );
+    assert_eq!(
+        config.jwk_refresh_interval(&OIDCProvider::Apple),
+        Duration::from_secs(600)
+    );
+    let config = Config::from_toml("[provider_refresh_interval_secs]\nUnknown = 60").unwrap();
+    assert!(config.oidc_providers().is_err());
+    // A zero interval would refetch the JWKs in a tight loop.
+    for toml in [
+        "jwk_refresh_interval_secs = 0",
+        "[provider_refresh_interval_secs]\nGoogle = 0",
+    ] {
+        assert!(Config::from_toml(toml).unwrap().validate().is_err());
+    }
+
+    // HTTP options can be tuned, keeping the defaults of unset ones.
+    let config =
+        Config::from_toml("[http]\nhttp2_max_concurrent_streams = 256\ntcp_nodelay = true")
+            .unwrap();
+    assert_eq!(config.http.http2_max_concurrent_streams, Some(256));
+    assert!(config.http.tcp_nodelay);
+    assert!(config.http.http1_keep_alive);
+    assert_eq!(config.http.http2_keep_alive_timeout_secs, 20);
+
+    // Unknown providers are rejected.
+    let config = Config::from_toml("providers = [\"Unknown\"]").unwrap();
+    assert!(config.oidc_providers().is_err());
+
+    // Duplicates are dropped, and an empty list is rejected.
+    let config = Config::from_toml("providers = [\"Google\", \"Apple\", \"Google\"]").unwrap();
+    assert_eq!(
+        config.oidc_providers().unwrap(),
+        vec![OIDCProvider::Google, OIDCProvider::Apple]
+    );
+    let config = Config::from_toml("providers = []").unwrap();
+    assert!(config.oidc_providers().is_err());
+
+    // Tenants are found by API key, which must be unique.
+    let tenants = "[[tenants]]\nname = \"a\"\napi_key = \"k1\"\ndefault_network = \"Devnet\"\n\n[[tenants]]\nname = \"b\"\napi_key = \"k2\"\n";
+    let config = Config::from_toml(tenants).unwrap();
+    assert!(config.validate().is_ok());
+    assert_eq!(config.tenant("k2").unwrap().name, "b");
+    assert_eq!(
+        config.tenant("k1").unwrap().default_network,
+        Some(crate::SuiEnv::Devnet)
+    );
+    assert!(config.tenant("k3").is_none());
+    let config = Config::from_toml(&tenants.replace("k2", "k1")).unwrap();
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn test_apply_provider_jwks() {
+    let state = AppState::default();
+    let (jwk_id, jwk) = test_jwk();
+    let iss = jwk_id.iss.clone();
+    let now = Instant::now();
+
+    assert!(apply_provider_jwks(
+        &state,
+        &iss,
+        vec![(jwk_id.clone(), jwk.clone())],
+        now
+    ));
+    assert_eq!(state.jwks.load().get(&jwk_id), Some(&jwk));
+
+    // Applying the same keys again changes nothing.
+    assert!(!apply_provider_jwks(
+        &state,
+        &iss,
+        vec![(jwk_id.clone(), jwk.clone())],
+        now
+    ));
+
+    // A kid returned with different key material is replaced.
+    let rotated = JWK {
+        n: "rotated".to_string(),
+        ..jwk.clone()
+    };
+    apply_provider_jwks(&state, &iss, vec![(jwk_id.clone(), rotated.clone())], now);
+    assert_eq!(state.jwks.load().get(&jwk_id), Some(&rotated));
+
+    // A key no longer returned is kept within the grace period, and pruned
+    // after it.
+    let grace_period = Duration::from_secs(state.config.load().jwk_prune_grace_period_secs);
+    apply_provider_jwks(&state, &iss, vec![], now + grace_period);
+    assert!(state.jwks.load().contains_key(&jwk_id));
+    apply_provider_jwks(
+        &state,
+        &iss,
+        vec![],
+        now + grace_period + Duration::from_secs(1),
+    );
+    assert!(!state.jwks.load().contains_key(&jwk_id));
+
+    // Keys of other providers are left alone.
+    apply_provider_jwks(&state, &iss, vec![(jwk_id.clone(), jwk)], now);
+    apply_provider_jwks(
+        &state,
+        "https://accounts.google.com",
+        vec![],
+        now + grace_period * 2,
+    );
+    assert!(state.jwks.load().contains_key(&jwk_id));
+}
+
+#[tokio::test]
+async fn test_admin_jwks() {
+    let state = Arc::new(AppState {
+        config: Config {
+            admin_token: Some("secret".to_string()),
+            ..Default::default()
+        }
+        .into(),
+        ..Default::default()
+    });
+    let (jwk_id, jwk) = test_jwk();
+    let request = || {
+        serde_json::from_value::<AdminJwksRequest>(serde_json::json!({
+            "iss": jwk_id.iss,
+            "keys": [{ "kid": jwk_id.kid, "kty": jwk.kty, "e": jwk.e, "n": jwk.n }],
+        }))
+        .unwrap()
+    };
+
+    // Rejected without the admin token.
+    let mut headers = HeaderMap::new();
+    let res = admin_jwks(State(state.clone()), headers.clone(), Json(request())).await;
+    assert_eq!(res.unwrap_err().0, StatusCode::UNAUTHORIZED);
+    headers.insert(AUTHORIZATION, "Bearer wrong".parse().unwrap());
+    let res = admin_jwks(State(state.clone()), headers.clone(), Json(request())).await;
+    assert_eq!(res.unwrap_err().0, StatusCode::UNAUTHORIZED);
+    assert!(state.jwks.load().is_empty());
+
+    // Inserted with the admin token, after which the test signature verifies.
+    headers.insert(AUTHORIZATION, "Bearer secret".parse().unwrap());
+    let res = admin_jwks(State(state.clone()), headers.clone(), Json(request())).await;
+    assert_eq!(res.unwrap().inserted, 1);
+    assert_eq!(state.jwks.load().get(&jwk_id), Some(&jwk));
+    assert_eq!(
+        state.jwk_metadata.lock().get(&jwk_id).unwrap().source,
+        JwkSource::Admin
+    );
+    let res = verify(State(state.clone()), Json(test_request())).await;
+    assert!(res.unwrap().is_verified);
+
+    // A single JWK with its iss and kid is accepted too.
+    let single = serde_json::from_value::<AdminJwksRequest>(serde_json::json!({
+        "iss": "https://accounts.google.com",
+        "kid": "2",
+        "kty": "RSA",
+        "alg": "RS256",
+        "n": "abc",
+        "e": "AQAB",
+    }))
+    .unwrap();
+    let res = admin_jwks(State(state.clone()), headers, Json(single)).await;
+    assert_eq!(res.unwrap().inserted, 1);
+    assert_eq!(state.jwks.load().len(), 2);
+}
+
+#[tokio::test]
+async fn test_get_jwks_status() {
+    let state = Arc::new(AppState {
+        providers: vec![OIDCProvider::Twitch, OIDCProvider::Google].into(),
+        ..Default::default()
+    });
+    let (jwk_id, jwk) = test_jwk();
+    state.update_jwks(|jwks| jwks.insert(jwk_id, jwk));
+    state.fetch_status.lock().insert(
+        OIDCProvider::Twitch,
+        FetchStatus {
+            last_success: Some(SystemTime::now()),
+            ..Default::default()
+        },
+    );
+    state.fetch_status.lock().insert(
+        OIDCProvider::Google,
+        FetchStatus {
+            last_error: Some("timeout".to_string()),
+            ..Default::default()
+        },
+    );
+
+    let Json(res) = get_jwks_status(State(state)).await;
+    let twitch = &res.providers[0];
+    assert_eq!(twitch.provider, "Twitch");
+    assert_eq!(twitch.keys, 1);
+    assert!(twitch.last_success.is_some());
+    assert!(!twitch.overdue);
+    let google = &res.providers[1];
+    assert_eq!(google.keys, 0);
+    assert_eq!(google.last_error.as_deref(), Some("timeout"));
+    assert!(google.overdue);
+}
+
+#[tokio::test]
+async fn test_stale_data() {
+    let state = Arc::new(AppState {
+        config: Config {
+            stale_jwks_after_secs: Some(60),
+            stale_epoch_after_secs: Some(60),
+            ..Default::default()
+        }
+        .into(),
+        ..Default::default()
+    });
+    let (jwk_id, jwk) = test_jwk();
+    state.update_jwks(|jwks| jwks.insert(jwk_id, jwk));
+    let fetched_ago = |secs| Some(SystemTime::now() - Duration::from_secs(secs));
+    state.fetch_status.lock().insert(
+        OIDCProvider::Twitch,
+        FetchStatus {
+            last_success: fetched_ago(30),
+            ..Default::default()
+        },
+    );
+    state.epochs.lock().insert(
+        crate::SuiEnv::Devnet,
+        EpochStatus {
+            epoch: 1,
+            fetched_at: SystemTime::now() - Duration::from_secs(120),
+            next_epoch_at: SystemTime::now() + Duration::from_secs(3600),
+        },
+    );
+    let tracked_epoch = VerifyRequest {
+        curr_epoch: None,
+        ..test_request()
+    };
+
+    let res = verify(State(state.clone()), Json(tracked_epoch.clone()))
+        .await
+        .unwrap();
+    let stale_data = res.0.stale_data.unwrap();
+    assert_eq!(stale_data.jwks_age_secs, None);
+    assert!(stale_data.epoch_age_secs.unwrap() >= 120);
+
+    // An epoch of the request is never stale.
+    let res = verify(State(state.clone()), Json(test_request()))
+        .await
+        .unwrap();
+    assert_eq!(res.0.stale_data, None);
+
+    state
+        .fetch_status
+        .lock()
+        .get_mut(&OIDCProvider::Twitch)
+        .unwrap()
+        .last_success = fetched_ago(120);
+    let res = verify(State(state.clone()), Json(test_request()))
+        .await
+        .unwrap();
+    assert!(res.0.stale_data.unwrap().jwks_age_secs.unwrap() >= 120);
+}
+
+#[tokio::test]
+async fn test_concurrency_limiter() {
+    assert!(ConcurrencyLimiter::new(None)
+        .acquire()
+        .await
+        .unwrap()
+        .is_none());
+
+    let limiter = ConcurrencyLimiter::new(Some(&ConcurrencyLimitConfig {
+        max_in_flight: 1,
+        max_queued: 1,
+    }));
+    let first = limiter.acquire().await.unwrap();
+    let waiting = tokio::spawn({
+        let limiter = limiter.clone();
+        async move { limiter.acquire().await.map(|permit| permit.is_some()) }
+    });
+    while limiter.queued() == 0 {
+        tokio::task::yield_now().await;
+    }
+    // The queue is full.
+    assert_eq!(limiter.acquire().await.unwrap_err(), Overloaded);
+
+    drop(first);
+    assert_eq!(waiting.await.unwrap(), Ok(true));
+    assert_eq!(limiter.queued(), 0);
+}
+
+#[test]
+fn test_log_sampler() {
+    struct CountEvents(Arc<AtomicUsize>);
+    impl<S: tracing::Subscriber> Layer<S> for CountEvents {
+        fn on_event(&self, _: &tracing::Event<'_>, _: Context<'_, S>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+    let count = Arc::new(AtomicUsize::new(0));
+    let subscriber = tracing_subscriber::registry()
+        .with(LogSampler::new(3))
+        .with(CountEvents(count.clone()));
+    tracing::subscriber::with_default(subscriber, || {
+        for i in 0..6 {
+            let _span = tracing::info_span!("request").entered();
+            tracing::info!("verify called");
+            tracing::warn!("fullnode slow");
+            if i % 2 == 1 {
+                tracing::info!(failed = true, "verify failed");
+            }
+            tracing::info!("verify answered");
+        }
+        tracing::info!("outside of requests");
+    });
+    // All logs of the sampled requests 0 and 3, the warnings, failures and
+    // logs after the failures of requests 1 and 5, the warnings of requests 2
+    // and 4, and the log outside of requests.
+    assert_eq!(count.load(Ordering::SeqCst), (3 + 4) + 2 * 3 + 2 + 1);
+}
+
+#[test]
+fn test_access_log_capacity() {
+    /// Blocks every write until released, reporting that it started.
+    struct StalledWriter {
+        started: std::sync::mpsc::Sender<()>,
+        release: std::sync::mpsc::Receiver<()>,
+    }
+    impl std::io::Write for StalledWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let _ = self.started.send(());
+            let _ = self.release.recv();
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    let (started, started_rx) = std::sync::mpsc::channel();
+    let (release_tx, release) = std::sync::mpsc::channel();
+    let dropped = prometheus::IntCounter::new("dropped", "dropped").unwrap();
+    let log = AccessLog::with_writer(
+        Box::new(StalledWriter { started, release }),
+        1,
+        dropped.clone(),
+    );
+    let entry = AccessLogEntry {
+        timestamp_ms: 0,
+        method: "GET".to_string(),
+        path: "/health".to_string(),
+        status: 200,
+        latency_ms: 1.0,
+        client_ip: None,
+        request_id: None,
+    };
+
+    // The writer stalls on the first entry, the second waits and the third
+    // is dropped instead of queueing without bound.
+    log.log(entry.clone());
+    started_rx.recv().unwrap();
+    log.log(entry.clone());
+    log.log(entry);
+    assert_eq!(dropped.get(), 1);
+    drop(release_tx);
+}
+
+#[test]
+fn test_slo_burn_rates() {
+    let config: Config = Config::from_toml(
+        r#"
+        [metrics]
+        verify_latency_buckets = [0.001, 0.01, 0.1]
+
+        [[metrics.slos]]
+        name = "latency"
+        objective = 0.75
+        latency_threshold_secs = 0.05
+        windows_secs = [60, 600]
+    "#,
+    )
+    .unwrap();
+    config.validate().unwrap();
+    let metrics = Metrics::new(Registry::new(), &config.metrics);
+    let slos = SloTracker::new(&config.metrics.slos);
+    let rates = |now| {
+        slos.burn_rates(now)
+            .into_iter()
+            .map(|rate| (rate.window_secs, rate.rate))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(rates(1000), vec![(60, 0.0), (600, 0.0)]);
+
+    // Errors and slow requests are bad, rejections are good.
+    slos.record(&metrics, Duration::from_millis(10), "verified", 500);
+    slos.record(&metrics, Duration::from_millis(10), "rejected", 500);
+    slos.record(&metrics, Duration::from_millis(100), "verified", 950);
+    slos.record(&metrics, Duration::from_millis(10), "error", 950);
+    // All bad in the last minute and half overall, with a quarter allowed.
+    assert_eq!(rates(1000), vec![(60, 4.0), (600, 2.0)]);
+    assert_eq!(rates(1200), vec![(60, 0.0), (600, 4.0)]);
+    assert_eq!(
+        metrics
+            .slo_requests
+            .with_label_values(&["latency", "bad"])
+            .get(),
+        2
+    );
+
+    let mut config = Config::default();
+    config.metrics.verify_latency_buckets = vec![0.1, 0.01];
+    assert!(config.validate().is_err());
+    config.metrics.verify_latency_buckets = Vec::new();
+    config.metrics.slos = vec![SloConfig {
+        name: "availability".to_string(),
+        objective: 1.0,
+        latency_threshold_secs: None,
+        windows_secs: vec![300],
+    }];
+    assert!(config.validate().is_err());
+}
+
+#[tokio::test]
+async fn test_compute_pool() {
+    // At least one thread, however many CPUs are reserved.
+    assert_eq!(ComputePool::new(None, usize::MAX).size(), 1);
+    assert_eq!(ComputePool::new(Some(3), usize::MAX).size(), 3);
+
+    let pool = ComputePool::new(Some(1), 0);
+    let (release, released) = std::sync::mpsc::channel::<()>();
+    let running = tokio::spawn({
+        let pool = pool.clone();
+        async move { pool.run(move || released.recv().unwrap()).await }
+    });
+    let queued = tokio::spawn({
+        let pool = pool.clone();
+        async move { pool.run(|| 1).await }
+    });
+    while pool.queue_depth() == 0 {
+        tokio::task::yield_now().await;
+    }
+    assert_eq!(pool.queue_depth(), 1);
+
+    release.send(()).unwrap();
+    running.await.unwrap();
+    assert_eq!(queued.await.unwrap(), 1);
+    assert_eq!(pool.queue_depth(), 0);
+}
+
+#[tokio::test]
+async fn test_compute_pool_cancelled() {
+    let pool = ComputePool::new(Some(1), 0);
+    let running = Arc::new(AtomicUsize::new(0));
+    let max_running = Arc::new(AtomicUsize::new(0));
+    let job = {
+        let running = running.clone();
+        let max_running = max_running.clone();
+        move |wait: std::sync::mpsc::Receiver<()>| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            max_running.fetch_max(now, Ordering::SeqCst);
+            let _ = wait.recv();
+            running.fetch_sub(1, Ordering::SeqCst);
+        }
+    };
+
+    let (release, released) = std::sync::mpsc::channel::<()>();
+    let cancelled = tokio::spawn({
+        let (pool, job) = (pool.clone(), job.clone());
+        async move { pool.run(move || job(released)).await }
+    });
+    while running.load(Ordering::SeqCst) == 0 {
+        tokio::task::yield_now().await;
+    }
+    // The request is dropped, e.g. on a timeout, while its proof is still
+    // being verified.
+    cancelled.abort();
+    assert!(cancelled.await.unwrap_err().is_cancelled());
+
+    let (_, done) = std::sync::mpsc::channel::<()>();
+    let next = tokio::spawn({
+        let pool = pool.clone();
+        async move { pool.run(move || job(done)).await }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(pool.queue_depth(), 1);
+
+    release.send(()).unwrap();
+    next.await.unwrap();
+    assert_eq!(max_running.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_backoff_delay() {
+    assert_eq!(backoff_delay(1), Duration::from_secs(5));
+    assert_eq!(backoff_delay(2), Duration::from_secs(10));
+    assert_eq!(backoff_delay(3), Duration::from_secs(20));
+    assert_eq!(backoff_delay(8), Duration::from_secs(600));
+    assert_eq!(backoff_delay(u32::MAX), Duration::from_secs(600));
+}
+
+#[tokio::test]
+async fn test_admin_refresh_jwks() {
+    let state = Arc::new(AppState {
+        providers: vec![OIDCProvider::Twitch].into(),
+        config: Config {
+            admin_token: Some("secret".to_string()),
+            ..Default::default()
+        }
+        .into(),
+        ..Default::default()
+    });
+    let request = |provider: &str| {
+        Json(AdminRefreshRequest {
+            provider: provider.to_string(),
+        })
+    };
+    let mut headers = HeaderMap::new();
+    let res = admin_refresh_jwks(State(state.clone()), headers.clone(), request("Twitch")).await;
+    assert_eq!(res.unwrap_err().0, StatusCode::UNAUTHORIZED);
+
+    headers.insert(AUTHORIZATION, "Bearer secret".parse().unwrap());
+    let res = admin_refresh_jwks(State(state.clone()), headers.clone(), request("Google")).await;
+    assert_eq!(res.unwrap_err().0, StatusCode::BAD_REQUEST);
+
+    // The trigger wakes the updater of the provider.
+    let trigger = state.refresh_trigger(&OIDCProvider::Twitch);
+    let res = admin_refresh_jwks(State(state.clone()), headers, request("Twitch")).await;
+    assert_eq!(res.unwrap(), StatusCode::ACCEPTED);
+    tokio::time::timeout(Duration::from_secs(1), trigger.notified())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_reload_config() {
+    let config = Config {
+        providers: vec!["Twitch".to_string()],
+        ..Default::default()
+    };
+    let state = AppState::new(config.clone());
+    let (jwk_id, jwk) = test_jwk();
+    state.update_jwks(|jwks| jwks.insert(jwk_id, jwk));
+    assert_eq!(
+        reload_config(&state).unwrap_err(),
+        "Config reloading is not enabled"
+    );
+
+    let new = Config {
+        providers: vec!["Google".to_string()],
+        allowed_issuers: Some(vec!["https://accounts.google.com".to_string()]),
+        rate_limit: RateLimitConfig {
+            global: Some(BucketConfig {
+                per_second: 1.0,
+                burst: 1,
+            }),
+            ..Default::default()
+        },
+        // Not reloaded.
+        max_batch_size: 1,
+        ..config.clone()
+    };
+    assert_eq!(
+        apply_config(&state, new.clone()).unwrap(),
+        vec!["providers", "allowed_issuers", "rate_limit"]
+    );
+    assert_eq!(*state.providers.load(), vec![OIDCProvider::Google]);
+    assert_eq!(state.config.load().allowed_issuers, new.allowed_issuers);
+    assert_eq!(state.config.load().max_batch_size, config.max_batch_size);
+    // The JWKs of the removed provider no longer verify.
+    assert!(state.jwks.load().is_empty());
+    tokio::time::timeout(Duration::from_secs(1), state.providers_changed.notified())
+        .await
+        .unwrap();
+    let ip = "10.0.0.1".parse().unwrap();
+    assert!(state.rate_limiter.check(ip, Instant::now()).is_ok());
+    assert!(state.rate_limiter.check(ip, Instant::now()).is_err());
+
+    // Invalid configs change nothing.
+    let invalid = Config {
+        providers: vec!["Unknown".to_string()],
+        ..new.clone()
+    };
+    assert!(apply_config(&state, invalid).is_err());
+    assert_eq!(*state.providers.load(), vec![OIDCProvider::Google]);
+    assert!(apply_config(&state, new).unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_version() {
+    let res = get_version().await.0;
+    assert_eq!(res.version, env!("CARGO_PKG_VERSION"));
+    assert!(!res.git_commit.is_empty());
+    assert!(res.build_timestamp > 0);
+    assert_eq!(
+        res.dependencies.keys().collect::<Vec<_>>(),
+        vec!["fastcrypto", "fastcrypto-zkp", "sui-types"]
+    );
+}
+
+#[tokio::test]
+async fn test_default_network() {
+    let request = VerifyRequest {
+        network: None,
+        ..test_request()
+    };
+    // The test signature is for Devnet.
+    let state = Arc::new(AppState::default());
+    state.update_jwks(|jwks| {
+        let (jwk_id, jwk) = test_jwk();
+        jwks.insert(jwk_id, jwk);
+    });
+    let err = verify(State(state), Json(request.clone()))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::ProofVerifyFailed);
+
+    let state = Arc::new(AppState::new(Config {
+        default_network: crate::SuiEnv::Devnet,
+        ..Default::default()
+    }));
+    state.update_jwks(|jwks| {
+        let (jwk_id, jwk) = test_jwk();
+        jwks.insert(jwk_id, jwk);
+    });
+    assert!(verify(State(state.clone()), Json(request)).await.is_ok());
+    assert_eq!(
+        get_capabilities(State(state)).await.0.default_network,
+        crate::SuiEnv::Devnet
+    );
+}
+
+#[tokio::test]
+async fn test_capabilities() {
+    let state = Arc::new(AppState::new(Config {
+        providers: vec!["Google".to_string(), "Twitch".to_string()],
+        allowed_issuers: Some(vec!["https://accounts.google.com".to_string()]),
+        epoch_tolerance: 1,
+        ..Default::default()
+    }));
+    let res = get_capabilities(State(state)).await.0;
+    assert_eq!(
+        res.intent_scopes,
+        vec!["TransactionData", "PersonalMessage"]
+    );
+    assert_eq!(res.networks.len(), 4);
+    assert_eq!(res.providers, vec!["Google", "Twitch"]);
+    assert_eq!(
+        res.allowed_issuers,
+        Some(vec!["https://accounts.google.com".to_string()])
+    );
+    assert_eq!(res.verify_policy, VerifyPolicy::default());
+    assert_eq!(res.epoch_tolerance, 1);
+    assert!(!res.allow_inline_jwks);
+}
+
+#[test]
+fn test_self_check() {
+    self_check().unwrap();
+}
+
+#[test]
+fn test_rate_limiter() {
+    let bucket = BucketConfig {
+        per_second: 1.0,
+        burst: 2,
+    };
+    let limiter = RateLimiter::new(RateLimitConfig {
+        per_ip: Some(bucket.clone()),
+        ..Default::default()
+    });
+    let (a, b): (IpAddr, IpAddr) = ("10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap());
+    let now = Instant::now();
+
+    // Each client gets its own burst, then has to wait for a refill.
+    assert!(limiter.check(a, now).is_ok());
+    assert!(limiter.check(a, now).is_ok());
+    assert_eq!(limiter.check(a, now), Err(Duration::from_secs(1)));
+    assert!(limiter.check(b, now).is_ok());
+    assert!(limiter.check(a, now + Duration::from_secs(1)).is_ok());
+
+    // The global bucket is shared by all clients.
+    let limiter = RateLimiter::new(RateLimitConfig {
+        global: Some(bucket),
+        ..Default::default()
+    });
+    assert!(limiter.check(a, now).is_ok());
+    assert!(limiter.check(b, now).is_ok());
+    assert!(limiter.check(a, now).is_err());
+
+    // A request rejected by the global limit keeps the token of its client.
+    let limiter = RateLimiter::new(RateLimitConfig {
+        per_ip: Some(BucketConfig {
+            per_second: 0.1,
+            burst: 1,
+        }),
+        global: Some(BucketConfig {
+            per_second: 1.0,
+            burst: 1,
+        }),
+        ..Default::default()
+    });
+    assert!(limiter.check(a, now).is_ok());
+    assert_eq!(limiter.check(b, now), Err(Duration::from_secs(1)));
+    assert!(limiter.check(b, now + Duration::from_secs(1)).is_ok());
+
+    // Non-positive rates are rejected.
+    let config = Config::from_toml("[rate_limit.global]\nper_second = 0\nburst = 1").unwrap();
+    assert!(config.validate().is_err());
+}
+
+#[tokio::test]
+async fn test_address_rate_limit() {
+    let state = Arc::new(AppState::new(Config {
+        rate_limit: RateLimitConfig {
+            per_address: Some(BucketConfig {
+                per_second: 0.1,
+                burst: 1,
+            }),
+            ..Default::default()
+        },
+        ..Default::default()
+    }));
+    state.update_jwks(|jwks| {
+        let (jwk_id, jwk) = test_jwk();
+        jwks.insert(jwk_id, jwk);
+    });
+    assert!(verify(State(state.clone()), Json(test_request()))
+        .await
+        .is_ok());
+
+    // The address of the test signature has used up its burst, whichever
+    // client sends it.
+    let err = verify(State(state.clone()), Json(test_request()))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::RateLimited);
+    let response = err.into_response();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()[RETRY_AFTER], "10");
+
+    // Other addresses have their own buckets.
+    let now = Instant::now();
+    assert!(state
+        .rate_limiter
+        .check_address(SuiAddress::ZERO, now)
+        .is_ok());
+    assert!(state
+        .rate_limiter
+        .check_address(SuiAddress::ZERO, now)
+        .is_err());
+}
+
+#[test]
+fn test_cors_config() {
+    let config = Config::from_toml(
+        "[cors]\nallowed_origins = [\"https://app.example.com\"]\nallowed_methods = [\"POST\"]",
+    )
+    .unwrap();
+    let cors = config.cors.as_ref().unwrap();
+    assert_eq!(cors.allowed_origins, vec!["https://app.example.com"]);
+    // Unset fields take their defaults.
+    assert_eq!(cors.allowed_headers, vec!["content-type"]);
+    assert!(config.validate().is_ok());
+
+    let config = Config::from_toml("[cors]\nallowed_methods = [\"NOT A METHOD\"]").unwrap();
+    assert!(config.validate().is_err());
+}
+
+#[tokio::test]
+async fn test_request_id_in_error_body() {
+    // Outside of a request, errors carry no request ID.
+    let body = error_body("Unauthorized", ErrorCode::Unauthorized).0;
+    assert_eq!(body["code"], "UNAUTHORIZED");
+    assert!(body.get("request_id").is_none());
+
+    let body = REQUEST_ID
+        .scope("abc".to_string(), async {
+            error_body("Unauthorized", ErrorCode::Unauthorized)
+        })
+        .await
+        .0;
+    assert_eq!(body["request_id"], "abc");
+}
+
+#[test]
+fn test_intent_scope_names() {
+    let request = |scope: &str| {
+        serde_json::from_str::<VerifyRequest>(&format!(
+            r#"{{"signature": "", "bytes": "", "intent_scope": {}}}"#,
+            scope
+        ))
+    };
+    assert_eq!(
+        request("3").unwrap().intent_scope,
+        IntentScope::PersonalMessage
+    );
+    assert_eq!(
+        request(r#""TransactionData""#).unwrap().intent_scope,
+        IntentScope::TransactionData
+    );
+    assert_eq!(
+        request(r#""personalmessage""#).unwrap().intent_scope,
+        IntentScope::PersonalMessage
+    );
+    assert!(request(r#""CheckpointSummary""#)
+        .unwrap_err()
+        .to_string()
+        .contains("Unsupported intent scope"));
+}
+
+#[test]
+fn test_verify_policy() {
+    let config = Config::from_toml("[verify_policy]\nverify_legacy_address = false").unwrap();
+    assert!(!config.verify_policy.verify_legacy_address);
+    assert!(config.verify_policy.accept_zklogin_in_multisig);
+
+    assert_eq!(config.verify_policy.max_epoch_upper_bound_delta, Some(30));
+
+    // TOML has no null, so an unbounded max epoch is `0` or "none".
+    for delta in ["0", "\"none\""] {
+        let config = Config::from_toml(&format!(
+            "[verify_policy]\nmax_epoch_upper_bound_delta = {}",
+            delta
+        ))
+        .unwrap();
+        assert_eq!(config.verify_policy.max_epoch_upper_bound_delta, None);
+    }
+    assert!(Config::from_toml("[verify_policy]\nmax_epoch_upper_bound_delta = \"all\"").is_err());
+
+    // A request can disable what the server allows, but not enable what it
+    // disables.
+    let strict = VerifyPolicy {
+        verify_legacy_address: true,
+        accept_zklogin_in_multisig: false,
+        max_epoch_upper_bound_delta: None,
+    };
+    let request: VerifyPolicyOverride = serde_json::from_value(serde_json::json!({
+        "verify_legacy_address": false,
+        "accept_zklogin_in_multisig": true,
+        "max_epoch_upper_bound_delta": 5,
+    }))
+    .unwrap();
+    assert_eq!(
+        strict.restrict(request),
+        VerifyPolicy {
+            verify_legacy_address: false,
+            accept_zklogin_in_multisig: false,
+            max_epoch_upper_bound_delta: Some(5),
+        }
+    );
+
+    // Fields the request does not set keep the policy of the server, also
+    // an unbounded max epoch.
+    let request: VerifyPolicyOverride =
+        serde_json::from_value(serde_json::json!({"verify_legacy_address": false})).unwrap();
+    assert_eq!(request.max_epoch_upper_bound_delta, None);
+    assert_eq!(
+        strict.restrict(request),
+        VerifyPolicy {
+            verify_legacy_address: false,
+            ..strict
+        }
+    );
+
+    // A request cannot lift the bound of the server.
+    let request: VerifyPolicyOverride =
+        serde_json::from_value(serde_json::json!({ "max_epoch_upper_bound_delta": null })).unwrap();
+    assert_eq!(request.max_epoch_upper_bound_delta, Some(None));
+    assert_eq!(
+        VerifyPolicy::default().restrict(request),
+        VerifyPolicy::default()
+    );
+}
+
+#[tokio::test]
+async fn test_inline_jwk() {
+    let (jwk_id, jwk) = test_jwk();
+    let request = || VerifyRequest {
+        jwk: Some(JwkEntry::new(&jwk_id, &jwk)),
+        ..test_request()
+    };
+
+    // Inline JWKs are rejected unless allowed.
+    let state = Arc::new(AppState::default());
+    let res = verify(State(state), Json(request())).await;
+    assert_eq!(res.unwrap_err(), VerifyError::InlineJwkNotAllowed);
+
+    // The inline JWK is used even though the cache is empty.
+    let state = Arc::new(AppState {
+        config: Config {
+            allow_inline_jwks: true,
+            ..Default::default()
+        }
+        .into(),
+        ..Default::default()
+    });
+    let res = verify(State(state), Json(request())).await;
+    assert!(res.unwrap().0.is_verified);
+}
+
+#[tokio::test]
+async fn test_allowed_issuers() {
+    let state = |allowed_issuers: Vec<&str>| {
+        let state = AppState {
+            config: Config {
+                allowed_issuers: Some(allowed_issuers.into_iter().map(String::from).collect()),
+                ..Default::default()
+            }
+            .into(),
+            ..Default::default()
+        };
+        state.update_jwks(|jwks| {
+            let (jwk_id, jwk) = test_jwk();
+            jwks.insert(jwk_id, jwk);
+        });
+        Arc::new(state)
+    };
+
+    let res = verify(
+        State(state(vec!["https://accounts.google.com"])),
+        Json(test_request()),
+    )
+    .await;
+    assert_eq!(
+        res.unwrap_err(),
+        VerifyError::IssuerNotAllowed("https://id.twitch.tv/oauth2".to_string())
+    );
+
+    let res = verify(
+        State(state(vec!["https://id.twitch.tv/oauth2"])),
+        Json(test_request()),
+    )
+    .await;
+    assert!(res.unwrap().0.is_verified);
+}
+
+#[tokio::test]
+async fn test_verify_ephemeral() {
+    // Only the ephemeral signature is checked, so no JWK or epoch is needed.
+    let res = verify_ephemeral(Json(VerifyRequest {
+        curr_epoch: Some(100),
+        ..test_request()
+    }))
+    .await
+    .unwrap()
+    .0;
+    assert!(res.is_verified);
+    assert_eq!(res.ephemeral_scheme, "ED25519");
+
+    // Other bytes were not signed by the ephemeral key.
+    let mut tx_data: TransactionData =
+        bcs::from_bytes(&Base64::decode(TEST_BYTES).unwrap()).unwrap();
+    tx_data.gas_data_mut().budget += 1;
+    let res = verify_ephemeral(Json(VerifyRequest {
+        bytes: Base64::encode(bcs::to_bytes(&tx_data).unwrap()),
+        ..test_request()
+    }))
+    .await;
+    assert_eq!(res.unwrap_err().code(), ErrorCode::VerifyFailed);
+
+    // The bytes were signed under the Sui intent, not another app ID.
+    let res = verify_ephemeral(Json(VerifyRequest {
+        intent: Some(Intent {
+            scope: IntentScope::TransactionData,
+            version: IntentVersion::V0,
+            app_id: AppId::Narwhal,
+        }),
+        ..test_request()
+    }))
+    .await;
+    assert_eq!(res.unwrap_err().code(), ErrorCode::VerifyFailed);
+    let res = verify_ephemeral(Json(VerifyRequest {
+        intent: Some(Intent {
+            scope: IntentScope::PersonalMessage,
+            version: IntentVersion::V0,
+            app_id: AppId::Sui,
+        }),
+        ..test_request()
+    }))
+    .await;
+    assert_eq!(res.unwrap_err().code(), ErrorCode::IntentScopeMismatch);
+}
+
+#[tokio::test]
+async fn test_verify_transaction() {
+    let state = Arc::new(AppState::default());
+    state.update_jwks(|jwks| {
+        let (jwk_id, jwk) = test_jwk();
+        jwks.insert(jwk_id, jwk);
+    });
+    let request = |signatures: Vec<String>| VerifyTransactionRequest {
+        bytes: TEST_BYTES.to_string(),
+        signatures,
+        network: Some(crate::SuiEnv::Devnet),
+        curr_epoch: Some(1),
+        encoding: None,
+        dry_run: None,
+    };
+
+    let res = verify_transaction(
+        State(state.clone()),
+        Json(request(vec![TEST_SIGNATURE.to_string()])),
+    )
+    .await
+    .unwrap()
+    .0;
+    assert!(res.is_verified);
+    let tx_data: TransactionData = bcs::from_bytes(&Base64::decode(TEST_BYTES).unwrap()).unwrap();
+    assert_eq!(res.tx_digest, tx_data.digest());
+    assert_eq!(res.signatures.len(), 1);
+    assert!(res.signatures[0].is_verified);
+    assert_eq!(res.signatures[0].scheme, "ZkLoginAuthenticator");
+    assert!(res.signatures[0].signer.is_some());
+
+    // A signature by a key that is not a signer of the transaction.
+    let tx_data: TransactionData = bcs::from_bytes(&Base64::decode(TEST_BYTES).unwrap()).unwrap();
+    let (_, kp): (_, Ed25519KeyPair) = get_key_pair();
+    let ed25519_sig =
+        Signature::new_secure(&IntentMessage::new(Intent::sui_transaction(), tx_data), &kp);
+    let res = verify_transaction(
+        State(state.clone()),
+        Json(request(vec![
+            TEST_SIGNATURE.to_string(),
+            Base64::encode(ed25519_sig.as_ref()),
+        ])),
+    )
+    .await
+    .unwrap()
+    .0;
+    assert!(!res.is_verified);
+    assert!(res.signatures[0].is_verified);
+    assert!(!res.signatures[1].is_verified);
+    assert_eq!(res.signatures[1].scheme, "ED25519");
+    assert!(res.signatures[1].error.is_some());
+
+    // Validators reject a signer signing twice.
+    let res = verify_transaction(
+        State(state.clone()),
+        Json(request(vec![
+            TEST_SIGNATURE.to_string(),
+            TEST_SIGNATURE.to_string(),
+        ])),
+    )
+    .await
+    .unwrap()
+    .0;
+    assert!(!res.is_verified);
+    assert!(res.signatures.iter().all(|v| v.is_verified));
+
+    // No signature at all.
+    let res = verify_transaction(State(state), Json(request(vec![])))
+        .await
+        .unwrap()
+        .0;
+    assert!(!res.is_verified);
+}
+
+#[tokio::test]
+async fn test_verify_cache() {
+    let state = Arc::new(AppState {
+        verify_cache: VerifyCache::new(10),
+        ..Default::default()
+    });
+    state.update_jwks(|jwks| {
+        let (jwk_id, jwk) = test_jwk();
+        jwks.insert(jwk_id, jwk);
+    });
+
+    for _ in 0..3 {
+        let res = verify(State(state.clone()), Json(test_request())).await;
+        assert!(res.unwrap().0.is_verified);
+    }
+    assert_eq!(state.metrics.verify_cache_hits.get(), 2);
+
+    // Another epoch is another key.
+    let res = verify(
+        State(state.clone()),
+        Json(VerifyRequest {
+            curr_epoch: Some(2),
+            ..test_request()
+        }),
+    )
+    .await;
+    assert!(res.unwrap().0.is_verified);
+    assert_eq!(state.metrics.verify_cache_hits.get(), 2);
+
+    // Failures are not cached.
+    for _ in 0..2 {
+        let res = verify(
+            State(state.clone()),
+            Json(VerifyRequest {
+                network: Some(crate::SuiEnv::Mainnet),
+                ..test_request()
+            }),
+        )
+        .await;
+        assert!(res.is_err());
+    }
+    assert_eq!(state.metrics.verify_cache_hits.get(), 2);
+
+    // Refetching the same JWKs keeps the cache.
+    let (jwk_id, jwk) = test_jwk();
+    let iss = jwk_id.iss.clone();
+    assert!(!apply_provider_jwks(
+        &state,
+        &iss,
+        vec![(jwk_id, jwk)],
+        Instant::now()
+    ));
+    let res = verify(State(state.clone()), Json(test_request())).await;
+    assert!(res.unwrap().0.is_verified);
+    assert_eq!(state.metrics.verify_cache_hits.get(), 3);
+
+    // A JWK update clears the cache, so a removed JWK no longer verifies.
+    state.update_jwks(|jwks| jwks.clear());
+    let res = verify(State(state.clone()), Json(test_request())).await;
+    assert_eq!(res.unwrap_err().code(), ErrorCode::UnknownJwk);
+    assert_eq!(state.metrics.verify_cache_hits.get(), 3);
+}
+
+#[test]
+fn test_compare_jwks() {
+    let (id, jwk) = test_jwk();
+    let iss = id.iss.clone();
+    let other_id = |kid: &str| JwkId::new(iss.clone(), kid.to_string());
+    let other_jwk = JWK {
+        n: "other".to_string(),
+        ..jwk.clone()
+    };
+    let fetched: ImHashMap<JwkId, JWK> = [
+        (id.clone(), jwk.clone()),
+        (other_id("2"), jwk.clone()),
+        (other_id("3"), jwk.clone()),
+        (
+            JwkId::new("ignored".to_string(), "1".to_string()),
+            jwk.clone(),
+        ),
+    ]
+    .into_iter()
+    .collect();
+    let on_chain = vec![
+        (id.clone(), jwk.clone()),
+        (other_id("3"), other_jwk),
+        (other_id("4"), jwk.clone()),
+        (JwkId::new("ignored".to_string(), "2".to_string()), jwk),
+    ];
+
+    let drift = compare_jwks(&fetched, &on_chain, &[iss.clone()]);
+    assert_eq!(
+        drift,
+        JwkDrift {
+            only_fetched: vec![other_id("2")],
+            only_on_chain: vec![other_id("4")],
+            mismatched: vec![other_id("3")],
+        }
+    );
+    assert!(!drift.is_empty());
+
+    let on_chain: Vec<_> = fetched.clone().into_iter().collect();
+    assert!(compare_jwks(&fetched, &on_chain, &[iss]).is_empty());
+}
+
+/// The address of the client of requests in tests.
+fn test_peer() -> SocketAddr {
+    "10.0.0.1:1234".parse().unwrap()
+}
+
+#[tokio::test]
+async fn test_verify_batch() {
+    let state = Arc::new(AppState::default());
+    state.update_jwks(|jwks| {
+        let (jwk_id, jwk) = test_jwk();
+        jwks.insert(jwk_id, jwk);
+    });
+
+    let mut requests: Vec<VerifyRequest> = (0..8).map(|_| test_request()).collect();
+    requests[3].signature = "AA==".to_string();
+    requests[5].network = Some(crate::SuiEnv::Mainnet);
+    let res = verify_batch(
+        State(state.clone()),
+        ConnectInfo(test_peer()),
+        HeaderMap::new(),
+        Json(VerifyBatchRequest { requests }),
+    )
+    .await
+    .unwrap()
+    .0;
+    assert_eq!(res.results.len(), 8);
+    for (i, result) in res.results.iter().enumerate() {
+        match (i, result) {
+            (3, BatchResult::Failed { code, .. }) => {
+                assert_eq!(*code, ErrorCode::InvalidSignatureEncoding)
+            }
+            (5, BatchResult::Failed { code, .. }) => {
+                assert_eq!(*code, ErrorCode::ProofVerifyFailed)
+            }
+            (3 | 5, _) => panic!("request {} should fail", i),
+            (_, BatchResult::Verified(response)) => assert!(response.is_verified),
+            (_, BatchResult::Failed { error, .. }) => panic!("request {} failed: {}", i, error),
+        }
+    }
+
+    let res = verify_batch(
+        State(Arc::new(AppState {
+            config: Config {
+                max_batch_size: 2,
+                ..Default::default()
+            }
+            .into(),
+            ..Default::default()
+        })),
+        ConnectInfo(test_peer()),
+        HeaderMap::new(),
+        Json(VerifyBatchRequest {
+            requests: (0..3).map(|_| test_request()).collect(),
+        }),
+    )
+    .await;
+    assert_eq!(
+        res.unwrap_err(),
+        VerifyError::BatchTooLarge { len: 3, max: 2 }
+    );
+}
+
+#[tokio::test]
+async fn test_verify_batch_rate_limit() {
+    let state = Arc::new(AppState::new(Config {
+        providers: vec![],
+        rate_limit: RateLimitConfig {
+            per_ip: Some(BucketConfig {
+                per_second: 0.001,
+                burst: 3,
+            }),
+            ..Default::default()
+        },
+        ..Default::default()
+    }));
+    state.update_jwks(|jwks| {
+        let (jwk_id, jwk) = test_jwk();
+        jwks.insert(jwk_id, jwk);
+    });
+
+    // The request took a token for the first, and the others take one each.
+    let res = verify_batch(
+        State(state),
+        ConnectInfo(test_peer()),
+        HeaderMap::new(),
+        Json(VerifyBatchRequest {
+            requests: (0..6).map(|_| test_request()).collect(),
+        }),
+    )
+    .await
+    .unwrap()
+    .0;
+    for (i, result) in res.results.iter().enumerate() {
+        match (i, result) {
+            (0..=3, BatchResult::Verified(response)) => assert!(response.is_verified),
+            (4 | 5, BatchResult::Failed { code, .. }) => {
+                assert_eq!(*code, ErrorCode::RateLimited)
+            }
+            (_, result) => panic!("unexpected result of request {}: {:?}", i, result),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_ws_handle_message() {
+    let state = AppState::default();
+    state.update_jwks(|jwks| {
+        let (jwk_id, jwk) = test_jwk();
+        jwks.insert(jwk_id, jwk);
+    });
+
+    let message = serde_json::json!({ "id": "a1", "request": test_request() }).to_string();
+    let res = handle_message(&state, &message).await;
+    assert_eq!(res.id, "a1");
+    assert!(matches!(res.result, BatchResult::Verified(r) if r.is_verified));
+
+    let mut request = test_request();
+    request.signature = "AA==".to_string();
+    let message = serde_json::json!({ "id": 7, "request": request }).to_string();
+    let res = handle_message(&state, &message).await;
+    assert_eq!(res.id, 7);
+    assert!(matches!(
+        res.result,
+        BatchResult::Failed {
+            code: ErrorCode::InvalidSignatureEncoding,
+            ..
+        }
+    ));
+
+    let res = handle_message(&state, "{\"id\": 1}").await;
+    assert!(res.id.is_null());
+    assert!(matches!(
+        res.result,
+        BatchResult::Failed {
+            code: ErrorCode::InvalidRequest,
+            ..
+        }
+    ));
+}
+
+#[tokio::test]
+async fn test_generate_test_vector_checks_nonce() {
+    let (_, kp): (_, Ed25519KeyPair) = get_key_pair();
+    let ephemeral_key = SuiKeyPair::Ed25519(kp).encode_base64();
+    let params = |jwt: &str| TestVectorParams {
+        jwt: jwt.to_string(),
+        ephemeral_key: ephemeral_key.clone(),
+        jwt_randomness: "100681567828351849884072155819400689117".to_string(),
+        salt: "129390038577185583942388216820280642146".to_string(),
+        max_epoch: 10,
+        message: None,
+        // Never called, the nonce is checked first.
+        prover_url: "http://127.0.0.1:1".to_string(),
+    };
+
+    let err = generate_test_vector(params("not a jwt")).await.unwrap_err();
+    assert_eq!(err, "JWT has no payload");
+
+    // A JWT issued for another ephemeral key.
+    let payload =
+        Base64::encode(r#"{"sub":"1","aud":"test","nonce":"hTPpgF7XAKbW37rEUS6pEVZqmoI"}"#)
+            .replace('+', "-")
+            .replace('/', "_")
+            .replace('=', "");
+    let err = generate_test_vector(params(&format!("e30.{}.sig", payload)))
+        .await
+        .unwrap_err();
+    assert!(err.contains("does not match"), "{}", err);
+}
+
+#[cfg(feature = "graphql")]
+#[tokio::test]
+async fn test_graphql() {
+    let state = Arc::new(AppState::default());
+    state.update_jwks(|jwks| {
+        let (jwk_id, jwk) = test_jwk();
+        jwks.insert(jwk_id, jwk);
+    });
+    let schema = crate::graphql::schema(state);
+
+    let query = |signature: &str| {
+        format!(
+            r#"{{ verifySignature(request: {{signature: "{}", bytes: "{}", intentScope: "TransactionData", network: "Devnet", currEpoch: 1}}) {{ isVerified maxEpoch provider }} }}"#,
+            signature, TEST_BYTES
+        )
+    };
+    let res = schema.execute(query(TEST_SIGNATURE)).await;
+    assert!(res.errors.is_empty(), "{:?}", res.errors);
+    let data = res.data.into_json().unwrap();
+    assert_eq!(data["verifySignature"]["isVerified"], true);
+    assert_eq!(data["verifySignature"]["maxEpoch"], 10);
+    assert_eq!(data["verifySignature"]["provider"], "Twitch");
+
+    let res = schema.execute(query("AA==")).await;
+    assert_eq!(
+        res.errors[0].extensions.as_ref().unwrap().get("code"),
+        Some(&async_graphql::Value::from("INVALID_SIGNATURE_ENCODING"))
+    );
+
+    let res = schema.execute("{ jwks { iss kid } }").await;
+    let data = res.data.into_json().unwrap();
+    assert_eq!(data["jwks"][0]["kid"], "1");
 }
@@ -0,0 +1,160 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Generates complete verify requests from a JWT, for fixtures of SDKs and
+//! services verifying zkLogin signatures. The proof is requested from a
+//! prover, so that the fixtures verify against the Test verifying key with a
+//! dev prover.
+
+use crate::{verifier::personal_message_intent, SuiEnv, VerifyRequest};
+use fastcrypto::{
+    encoding::{Base64, Encoding},
+    rsa::{Base64UrlUnpadded, Encoding as _},
+    traits::EncodeDecodeBase64,
+};
+use fastcrypto_zkp::bn254::{
+    utils::{gen_address_seed, get_nonce, get_proof},
+    zk_login::ZkLoginInputs,
+};
+use num_bigint::BigUint;
+use serde_json::Value;
+use shared_crypto::intent::{Intent, IntentMessage, IntentScope};
+use sui_types::{
+    base_types::{ObjectDigest, ObjectID, SequenceNumber, SuiAddress},
+    committee::EpochId,
+    crypto::{Signature, SuiKeyPair},
+    signature::GenericSignature,
+    transaction::TransactionData,
+    zk_login_authenticator::ZkLoginAuthenticator,
+};
+
+/// The prover of Mysten Labs for the Test verifying key.
+pub const DEV_PROVER_URL: &str = "https://prover-dev.mystenlabs.com/v1";
+
+/// The inputs to generate a test vector from.
+#[derive(Debug, Clone)]
+pub struct TestVectorParams {
+    /// The JWT, issued with the nonce of the ephemeral key, max epoch and
+    /// randomness.
+    pub jwt: String,
+    /// The ephemeral key pair, Base64 encoded as flag || private key as
+    /// exported by `sui keytool`.
+    pub ephemeral_key: String,
+    /// The randomness the nonce of the JWT was computed with, as a BigInt.
+    pub jwt_randomness: String,
+    /// The user salt, as a BigInt.
+    pub salt: String,
+    /// The max epoch the nonce of the JWT was computed with.
+    pub max_epoch: EpochId,
+    /// Sign this PersonalMessage instead of a transfer transaction.
+    pub message: Option<String>,
+    /// The URL of the prover.
+    pub prover_url: String,
+}
+
+/// Generates a verify request of a zkLogin signature over a transfer of the
+/// gas coin to the signer, or over the message, that verifies on Devnet at
+/// the max epoch.
+pub async fn generate_test_vector(params: TestVectorParams) -> Result<VerifyRequest, String> {
+    let claims = jwt_claims(&params.jwt)?;
+    let claim = |name: &str| match &claims[name] {
+        Value::String(value) => Ok(value.clone()),
+        // aud may be a list of audiences.
+        Value::Array(values) if !values.is_empty() => {
+            Ok(values[0].as_str().unwrap_or_default().to_string())
+        }
+        _ => Err(format!("JWT has no {} claim", name)),
+    };
+    let (sub, aud, nonce) = (claim("sub")?, claim("aud")?, claim("nonce")?);
+
+    let kp = SuiKeyPair::decode_base64(&params.ephemeral_key)
+        .map_err(|e| format!("Invalid ephemeral key: {}", e))?;
+    let mut eph_pk_bytes = vec![kp.public().flag()];
+    eph_pk_bytes.extend(kp.public().as_ref());
+    let expected_nonce = get_nonce(&eph_pk_bytes, params.max_epoch, &params.jwt_randomness)
+        .map_err(|e| format!("Cannot compute nonce: {}", e))?;
+    if nonce != expected_nonce {
+        return Err(format!(
+            "JWT nonce {} does not match the nonce {} of the ephemeral key, max epoch and randomness",
+            nonce, expected_nonce
+        ));
+    }
+
+    let address_seed = gen_address_seed(&params.salt, "sub", &sub, &aud)
+        .map_err(|e| format!("Cannot compute address seed: {}", e))?;
+    let reader = get_proof(
+        &params.jwt,
+        params.max_epoch,
+        &params.jwt_randomness,
+        &BigUint::from_bytes_be(&eph_pk_bytes).to_string(),
+        &params.salt,
+        &params.prover_url,
+    )
+    .await
+    .map_err(|e| format!("Cannot get proof from {}: {}", params.prover_url, e))?;
+    let inputs = ZkLoginInputs::from_reader(reader, &address_seed)
+        .map_err(|e| format!("Invalid proof: {}", e))?;
+    let sender = SuiAddress::try_from_unpadded(&inputs)
+        .map_err(|e| format!("Cannot derive address: {}", e))?;
+
+    let (bytes, intent_scope, author, user_signature) = match params.message {
+        Some(message) => {
+            let intent_msg = personal_message_intent(message.as_bytes());
+            (
+                message.into_bytes(),
+                IntentScope::PersonalMessage,
+                Some(sender),
+                Signature::new_secure(&intent_msg, &kp),
+            )
+        }
+        None => {
+            let gas = (
+                ObjectID::random(),
+                SequenceNumber::new(),
+                ObjectDigest::random(),
+            );
+            let tx_data =
+                TransactionData::new_transfer_sui(sender, sender, None, gas, 10_000_000, 1000);
+            let bytes = bcs::to_bytes(&tx_data).map_err(|e| e.to_string())?;
+            let intent_msg = IntentMessage::new(Intent::sui_transaction(), tx_data);
+            (
+                bytes,
+                IntentScope::TransactionData,
+                None,
+                Signature::new_secure(&intent_msg, &kp),
+            )
+        }
+    };
+    let signature = GenericSignature::ZkLoginAuthenticator(ZkLoginAuthenticator::new(
+        inputs,
+        params.max_epoch,
+        user_signature,
+    ));
+
+    Ok(VerifyRequest {
+        signature: Base64::encode(signature.as_ref()),
+        bytes: Base64::encode(bytes),
+        intent_scope,
+        author,
+        network: Some(SuiEnv::Devnet),
+        curr_epoch: Some(params.max_epoch),
+        sponsor: None,
+        format: None,
+        encoding: None,
+        policy: None,
+        jwk: None,
+        historical_epoch: None,
+        intent: None,
+    })
+}
+
+/// Returns the claims of the JWT, without verifying it.
+fn jwt_claims(jwt: &str) -> Result<Value, String> {
+    let payload = jwt
+        .split('.')
+        .nth(1)
+        .ok_or_else(|| "JWT has no payload".to_string())?;
+    let bytes = Base64UrlUnpadded::decode_vec(payload.trim_end_matches('='))
+        .map_err(|_| "JWT payload is not Base64Url encoded".to_string())?;
+    serde_json::from_slice(&bytes).map_err(|_| "JWT payload is not JSON".to_string())
+}
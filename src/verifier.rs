@@ -0,0 +1,1085 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! The network-free core of the verifier, shared by the HTTP server, the
+//! offline CLI and the WASM bindings.
+
+use crate::{
+    BinaryEncoding, DeriveAddressResponse, EphemeralSignatureResponse, ErrorCode, FieldError,
+    IssClaimDetails, JwkEntry, ParseSignatureResponse, PayloadFormat, PreflightResponse,
+    PreflightSignature, SignatureVerdict, VerifyError, VerifyRequest, VerifyResponse,
+    VerifyTransactionResponse, ZkLoginDebugInfo,
+};
+use fastcrypto::{
+    encoding::{Base64, Encoding, Hex},
+    error::FastCryptoError,
+    rsa::{Base64UrlUnpadded, Encoding as _},
+};
+use fastcrypto_zkp::bn254::{
+    zk_login::{JwkId, JWK},
+    zk_login_api::ZkLoginEnv,
+};
+use im::hashmap::HashMap as ImHashMap;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize};
+use shared_crypto::intent::{
+    AppId, Intent, IntentMessage, IntentScope, IntentVersion, PersonalMessage,
+};
+use sui_types::{
+    base_types::SuiAddress,
+    committee::EpochId,
+    crypto::{CompressedSignature, PublicKey, SignatureScheme, SuiSignature, ToFromBytes},
+    multisig::MultiSig,
+    signature::{AuthenticatorTrait, GenericSignature, VerifyParams},
+    transaction::TransactionData,
+    zk_login_authenticator::ZkLoginAuthenticator,
+};
+use tracing::debug;
+
+/// The longest `signature` accepted, in characters of its encoding. zkLogin
+/// signatures take a few KiB, and multisigs of them a few times that.
+pub const MAX_SIGNATURE_LEN: usize = 64 * 1024;
+
+/// The longest `bytes` accepted, in characters of its encoding or JSON.
+/// Transactions take at most 128 KiB, or twice that in Hex.
+pub const MAX_BYTES_LEN: usize = 512 * 1024;
+
+/// Options for [verify_zklogin_signature].
+#[derive(Debug, Clone, Default)]
+pub struct VerifyOptions {
+    /// The JWKs to verify the zkLogin proof against.
+    pub jwks: ImHashMap<JwkId, JWK>,
+    /// The ZkLoginEnv that determines the verifying key.
+    pub env: ZkLoginEnv,
+    /// The current epoch to verify the signature against.
+    pub curr_epoch: EpochId,
+    /// The author of the intent, required for PersonalMessage.
+    pub author: Option<SuiAddress>,
+    /// For TransactionData, verify against the gas owner (sponsor) instead
+    /// of the sender.
+    pub sponsor: bool,
+    /// The verification policy.
+    pub policy: VerifyPolicy,
+    /// The intent to verify against, instead of the intent scope with
+    /// version V0 and app ID Sui. Its scope must be the intent scope.
+    pub intent: Option<Intent>,
+}
+
+/// Verification policy, mostly passed on to [VerifyParams]. Defaults to the
+/// policy of Sui mainnet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VerifyPolicy {
+    /// Also accept zkLogin addresses derived with the legacy, padded address
+    /// seed.
+    pub verify_legacy_address: bool,
+    /// Accept zkLogin signers as multisig participants.
+    pub accept_zklogin_in_multisig: bool,
+    /// Reject signatures whose max epoch is more than this many epochs after
+    /// the current epoch. Unbounded if not set, which the config spells `0`
+    /// or `"none"` as TOML has no null.
+    #[serde(deserialize_with = "deserialize_max_epoch_delta")]
+    pub max_epoch_upper_bound_delta: Option<u64>,
+}
+
+impl Default for VerifyPolicy {
+    fn default() -> Self {
+        Self {
+            verify_legacy_address: true,
+            accept_zklogin_in_multisig: true,
+            max_epoch_upper_bound_delta: Some(30),
+        }
+    }
+}
+
+impl VerifyPolicy {
+    /// Returns the policy with the fields set in the overrides of a request
+    /// applied, only where they tighten it, so that a request cannot loosen
+    /// the policy of the server.
+    pub fn restrict(self, overrides: VerifyPolicyOverride) -> Self {
+        Self {
+            verify_legacy_address: self.verify_legacy_address
+                && overrides.verify_legacy_address.unwrap_or(true),
+            accept_zklogin_in_multisig: self.accept_zklogin_in_multisig
+                && overrides.accept_zklogin_in_multisig.unwrap_or(true),
+            max_epoch_upper_bound_delta: match (
+                self.max_epoch_upper_bound_delta,
+                overrides.max_epoch_upper_bound_delta.flatten(),
+            ) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            },
+        }
+    }
+}
+
+/// The [VerifyPolicy] fields a request sets. Fields that are not set keep
+/// the policy of the server.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifyPolicyOverride {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify_legacy_address: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accept_zklogin_in_multisig: Option<bool>,
+    /// `Some(None)`, from `null`, `0` or `"none"`, asks for no bound, which
+    /// leaves the bound of the server in place.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_max_epoch_delta_override"
+    )]
+    pub max_epoch_upper_bound_delta: Option<Option<u64>>,
+}
+
+/// Deserializes a max epoch delta from a number, `0`, `"none"` or null
+/// meaning unbounded.
+fn deserialize_max_epoch_delta<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Delta {
+        Number(u64),
+        Name(String),
+    }
+    match Option::<Delta>::deserialize(deserializer)? {
+        None | Some(Delta::Number(0)) => Ok(None),
+        Some(Delta::Number(delta)) => Ok(Some(delta)),
+        Some(Delta::Name(name)) if name.eq_ignore_ascii_case("none") => Ok(None),
+        Some(Delta::Name(name)) => Err(D::Error::custom(format!(
+            "Invalid max epoch delta: {}, expected a number or \"none\"",
+            name
+        ))),
+    }
+}
+
+/// Deserializes a set max epoch delta, so that null is told apart from an
+/// unset field.
+fn deserialize_max_epoch_delta_override<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Option<u64>>, D::Error> {
+    deserialize_max_epoch_delta(deserializer).map(Some)
+}
+
+/// Verifies a zkLogin signature, either standalone or as a participant of a
+/// multisig, over the BCS bytes of TransactionData or the bytes of a
+/// PersonalMessage as determined by `intent_scope`. This makes no network
+/// calls, all chain state is passed in through `options`.
+pub fn verify_zklogin_signature(
+    bytes: &[u8],
+    signature: &GenericSignature,
+    intent_scope: IntentScope,
+    options: VerifyOptions,
+) -> Result<VerifyResponse, VerifyError> {
+    match signature {
+        GenericSignature::ZkLoginAuthenticator(_) => {}
+        GenericSignature::MultiSig(multisig) if has_zklogin_participant(multisig) => {}
+        _ => {
+            return Err(VerifyError::NotZkLoginSignature(signature_scheme(
+                signature,
+            )))
+        }
+    }
+    verify_generic_signature(bytes, signature, intent_scope, options)
+}
+
+/// Verifies a signature of any scheme, e.g. Ed25519, Secp256k1, Secp256r1,
+/// multisig or zkLogin, for the author of the intent as
+/// [verify_zklogin_signature] does. The JWKs and epoch of `options` are only
+/// used by zkLogin signatures. This makes no network calls.
+pub fn verify_generic_signature(
+    bytes: &[u8],
+    signature: &GenericSignature,
+    intent_scope: IntentScope,
+    options: VerifyOptions,
+) -> Result<VerifyResponse, VerifyError> {
+    let intent = scope_intent(intent_scope, options.intent.clone())?;
+    match intent_scope {
+        IntentScope::TransactionData => {
+            let tx_data: TransactionData =
+                bcs::from_bytes(bytes).map_err(VerifyError::BcsDecodeError)?;
+            let (_, sender, gas_data) = tx_data.execution_parts();
+            let author = if options.sponsor {
+                gas_data.owner
+            } else {
+                sender
+            };
+            let tx_digest = tx_data.digest();
+            let intent_msg = IntentMessage::new(intent, tx_data);
+            let response = verify_for_author(signature, &intent_msg, author, options)?;
+            Ok(VerifyResponse {
+                tx_digest: Some(tx_digest),
+                ..response
+            })
+        }
+        IntentScope::PersonalMessage => {
+            let intent_msg = IntentMessage::new(
+                intent,
+                PersonalMessage {
+                    message: bytes.to_vec(),
+                },
+            );
+            let author = match options.author {
+                Some(author) => author,
+                None => return Err(VerifyError::MissingAuthor),
+            };
+            verify_for_author(signature, &intent_msg, author, options)
+        }
+        scope => Err(VerifyError::UnsupportedIntentScope(scope as u8)),
+    }
+}
+
+/// Verifies a zkLogin signature over the BCS bytes of TransactionData for its
+/// sender, at `curr_epoch` against `jwks` with the verifying key of `env` and
+/// the default [VerifyPolicy]. Synchronous and network-free, so it can be
+/// called from any context, see [verify_zklogin_signature] for more options.
+pub fn verify_transaction_data(
+    tx_bytes: &[u8],
+    signature: &GenericSignature,
+    curr_epoch: EpochId,
+    jwks: ImHashMap<JwkId, JWK>,
+    env: ZkLoginEnv,
+) -> Result<VerifyResponse, VerifyError> {
+    verify_zklogin_signature(
+        tx_bytes,
+        signature,
+        IntentScope::TransactionData,
+        VerifyOptions {
+            jwks,
+            env,
+            curr_epoch,
+            ..Default::default()
+        },
+    )
+}
+
+/// Verifies a zkLogin signature over a PersonalMessage for `author`, like
+/// [verify_transaction_data].
+pub fn verify_personal_message(
+    message: &[u8],
+    author: SuiAddress,
+    signature: &GenericSignature,
+    curr_epoch: EpochId,
+    jwks: ImHashMap<JwkId, JWK>,
+    env: ZkLoginEnv,
+) -> Result<VerifyResponse, VerifyError> {
+    verify_zklogin_signature(
+        message,
+        signature,
+        IntentScope::PersonalMessage,
+        VerifyOptions {
+            jwks,
+            env,
+            curr_epoch,
+            author: Some(author),
+            ..Default::default()
+        },
+    )
+}
+
+/// Verifies the signature over the intent message for the author, running
+/// the cheap checks first.
+fn verify_for_author<T: Serialize>(
+    signature: &GenericSignature,
+    intent_msg: &IntentMessage<T>,
+    author: SuiAddress,
+    options: VerifyOptions,
+) -> Result<VerifyResponse, VerifyError> {
+    precheck(signature, intent_msg, author, &options)?;
+
+    debug!("Verifying against {} JWKs", options.jwks.len());
+    let aux_verify_data = VerifyParams::new(
+        options.jwks,
+        vec![],
+        options.env,
+        options.policy.verify_legacy_address,
+        options.policy.accept_zklogin_in_multisig,
+    );
+    verify_intent_message(
+        signature,
+        intent_msg,
+        author,
+        options.curr_epoch,
+        &aux_verify_data,
+    )
+}
+
+/// Runs the checks of verifying that are cheap next to the Groth16 proof of
+/// a zkLogin signature, so that signatures failing them are rejected with a
+/// precise error before the proof is verified: the epoch and JWK of each
+/// zkLogin signature, standalone or as multisig participant, the author and
+/// the ephemeral signature.
+fn precheck<T: Serialize>(
+    signature: &GenericSignature,
+    intent_msg: &IntentMessage<T>,
+    author: SuiAddress,
+    options: &VerifyOptions,
+) -> Result<(), VerifyError> {
+    let authenticators = zklogin_authenticators(signature);
+    for zk in &authenticators {
+        if options.curr_epoch > zk.get_max_epoch() {
+            return Err(VerifyError::SignatureExpired {
+                max_epoch: zk.get_max_epoch(),
+                curr_epoch: options.curr_epoch,
+            });
+        }
+        check_max_epoch_delta(zk, options.curr_epoch, &options.policy)?;
+    }
+    for zk in &authenticators {
+        check_jwk_known(zk, &options.jwks)?;
+    }
+
+    match signature {
+        GenericSignature::ZkLoginAuthenticator(zk) => {
+            let signer =
+                SuiAddress::try_from_unpadded(&zk.inputs).map_err(VerifyError::VerifyFailed)?;
+            let legacy_signer = || SuiAddress::try_from_padded(&zk.inputs).ok();
+            if author != signer
+                && !(options.policy.verify_legacy_address && legacy_signer() == Some(author))
+            {
+                return Err(VerifyError::AuthorMismatch { author, signer });
+            }
+            zk.user_signature
+                .verify_secure(intent_msg, author, SignatureScheme::ZkLoginAuthenticator)
+                .map_err(VerifyError::VerifyFailed)?;
+        }
+        GenericSignature::MultiSig(multisig) => {
+            let signer = SuiAddress::from(multisig.get_pk());
+            if author != signer {
+                return Err(VerifyError::AuthorMismatch { author, signer });
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Verifies every signature on a transaction, of any scheme, the way
+/// validators do: each signature must verify for the sender or the gas owner,
+/// and each of them must have signed exactly once. Returns a verdict per
+/// signature. This makes no network calls; `options.author`,
+/// `options.sponsor` and `options.intent` are ignored.
+pub fn verify_transaction_signatures(
+    tx_bytes: &[u8],
+    signatures: &[GenericSignature],
+    options: VerifyOptions,
+) -> Result<VerifyTransactionResponse, VerifyError> {
+    let tx_data: TransactionData =
+        bcs::from_bytes(tx_bytes).map_err(VerifyError::BcsDecodeError)?;
+    let (_, sender, gas_data) = tx_data.execution_parts();
+    let mut signers = vec![sender];
+    if gas_data.owner != sender {
+        signers.push(gas_data.owner);
+    }
+    let tx_digest = tx_data.digest();
+    let intent_msg = IntentMessage::new(Intent::sui_transaction(), tx_data);
+    let aux_verify_data = VerifyParams::new(
+        options.jwks,
+        vec![],
+        options.env,
+        options.policy.verify_legacy_address,
+        options.policy.accept_zklogin_in_multisig,
+    );
+
+    let verdicts: Vec<SignatureVerdict> = signatures
+        .iter()
+        .map(|sig| {
+            let result = check_max_epoch_bound(sig, options.curr_epoch, &options.policy)
+                .map_err(|e| e.to_string())
+                .and_then(|_| {
+                    let mut error = String::new();
+                    for signer in &signers {
+                        match sig.verify_authenticator(
+                            &intent_msg,
+                            *signer,
+                            Some(options.curr_epoch),
+                            &aux_verify_data,
+                        ) {
+                            Ok(_) => return Ok(*signer),
+                            Err(e) => error = e.to_string(),
+                        }
+                    }
+                    Err(error)
+                });
+            SignatureVerdict {
+                scheme: signature_scheme(sig),
+                is_verified: result.is_ok(),
+                signer: result.as_ref().ok().copied(),
+                error: result.err(),
+            }
+        })
+        .collect();
+
+    let is_verified = verdicts.len() == signers.len()
+        && signers.iter().all(|signer| {
+            verdicts
+                .iter()
+                .filter(|v| v.signer == Some(*signer))
+                .count()
+                == 1
+        });
+    Ok(VerifyTransactionResponse {
+        is_verified,
+        tx_digest,
+        signatures: verdicts,
+        dry_run: None,
+    })
+}
+
+/// Like validators, rejects zkLogin signatures, standalone or as multisig
+/// participants, valid for longer than the policy allows.
+fn check_max_epoch_bound(
+    signature: &GenericSignature,
+    curr_epoch: EpochId,
+    policy: &VerifyPolicy,
+) -> Result<(), VerifyError> {
+    zklogin_authenticators(signature)
+        .iter()
+        .try_for_each(|zk| check_max_epoch_delta(zk, curr_epoch, policy))
+}
+
+/// Checks the max epoch of one zkLogin signature, see [check_max_epoch_bound].
+fn check_max_epoch_delta(
+    zk: &ZkLoginAuthenticator,
+    curr_epoch: EpochId,
+    policy: &VerifyPolicy,
+) -> Result<(), VerifyError> {
+    match policy.max_epoch_upper_bound_delta {
+        Some(delta) if zk.get_max_epoch() > curr_epoch.saturating_add(delta) => {
+            Err(VerifyError::MaxEpochTooFar {
+                max_epoch: zk.get_max_epoch(),
+                curr_epoch,
+                max_delta: delta,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Checks the zkLogin signatures in `signature`, standalone or as multisig
+/// participants, for a JWK among `jwks` and a max epoch in range of
+/// `curr_epoch` under `policy`, as verifying does before the Groth16 proof.
+/// This is cheap, but a signature passing it may still fail to verify.
+pub fn preflight_zklogin_signature(
+    signature: &GenericSignature,
+    jwks: &ImHashMap<JwkId, JWK>,
+    curr_epoch: EpochId,
+    policy: &VerifyPolicy,
+) -> Result<PreflightResponse, VerifyError> {
+    let authenticators = zklogin_authenticators(signature);
+    if authenticators.is_empty() {
+        return Err(VerifyError::NotZkLoginSignature(signature_scheme(
+            signature,
+        )));
+    }
+    let signatures: Vec<PreflightSignature> = authenticators
+        .iter()
+        .map(|zk| {
+            let jwk = check_jwk_known(zk, jwks);
+            let epoch = if curr_epoch > zk.get_max_epoch() {
+                Err(VerifyError::SignatureExpired {
+                    max_epoch: zk.get_max_epoch(),
+                    curr_epoch,
+                })
+            } else {
+                check_max_epoch_delta(zk, curr_epoch, policy)
+            };
+            let error = jwk.as_ref().err().or(epoch.as_ref().err());
+            PreflightSignature {
+                iss: zk.get_iss().to_string(),
+                kid: zk.inputs.get_kid().to_string(),
+                max_epoch: zk.get_max_epoch(),
+                jwk_known: jwk.is_ok(),
+                epoch_in_range: epoch.is_ok(),
+                error: error.map(|e| e.to_string()),
+                code: error.map(VerifyError::code),
+            }
+        })
+        .collect();
+    Ok(PreflightResponse {
+        ready: signatures.iter().all(|sig| sig.error.is_none()),
+        curr_epoch,
+        signatures,
+    })
+}
+
+/// Verifies only the ephemeral signature inside a zkLogin signature over the
+/// bytes, skipping the proof, JWK and epoch checks. This tells a wrong
+/// ephemeral key apart from a stale proof, but does not authenticate anyone.
+/// `intent` is the full intent signed over, as in [VerifyOptions::intent].
+pub fn verify_ephemeral_signature(
+    bytes: &[u8],
+    signature: &GenericSignature,
+    intent_scope: IntentScope,
+    intent: Option<Intent>,
+) -> Result<EphemeralSignatureResponse, VerifyError> {
+    let zk = match signature {
+        GenericSignature::ZkLoginAuthenticator(zk) => zk,
+        sig => return Err(VerifyError::NotZkLoginSignature(signature_scheme(sig))),
+    };
+    let intent = scope_intent(intent_scope, intent)?;
+    // The ephemeral key is not tied to an address, so the author is not
+    // checked with the zkLogin scheme.
+    let result = match intent_scope {
+        IntentScope::TransactionData => {
+            let tx_data: TransactionData =
+                bcs::from_bytes(bytes).map_err(VerifyError::BcsDecodeError)?;
+            zk.user_signature.verify_secure(
+                &IntentMessage::new(intent, tx_data),
+                SuiAddress::ZERO,
+                SignatureScheme::ZkLoginAuthenticator,
+            )
+        }
+        IntentScope::PersonalMessage => zk.user_signature.verify_secure(
+            &IntentMessage::new(
+                intent,
+                PersonalMessage {
+                    message: bytes.to_vec(),
+                },
+            ),
+            SuiAddress::ZERO,
+            SignatureScheme::ZkLoginAuthenticator,
+        ),
+        scope => return Err(VerifyError::UnsupportedIntentScope(scope as u8)),
+    };
+    result.map_err(VerifyError::VerifyFailed)?;
+    Ok(EphemeralSignatureResponse {
+        is_verified: true,
+        ephemeral_pubkey: Base64::encode(zk.user_signature.public_key_bytes()),
+        ephemeral_scheme: format!("{:?}", zk.user_signature.scheme()),
+    })
+}
+
+/// Verifies the ephemeral signature of the zkLogin signature in the request.
+/// See [verify_ephemeral_signature].
+pub fn verify_request_ephemeral(
+    payload: &VerifyRequest,
+) -> Result<EphemeralSignatureResponse, VerifyError> {
+    let sig = decode_signature(&payload.signature, payload.encoding.unwrap_or_default())?;
+    verify_ephemeral_signature(
+        &decode_payload_bytes(payload)?,
+        &sig,
+        payload.intent_scope,
+        payload.intent.clone(),
+    )
+}
+
+/// Returns the intent message of a PersonalMessage over the bytes.
+pub(crate) fn personal_message_intent(bytes: &[u8]) -> IntentMessage<PersonalMessage> {
+    IntentMessage::new(
+        Intent {
+            scope: IntentScope::PersonalMessage,
+            version: IntentVersion::V0,
+            app_id: AppId::Sui,
+        },
+        PersonalMessage {
+            message: bytes.to_vec(),
+        },
+    )
+}
+
+/// Returns the intent to verify against for the scope: `intent` if set and
+/// of the scope, or else the scope with version V0 and app ID Sui.
+fn scope_intent(scope: IntentScope, intent: Option<Intent>) -> Result<Intent, VerifyError> {
+    match intent {
+        Some(intent) if intent.scope != scope => Err(VerifyError::IntentScopeMismatch {
+            intent: intent.scope as u8,
+            scope: scope as u8,
+        }),
+        Some(intent) => Ok(intent),
+        None => Ok(Intent {
+            scope,
+            version: IntentVersion::V0,
+            app_id: AppId::Sui,
+        }),
+    }
+}
+
+/// Checks the fields of the request without verifying anything, returning
+/// all problems found rather than only the first one verifying fails on.
+/// Returns the decoded signature and the bytes it is over, so that they are
+/// decoded once per request.
+pub fn validate_request(
+    payload: &VerifyRequest,
+) -> Result<(GenericSignature, Vec<u8>), VerifyError> {
+    let mut problems = Vec::new();
+    let encoding = payload.encoding.unwrap_or_default();
+    let mut signature = None;
+    let mut bytes = None;
+    if payload.signature.is_empty() {
+        problems.push(field_error(
+            "signature",
+            ErrorCode::InvalidSignatureEncoding,
+            "signature must not be empty".to_string(),
+        ));
+    } else if payload.signature.len() > MAX_SIGNATURE_LEN {
+        problems.push(field_error(
+            "signature",
+            ErrorCode::PayloadTooLarge,
+            format!(
+                "signature is longer than the maximum of {} characters",
+                MAX_SIGNATURE_LEN
+            ),
+        ));
+    } else {
+        match decode_signature(&payload.signature, encoding) {
+            Ok(sig) => signature = Some(sig),
+            Err(e) => {
+                let error = match e {
+                    VerifyError::InvalidEncoding { .. } => {
+                        format!("signature is not valid {:?}", encoding)
+                    }
+                    _ => "signature is not a valid signature".to_string(),
+                };
+                problems.push(field_error("signature", e.code(), error));
+            }
+        }
+    }
+
+    let scope = payload.intent_scope;
+    if !matches!(
+        scope,
+        IntentScope::TransactionData | IntentScope::PersonalMessage
+    ) {
+        problems.push(field_error(
+            "intent_scope",
+            ErrorCode::UnsupportedIntentScope,
+            format!(
+                "intent_scope {} is not supported, expected TransactionData or PersonalMessage",
+                scope as u8
+            ),
+        ));
+    }
+
+    if payload.bytes.is_empty() {
+        problems.push(field_error(
+            "bytes",
+            ErrorCode::InvalidBytes,
+            "bytes must not be empty".to_string(),
+        ));
+    } else if payload.bytes.len() > MAX_BYTES_LEN {
+        problems.push(field_error(
+            "bytes",
+            ErrorCode::PayloadTooLarge,
+            format!(
+                "bytes are longer than the maximum of {} characters",
+                MAX_BYTES_LEN
+            ),
+        ));
+    } else {
+        match payload.format.unwrap_or_default() {
+            PayloadFormat::Bcs => match decode_binary(&payload.bytes, encoding) {
+                Ok(decoded) => bytes = Some(decoded),
+                Err(_) => problems.push(field_error(
+                    "bytes",
+                    ErrorCode::InvalidBytes,
+                    format!("bytes are not valid {:?}", encoding),
+                )),
+            },
+            PayloadFormat::Json if scope != IntentScope::TransactionData => {
+                problems.push(field_error(
+                    "format",
+                    ErrorCode::UnsupportedIntentScope,
+                    "format Json is only supported for TransactionData".to_string(),
+                ))
+            }
+            _ => {}
+        }
+    }
+
+    if scope == IntentScope::PersonalMessage && payload.author.is_none() {
+        problems.push(field_error(
+            "author",
+            ErrorCode::MissingAuthor,
+            "author is required for PersonalMessage".to_string(),
+        ));
+    }
+    if let Some(intent) = &payload.intent {
+        if intent.scope != scope {
+            problems.push(field_error(
+                "intent",
+                ErrorCode::IntentScopeMismatch,
+                format!(
+                    "intent has scope {}, expected intent_scope {}",
+                    intent.scope as u8, scope as u8
+                ),
+            ));
+        }
+    }
+    if payload.historical_epoch.is_some() && payload.curr_epoch.is_some() {
+        problems.push(field_error(
+            "historical_epoch",
+            ErrorCode::InvalidHistoricalEpoch,
+            "historical_epoch cannot be combined with curr_epoch".to_string(),
+        ));
+    }
+
+    match signature {
+        // The signature is only missing if a problem was found.
+        Some(signature) if problems.is_empty() => {
+            // TransactionData JSON is only encoded to BCS once the rest of
+            // the request is valid.
+            let bytes = match bytes {
+                Some(bytes) => bytes,
+                None => decode_payload_bytes(payload)?,
+            };
+            Ok((signature, bytes))
+        }
+        _ => Err(VerifyError::InvalidFields(problems)),
+    }
+}
+
+fn field_error(field: &str, code: ErrorCode, error: String) -> FieldError {
+    FieldError {
+        field: field.to_string(),
+        code,
+        error,
+    }
+}
+
+/// Verifies the signature in the request against the given JWKs, or the JWK
+/// of the request if any, env and current epoch, under `policy` as restricted
+/// by the policy of the request. This makes no network calls.
+pub fn verify_request(
+    payload: &VerifyRequest,
+    jwks: ImHashMap<JwkId, JWK>,
+    env: ZkLoginEnv,
+    curr_epoch: EpochId,
+    policy: VerifyPolicy,
+) -> Result<VerifyResponse, VerifyError> {
+    let (sig, bytes) = validate_request(payload)?;
+    verify_validated_request(payload, &sig, &bytes, jwks, env, curr_epoch, policy)
+}
+
+/// Verifies the request like [verify_request], with its signature and bytes
+/// as returned by [validate_request].
+pub(crate) fn verify_validated_request(
+    payload: &VerifyRequest,
+    sig: &GenericSignature,
+    bytes: &[u8],
+    jwks: ImHashMap<JwkId, JWK>,
+    env: ZkLoginEnv,
+    curr_epoch: EpochId,
+    policy: VerifyPolicy,
+) -> Result<VerifyResponse, VerifyError> {
+    verify_zklogin_signature(
+        bytes,
+        sig,
+        payload.intent_scope,
+        request_options(payload, jwks, env, curr_epoch, policy),
+    )
+}
+
+/// Verifies the signature in the request, of any scheme, like
+/// [verify_request]. See [verify_generic_signature].
+pub fn verify_generic_request(
+    payload: &VerifyRequest,
+    jwks: ImHashMap<JwkId, JWK>,
+    env: ZkLoginEnv,
+    curr_epoch: EpochId,
+    policy: VerifyPolicy,
+) -> Result<VerifyResponse, VerifyError> {
+    let (sig, bytes) = validate_request(payload)?;
+    verify_validated_generic_request(payload, &sig, &bytes, jwks, env, curr_epoch, policy)
+}
+
+/// Verifies the request like [verify_generic_request], with its signature
+/// and bytes as returned by [validate_request].
+pub(crate) fn verify_validated_generic_request(
+    payload: &VerifyRequest,
+    sig: &GenericSignature,
+    bytes: &[u8],
+    jwks: ImHashMap<JwkId, JWK>,
+    env: ZkLoginEnv,
+    curr_epoch: EpochId,
+    policy: VerifyPolicy,
+) -> Result<VerifyResponse, VerifyError> {
+    verify_generic_signature(
+        bytes,
+        sig,
+        payload.intent_scope,
+        request_options(payload, jwks, env, curr_epoch, policy),
+    )
+}
+
+/// Returns the options to verify the request with.
+fn request_options(
+    payload: &VerifyRequest,
+    jwks: ImHashMap<JwkId, JWK>,
+    env: ZkLoginEnv,
+    curr_epoch: EpochId,
+    policy: VerifyPolicy,
+) -> VerifyOptions {
+    VerifyOptions {
+        jwks: match &payload.jwk {
+            Some(jwk) => ImHashMap::from_iter([jwk.clone().into_parts()]),
+            None => jwks,
+        },
+        env,
+        curr_epoch,
+        author: payload.author,
+        sponsor: payload.sponsor.unwrap_or(false),
+        policy: payload
+            .policy
+            .map_or(policy, |request_policy| policy.restrict(request_policy)),
+        intent: payload.intent.clone(),
+    }
+}
+
+/// Decodes the bytes of the request, in its format and encoding, to the bytes
+/// the signature is over.
+fn decode_payload_bytes(payload: &VerifyRequest) -> Result<Vec<u8>, VerifyError> {
+    check_len("bytes", &payload.bytes, MAX_BYTES_LEN)?;
+    Ok(
+        match (payload.format.unwrap_or_default(), payload.intent_scope) {
+            (PayloadFormat::Bcs, _) => {
+                decode_binary(&payload.bytes, payload.encoding.unwrap_or_default()).map_err(
+                    |source| VerifyError::InvalidEncoding {
+                        field: "bytes",
+                        source,
+                    },
+                )?
+            }
+            (PayloadFormat::Json, IntentScope::TransactionData) => {
+                let tx_data: TransactionData = serde_json::from_str(&payload.bytes)
+                    .map_err(VerifyError::InvalidTransactionJson)?;
+                bcs::to_bytes(&tx_data).map_err(VerifyError::BcsEncodeError)?
+            }
+            (PayloadFormat::Json, scope) => {
+                return Err(VerifyError::UnsupportedIntentScope(scope as u8))
+            }
+        },
+    )
+}
+
+/// Parses a zkLogin signature and returns its components without verifying it.
+pub fn parse_zklogin_signature(signature: &str) -> Result<ParseSignatureResponse, VerifyError> {
+    match decode_signature(signature, BinaryEncoding::Base64)? {
+        GenericSignature::ZkLoginAuthenticator(zk) => Ok(parse_authenticator(&zk)),
+        sig => Err(VerifyError::NotZkLoginSignature(signature_scheme(&sig))),
+    }
+}
+
+fn parse_authenticator(zk: &ZkLoginAuthenticator) -> ParseSignatureResponse {
+    ParseSignatureResponse {
+        iss: zk.get_iss().to_string(),
+        kid: zk.inputs.get_kid().to_string(),
+        max_epoch: zk.get_max_epoch(),
+        ephemeral_pubkey: Base64::encode(zk.user_signature.public_key_bytes()),
+        ephemeral_scheme: format!("{:?}", zk.user_signature.scheme()),
+        address_seed: zk.inputs.get_address_seed().to_string(),
+    }
+}
+
+/// The fields of the zkLogin inputs without accessors, read from their
+/// serialization.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SerializedInputs {
+    header_base64: String,
+    iss_base64_details: SerializedClaim,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SerializedClaim {
+    value: String,
+    index_mod_4: u8,
+}
+
+/// Decodes the public inputs of the zkLogin signatures of the request,
+/// standalone or as multisig participants, and selects the JWK each is
+/// verified against from the JWK of the request, or else from `jwks`.
+pub fn debug_zklogin_signatures(
+    payload: &VerifyRequest,
+    jwks: &ImHashMap<JwkId, JWK>,
+) -> Result<Vec<ZkLoginDebugInfo>, VerifyError> {
+    let signature = decode_signature(&payload.signature, payload.encoding.unwrap_or_default())?;
+    let inline_jwk = payload.jwk.clone().map(JwkEntry::into_parts);
+    Ok(zklogin_authenticators(&signature)
+        .iter()
+        .map(|zk| {
+            let inputs: SerializedInputs = serde_json::to_value(&zk.inputs)
+                .and_then(serde_json::from_value)
+                .expect("zkLogin inputs serialize with their header and iss claim");
+            let header = Base64UrlUnpadded::decode_vec(&inputs.header_base64)
+                .ok()
+                .and_then(|header| serde_json::from_slice(&header).ok())
+                .unwrap_or_default();
+            let id = JwkId::new(zk.get_iss().to_string(), zk.inputs.get_kid().to_string());
+            let jwk = match &inline_jwk {
+                Some((inline_id, jwk)) => (*inline_id == id).then(|| jwk.clone()),
+                None => jwks.get(&id).cloned(),
+            };
+            ZkLoginDebugInfo {
+                signature: parse_authenticator(zk),
+                header,
+                header_base64: inputs.header_base64,
+                iss_base64_details: IssClaimDetails {
+                    value: inputs.iss_base64_details.value,
+                    index_mod_4: inputs.iss_base64_details.index_mod_4,
+                },
+                jwk,
+            }
+        })
+        .collect())
+}
+
+/// Derives the address and the legacy address of the signer of a zkLogin
+/// signature, and whether signatures verify for the legacy address under
+/// `policy`.
+pub fn derive_zklogin_addresses(
+    signature: &str,
+    encoding: BinaryEncoding,
+    policy: &VerifyPolicy,
+) -> Result<DeriveAddressResponse, VerifyError> {
+    let zk = match decode_signature(signature, encoding)? {
+        GenericSignature::ZkLoginAuthenticator(zk) => zk,
+        sig => return Err(VerifyError::NotZkLoginSignature(signature_scheme(&sig))),
+    };
+    let address = SuiAddress::try_from_unpadded(&zk.inputs).map_err(VerifyError::VerifyFailed)?;
+    let legacy_address =
+        SuiAddress::try_from_padded(&zk.inputs).map_err(VerifyError::VerifyFailed)?;
+    Ok(DeriveAddressResponse {
+        address,
+        legacy_address,
+        legacy_address_accepted: policy.verify_legacy_address,
+    })
+}
+
+/// Verifies the signature over the intent message for the given author. For a
+/// multisig, this checks that the participants' signatures meet the threshold.
+fn verify_intent_message<T: Serialize>(
+    sig: &GenericSignature,
+    intent_msg: &IntentMessage<T>,
+    author: SuiAddress,
+    curr_epoch: EpochId,
+    aux_verify_data: &VerifyParams,
+) -> Result<VerifyResponse, VerifyError> {
+    sig.verify_authenticator(intent_msg, author, Some(curr_epoch), aux_verify_data)
+        .map_err(|e| match sig {
+            // Precheck passed all but the proof of a standalone zkLogin
+            // signature. A multisig may also fail on its other participants
+            // or threshold.
+            GenericSignature::ZkLoginAuthenticator(_) => VerifyError::ProofVerifyFailed(e),
+            _ => VerifyError::VerifyFailed(e),
+        })?;
+    Ok(VerifyResponse::verified(sig, author, curr_epoch))
+}
+
+/// Returns the ids of the JWKs that the zkLogin signatures in `signature`,
+/// standalone or as multisig participants, are verified against.
+pub fn zklogin_jwk_ids(signature: &GenericSignature) -> Vec<JwkId> {
+    zklogin_authenticators(signature)
+        .iter()
+        .map(|zk| JwkId::new(zk.get_iss().to_string(), zk.inputs.get_kid().to_string()))
+        .collect()
+}
+
+/// Returns the zkLogin signatures in `signature`, standalone or as multisig
+/// participants.
+fn zklogin_authenticators(signature: &GenericSignature) -> Vec<ZkLoginAuthenticator> {
+    match signature {
+        GenericSignature::ZkLoginAuthenticator(zk) => vec![ZkLoginAuthenticator::clone(zk)],
+        GenericSignature::MultiSig(multisig) => multisig
+            .get_sigs()
+            .iter()
+            .filter_map(|sig| match sig {
+                CompressedSignature::ZkLogin(bytes) => {
+                    ZkLoginAuthenticator::from_bytes(&bytes.0).ok()
+                }
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Returns the addresses of the zkLogin signatures in `signature`, standalone
+/// or as multisig participants. Deriving them hashes the address seed, but
+/// does not verify anything.
+pub(crate) fn zklogin_addresses(signature: &GenericSignature) -> Vec<SuiAddress> {
+    zklogin_authenticators(signature)
+        .iter()
+        .filter_map(|zk| SuiAddress::try_from_unpadded(&zk.inputs).ok())
+        .collect()
+}
+
+/// Checks that the JWK of the zkLogin signature is among `jwks`, or returns
+/// an error naming it along with the kids known for its iss.
+fn check_jwk_known(
+    zk: &ZkLoginAuthenticator,
+    jwks: &ImHashMap<JwkId, JWK>,
+) -> Result<(), VerifyError> {
+    let (iss, kid) = (zk.get_iss().to_string(), zk.inputs.get_kid().to_string());
+    if jwks.contains_key(&JwkId::new(iss.clone(), kid.clone())) {
+        return Ok(());
+    }
+    let mut known_kids: Vec<String> = jwks
+        .keys()
+        .filter(|id| id.iss == iss)
+        .map(|id| id.kid.clone())
+        .collect();
+    known_kids.sort();
+    Err(VerifyError::JwkNotFound {
+        iss,
+        kid,
+        known_kids,
+    })
+}
+
+/// Returns true if any participant of the multisig is a zkLogin signer.
+fn has_zklogin_participant(multisig: &MultiSig) -> bool {
+    multisig
+        .get_pk()
+        .pubkeys()
+        .iter()
+        .any(|(pk, _)| matches!(pk, PublicKey::ZkLogin(_)))
+}
+
+/// Decodes a [GenericSignature] in the given encoding.
+pub(crate) fn decode_signature(
+    signature: &str,
+    encoding: BinaryEncoding,
+) -> Result<GenericSignature, VerifyError> {
+    check_len("signature", signature, MAX_SIGNATURE_LEN)?;
+    let bytes =
+        decode_binary(signature, encoding).map_err(|source| VerifyError::InvalidEncoding {
+            field: "signature",
+            source,
+        })?;
+    GenericSignature::from_bytes(&bytes).map_err(VerifyError::InvalidSignature)
+}
+
+/// Fails if the named field is longer than `max`, so that oversized input is
+/// rejected before it is decoded or parsed.
+pub(crate) fn check_len(field: &'static str, data: &str, max: usize) -> Result<(), VerifyError> {
+    if data.len() > max {
+        return Err(VerifyError::PayloadTooLarge {
+            field,
+            len: data.len(),
+            max,
+        });
+    }
+    Ok(())
+}
+
+/// Decodes binary data from text in the given encoding.
+pub(crate) fn decode_binary(
+    data: &str,
+    encoding: BinaryEncoding,
+) -> Result<Vec<u8>, FastCryptoError> {
+    match encoding {
+        BinaryEncoding::Base64 => Base64::decode(data),
+        BinaryEncoding::Base64Url => Base64UrlUnpadded::decode_vec(data.trim_end_matches('='))
+            .map_err(|_| FastCryptoError::InvalidInput),
+        BinaryEncoding::Hex => Hex::decode(data.strip_prefix("0x").unwrap_or(data)),
+    }
+}
+
+/// Returns the name of the scheme of the signature, given by its flag byte.
+pub(crate) fn signature_scheme(signature: &GenericSignature) -> String {
+    match SignatureScheme::from_flag_byte(&signature.as_ref()[0]) {
+        Ok(scheme) => format!("{:?}", scheme),
+        Err(_) => "Unknown".to_string(),
+    }
+}
@@ -0,0 +1,51 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! WASM bindings to the network-free verifier, so browsers and Node can
+//! verify zkLogin signatures locally given JWKs and an epoch.
+
+use crate::{
+    verifier::{parse_zklogin_signature, verify_request},
+    ErrorCode, JwkEntry, JwksResponse, VerifyError, VerifyPolicy, VerifyRequest,
+};
+use serde::Serialize;
+use sui_types::committee::EpochId;
+use wasm_bindgen::prelude::*;
+
+/// The error a call throws, with its message and code as in the error
+/// responses of the server.
+#[derive(Serialize)]
+struct JsError {
+    error: String,
+    code: ErrorCode,
+}
+
+fn to_js_err(e: VerifyError) -> JsValue {
+    let error = JsError {
+        error: e.to_string(),
+        code: e.code(),
+    };
+    serde_wasm_bindgen::to_value(&error).unwrap_or_else(|_| JsValue::from_str(&error.error))
+}
+
+/// Verifies the signature in `request` against `jwks` at `curr_epoch`.
+/// `request` is a `VerifyRequest` and `jwks` is a `JwksResponse` as returned
+/// by `GET /jwks`. Returns a `VerifyResponse`, or throws `{error, code}`.
+#[wasm_bindgen]
+pub fn verify(request: JsValue, jwks: JsValue, curr_epoch: EpochId) -> Result<JsValue, JsValue> {
+    let request: VerifyRequest = serde_wasm_bindgen::from_value(request)?;
+    let jwks: JwksResponse = serde_wasm_bindgen::from_value(jwks)?;
+    let (_, env) = request.network.unwrap_or_default().get_params();
+    let jwks = jwks.jwks.into_iter().map(JwkEntry::into_parts).collect();
+    let res = verify_request(&request, jwks, env, curr_epoch, VerifyPolicy::default())
+        .map_err(to_js_err)?;
+    Ok(serde_wasm_bindgen::to_value(&res)?)
+}
+
+/// Parses a Base64 encoded zkLogin signature and returns its components
+/// as a `ParseSignatureResponse`, without verifying it. Throws `{error, code}`.
+#[wasm_bindgen]
+pub fn parse_signature(signature: &str) -> Result<JsValue, JsValue> {
+    let res = parse_zklogin_signature(signature).map_err(to_js_err)?;
+    Ok(serde_wasm_bindgen::to_value(&res)?)
+}
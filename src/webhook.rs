@@ -0,0 +1,133 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Webhooks notifying operators of JWK fetch failures and key rotations, so
+//! that outages around a rotation are noticed before verifications fail.
+
+use crate::{
+    config::{WebhookConfig, WebhookFormat},
+    AppState,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::time::Duration;
+use tracing::warn;
+
+/// Time a webhook endpoint gets to accept an event.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An event about the JWKs of a provider, posted as JSON to generic webhooks.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WebhookEvent {
+    /// Fetching the JWKs of the provider failed `consecutive_failures` times
+    /// in a row.
+    FetchFailed {
+        provider: String,
+        iss: String,
+        consecutive_failures: u32,
+        error: String,
+    },
+    /// Fetching the JWKs of the provider succeeded again after failing
+    /// `consecutive_failures` times in a row.
+    FetchRecovered {
+        provider: String,
+        iss: String,
+        consecutive_failures: u32,
+    },
+    /// The cached JWKs of the provider changed.
+    KeysChanged {
+        provider: String,
+        iss: String,
+        /// The kids added to the cache.
+        added: Vec<String>,
+        /// The kids removed from the cache.
+        removed: Vec<String>,
+        /// The kids now in the cache.
+        kids: Vec<String>,
+    },
+}
+
+impl WebhookEvent {
+    /// Returns whether the event is sent to the webhook. Fetch failures are
+    /// sent once a failure streak reaches `failure_threshold`, and recoveries
+    /// only for streaks that were sent.
+    pub fn is_sent_to(&self, webhook: &WebhookConfig) -> bool {
+        match self {
+            Self::FetchFailed {
+                consecutive_failures,
+                ..
+            } => *consecutive_failures == webhook.failure_threshold,
+            Self::FetchRecovered {
+                consecutive_failures,
+                ..
+            } => *consecutive_failures >= webhook.failure_threshold,
+            Self::KeysChanged { .. } => true,
+        }
+    }
+
+    /// Returns a one-line description of the event, for chat webhooks.
+    pub fn summary(&self) -> String {
+        match self {
+            Self::FetchFailed {
+                provider,
+                consecutive_failures,
+                error,
+                ..
+            } => format!(
+                "zkLogin verifier: fetching the JWKs of {} failed {} times in a row: {}",
+                provider, consecutive_failures, error
+            ),
+            Self::FetchRecovered {
+                provider,
+                consecutive_failures,
+                ..
+            } => format!(
+                "zkLogin verifier: fetching the JWKs of {} recovered after {} failures",
+                provider, consecutive_failures
+            ),
+            Self::KeysChanged {
+                provider,
+                added,
+                removed,
+                ..
+            } => format!(
+                "zkLogin verifier: the JWKs of {} changed, added kids {:?}, removed kids {:?}",
+                provider, added, removed
+            ),
+        }
+    }
+
+    /// Returns the body posted to a webhook of the format.
+    pub fn body(&self, format: WebhookFormat) -> serde_json::Value {
+        match format {
+            WebhookFormat::Generic => json!(self),
+            WebhookFormat::Slack => json!({ "text": self.summary() }),
+        }
+    }
+}
+
+/// Posts the event to the configured webhooks in the background. Failures
+/// are logged and not retried.
+pub fn notify(state: &AppState, event: WebhookEvent) {
+    for webhook in state.config.load().webhooks.iter() {
+        if !event.is_sent_to(webhook) {
+            continue;
+        }
+        let request = state
+            .http_client
+            .post(&webhook.url)
+            .timeout(WEBHOOK_TIMEOUT)
+            .json(&event.body(webhook.format));
+        // Webhook URLs often hold a secret, so only their host is logged.
+        let host = reqwest::Url::parse(&webhook.url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_default();
+        tokio::spawn(async move {
+            if let Err(e) = request.send().await.and_then(|r| r.error_for_status()) {
+                warn!("Cannot post to webhook at {}: {}", host, e.without_url());
+            }
+        });
+    }
+}
@@ -0,0 +1,185 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Streaming verification over a WebSocket at `/verify_ws`, for clients
+//! verifying many signatures without a request per signature.
+
+use crate::{
+    config::TenantConfig,
+    server::{request_jwk_ids, verify_payload},
+    tenant::{current_tenant, with_tenant},
+    AppState, BatchResult, ErrorCode, VerifyRequest,
+};
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, State,
+    },
+    http::HeaderMap,
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Instant,
+};
+use tokio::sync::mpsc;
+use tracing::{debug, info, Instrument, Span};
+
+/// A request sent over the WebSocket.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WsVerifyRequest {
+    /// Chosen by the client and echoed in the result, which may arrive out
+    /// of order.
+    pub id: Value,
+    /// The request, as for `POST /verify`.
+    pub request: VerifyRequest,
+}
+
+/// The result of a request sent over the WebSocket.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WsVerifyResponse {
+    /// The id of the request, or null if the message could not be parsed.
+    pub id: Value,
+    /// The response as from `POST /verify`, or the error it failed with.
+    pub result: BatchResult,
+}
+
+/// Upgrades the connection to a WebSocket streaming verifications.
+pub async fn verify_ws(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let ip = state.rate_limiter.client_ip(&headers, peer);
+    let tenant = current_tenant();
+    // The connection is served in a task of its own, in the span of the
+    // upgrade request.
+    let span = Span::current();
+    ws.on_upgrade(move |socket| {
+        async move {
+            info!(%ip, "verify_ws connected");
+            serve_socket(state, socket, ip, tenant).await;
+            info!(%ip, "verify_ws disconnected");
+        }
+        .instrument(span)
+    })
+}
+
+/// Verifies the requests of the socket concurrently, with at most
+/// `ws_max_in_flight` of them pending. Once the window is full, no more
+/// messages are read, so that a client sending faster than the verifier
+/// keeps up is slowed down by TCP flow control instead of growing a queue.
+async fn serve_socket(
+    state: Arc<AppState>,
+    mut socket: WebSocket,
+    ip: IpAddr,
+    tenant: Option<Arc<TenantConfig>>,
+) {
+    let window = state.config.load().ws_max_in_flight.max(1);
+    let (tx, mut rx) = mpsc::channel(window);
+    let mut in_flight = 0;
+    loop {
+        tokio::select! {
+            message = socket.recv(), if in_flight < window => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Binary(bytes))) => match String::from_utf8(bytes) {
+                        Ok(text) => text,
+                        Err(_) => {
+                            let response = invalid_request("Message is not UTF-8".to_string());
+                            if send(&mut socket, &response).await.is_err() {
+                                return;
+                            }
+                            continue;
+                        }
+                    },
+                    // Pings are answered by axum.
+                    Some(Ok(Message::Ping(_) | Message::Pong(_))) => continue,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                };
+                if let Some(response) = check_rate_limit(&state, ip, tenant.as_deref(), &text) {
+                    if send(&mut socket, &response).await.is_err() {
+                        return;
+                    }
+                    continue;
+                }
+                in_flight += 1;
+                let (state, tx) = (state.clone(), tx.clone());
+                tokio::spawn(
+                    with_tenant(tenant.clone(), async move {
+                        // The receiver is gone only if the socket closed.
+                        let _ = tx.send(handle_message(&state, &text).await).await;
+                    })
+                    .instrument(Span::current()),
+                );
+            }
+            Some(response) = rx.recv() => {
+                in_flight -= 1;
+                if send(&mut socket, &response).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Takes a token for the message from the rate limits of the client and its
+/// tenant, returning the result to send instead of verifying it if they are
+/// exhausted.
+pub(crate) fn check_rate_limit(
+    state: &AppState,
+    ip: IpAddr,
+    tenant: Option<&TenantConfig>,
+    text: &str,
+) -> Option<WsVerifyResponse> {
+    if state
+        .rate_limiter
+        .check_with_tenant(ip, tenant, Instant::now())
+        .is_ok()
+    {
+        return None;
+    }
+    let id = serde_json::from_str::<Value>(text)
+        .map(|message| message["id"].clone())
+        .unwrap_or_default();
+    Some(failed(
+        id,
+        "Too many requests".to_string(),
+        ErrorCode::RateLimited,
+    ))
+}
+
+/// Verifies the request of a message and returns its result.
+pub(crate) async fn handle_message(state: &AppState, text: &str) -> WsVerifyResponse {
+    let message: WsVerifyRequest = match serde_json::from_str(text) {
+        Ok(message) => message,
+        Err(e) => return invalid_request(format!("Invalid request: {}", e)),
+    };
+    debug!(id = %message.id, "verify_ws request");
+    let jwk_ids = request_jwk_ids(&message.request);
+    let result = verify_payload(state, &message.request, &jwk_ids).await;
+    WsVerifyResponse {
+        id: message.id,
+        result: BatchResult::from(result),
+    }
+}
+
+pub(crate) fn invalid_request(error: String) -> WsVerifyResponse {
+    failed(Value::Null, error, ErrorCode::InvalidRequest)
+}
+
+fn failed(id: Value, error: String, code: ErrorCode) -> WsVerifyResponse {
+    WsVerifyResponse {
+        id,
+        result: BatchResult::Failed { error, code },
+    }
+}
+
+async fn send(socket: &mut WebSocket, response: &WsVerifyResponse) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(response).expect("response is serializable");
+    socket.send(Message::Text(text)).await
+}